@@ -30,7 +30,7 @@ fn main() {
         }
         RequestEvent::DownloadProgress(progress) => {
             warn!(
-                "[{:?}] [{}] download = [{}]",
+                "[{:?}] [{}] download = [{:?}]",
                 progress.req_path(),
                 progress.uuid(),
                 progress.progress()
@@ -67,7 +67,17 @@ fn main() {
                 );
             }
         }
-        ReqStatus::Error {
+        ReqStatus::ClientError {
+            stream_id,
+            headers,
+            data,
+        }
+        | ReqStatus::ServerError {
+            stream_id,
+            headers,
+            data,
+        }
+        | ReqStatus::Error {
             stream_id,
             headers,
             data,
@@ -76,6 +86,9 @@ fn main() {
                 error!("[{:#?}]", serde_json::from_slice::<RequestError>(error))
             }
         }
+        ReqStatus::Redirect { headers, .. } => {
+            info!("redirected [{:?}]", headers)
+        }
         ReqStatus::None => {}
     }
 }
@@ -1,22 +1,126 @@
-pub use client_configuration::ClientConfig;
+pub use client_configuration::{ClientConfig, ClientIdentity, KeyLog, PemSource};
 pub use connexion_info::ConnexionInfos;
 mod client_configuration {
-    use std::net::SocketAddr;
+    use std::{
+        net::SocketAddr,
+        path::{Path, PathBuf},
+    };
+
+    use crate::client_init::NetworkImpairment;
+    use crate::client_manager::{Encoding, SpkiPins};
 
     use self::connexion_info::ConnexionInfos;
 
     use super::*;
 
+    ///
+    ///Where the TLS secrets of the connexions are written, in the NSS key log format read by
+    ///Wireshark.
+    ///
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum KeyLog {
+        ///
+        ///The file named by `SSLKEYLOGFILE` when the connexion starts, nothing if it is unset.
+        ///
+        FromEnv,
+        File(PathBuf),
+    }
+
+    impl KeyLog {
+        pub fn path(&self) -> Option<PathBuf> {
+            match self {
+                Self::FromEnv => std::env::var_os("SSLKEYLOGFILE")
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from),
+                Self::File(path) => Some(path.clone()),
+            }
+        }
+    }
+
+    ///
+    ///A PEM document, read from a file or already in memory.
+    ///
+    #[derive(Clone, PartialEq, Eq)]
+    pub enum PemSource {
+        File(PathBuf),
+        Bytes(Vec<u8>),
+    }
+
+    impl std::fmt::Debug for PemSource {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::File(path) => write!(f, "File({:?})", path),
+                // keeps private keys out of the logs
+                Self::Bytes(bytes) => write!(f, "Bytes([{} bytes])", bytes.len()),
+            }
+        }
+    }
+
+    ///
+    ///Client certificate chain and its private key, presented to peers that require client
+    ///authentication (mutual TLS).
+    ///
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ClientIdentity {
+        cert_chain: PemSource,
+        priv_key: PemSource,
+    }
+
+    impl ClientIdentity {
+        ///
+        ///`cert_chain` starts with the client certificate, followed by its intermediates.
+        ///
+        pub fn from_pem_files(cert_chain: impl AsRef<Path>, priv_key: impl AsRef<Path>) -> Self {
+            Self {
+                cert_chain: PemSource::File(cert_chain.as_ref().to_path_buf()),
+                priv_key: PemSource::File(priv_key.as_ref().to_path_buf()),
+            }
+        }
+        ///
+        ///The documents are written to a temporary file while quiche loads them, readable by
+        ///the user only. Off unix that can't be enforced and the connexion fails : use
+        ///from_pem_files() there.
+        ///
+        pub fn from_pem(cert_chain: &[u8], priv_key: &[u8]) -> Self {
+            Self {
+                cert_chain: PemSource::Bytes(cert_chain.to_vec()),
+                priv_key: PemSource::Bytes(priv_key.to_vec()),
+            }
+        }
+        pub fn cert_chain(&self) -> &PemSource {
+            &self.cert_chain
+        }
+        pub fn priv_key(&self) -> &PemSource {
+            &self.priv_key
+        }
+    }
+
     ///
     ///Access connexion_infos with fn connexion_infos() -> &ConnexionInfos
     ///
     pub struct ClientConfig {
         connexion_info: ConnexionInfos,
+        server_name: Option<String>,
+        compression_threshold: Option<(usize, Encoding)>,
+        qlog_dir: Option<PathBuf>,
+        key_log: Option<KeyLog>,
+        client_identity: Option<ClientIdentity>,
+        ca_file: Option<PathBuf>,
+        spki_pins: SpkiPins,
+        network_impairment: Option<NetworkImpairment>,
     }
     impl Clone for ClientConfig {
         fn clone(&self) -> Self {
             Self {
                 connexion_info: self.connexion_info.clone(),
+                server_name: self.server_name.clone(),
+                compression_threshold: self.compression_threshold,
+                qlog_dir: self.qlog_dir.clone(),
+                key_log: self.key_log.clone(),
+                client_identity: self.client_identity.clone(),
+                ca_file: self.ca_file.clone(),
+                spki_pins: self.spki_pins.clone(),
+                network_impairment: self.network_impairment.clone(),
             }
         }
     }
@@ -28,8 +132,112 @@ mod client_configuration {
         pub fn new() -> ClientConfig {
             Self {
                 connexion_info: ConnexionInfos::new(),
+                server_name: None,
+                compression_threshold: None,
+                qlog_dir: None,
+                key_log: None,
+                client_identity: None,
+                ca_file: None,
+                spki_pins: SpkiPins::new(),
+                network_impairment: None,
             }
         }
+        ///
+        ///Name of the peer : sent as TLS server name (SNI), in `:authority`, and host of the
+        ///requests for its cookies. Without it the peer is known by its IP.
+        ///
+        pub fn set_server_name(&mut self, server_name: &str) -> &mut Self {
+            self.server_name = Some(server_name.to_ascii_lowercase());
+            self
+        }
+        pub fn server_name(&self) -> Option<&str> {
+            self.server_name.as_deref()
+        }
+        ///
+        ///Request bodies of at least `min_size` bytes are compressed with `encoding`, unless the
+        ///request already chose its own encoding.
+        ///
+        pub fn set_compression_threshold(
+            &mut self,
+            min_size: usize,
+            encoding: Encoding,
+        ) -> &mut Self {
+            self.compression_threshold = Some((min_size, encoding));
+            self
+        }
+        pub fn compression_threshold(&self) -> Option<(usize, Encoding)> {
+            self.compression_threshold
+        }
+        ///
+        ///Write a qlog trace of every connexion in `dir`, as `client-<scid>.sqlog` (the scid
+        ///being the trace id of the connexion), to be loaded in qvis.
+        ///
+        pub fn set_qlog_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+            self.qlog_dir = Some(dir.as_ref().to_path_buf());
+            self
+        }
+        pub fn qlog_dir(&self) -> Option<&Path> {
+            self.qlog_dir.as_deref()
+        }
+        ///
+        ///Append the TLS secrets of every connexion to a key log file, to decrypt captured
+        ///traffic. For debugging only : anyone reading the file can decrypt the traffic.
+        ///
+        pub fn set_key_log(&mut self, key_log: KeyLog) -> &mut Self {
+            self.key_log = Some(key_log);
+            self
+        }
+        pub fn key_log(&self) -> Option<&KeyLog> {
+            self.key_log.as_ref()
+        }
+        ///
+        ///Present this certificate to peers requesting a client certificate.
+        ///
+        pub fn set_client_identity(&mut self, client_identity: ClientIdentity) -> &mut Self {
+            self.client_identity = Some(client_identity);
+            self
+        }
+        pub fn client_identity(&self) -> Option<&ClientIdentity> {
+            self.client_identity.as_ref()
+        }
+        ///
+        ///Verify the certificate of the peer against the CA certificates (PEM) of `ca_file`,
+        ///and its name against the server name. Without it the certificate is not verified :
+        ///the SPKI pins, if any, are the only check of the peer.
+        ///
+        pub fn set_ca_file(&mut self, ca_file: impl AsRef<Path>) -> &mut Self {
+            self.ca_file = Some(ca_file.as_ref().to_path_buf());
+            self
+        }
+        pub fn ca_file(&self) -> Option<&Path> {
+            self.ca_file.as_deref()
+        }
+        ///
+        ///Accept the peer only if the SHA-256 of its certificate public key is one of the
+        ///pins, see SpkiPins::add(). Checked after the CA verification if set_ca_file() was
+        ///called, as the only check of the peer otherwise.
+        ///
+        pub fn add_spki_pin(&mut self, pin: &str) -> &mut Self {
+            self.spki_pins.add(pin);
+            self
+        }
+        pub fn spki_pins(&self) -> &SpkiPins {
+            &self.spki_pins
+        }
+        ///
+        ///Send and receive the datagrams of every connexion through a simulated link with
+        ///loss, reordering, duplication, delay or a bandwidth cap. For tests only.
+        ///
+        pub fn set_network_impairment(
+            &mut self,
+            network_impairment: NetworkImpairment,
+        ) -> &mut Self {
+            self.network_impairment = Some(network_impairment);
+            self
+        }
+        pub fn network_impairment(&self) -> Option<&NetworkImpairment> {
+            self.network_impairment.as_ref()
+        }
         pub fn local_address(&self) -> Option<SocketAddr> {
             self.connexion_info.get_local_socket_address()
         }
@@ -39,6 +247,29 @@ mod client_configuration {
         pub fn connexion_infos(&self) -> ConnexionInfos {
             self.connexion_info.clone()
         }
+        ///
+        ///Same client options, for a connexion to another peer named `server_name` (the
+        ///connexion infos are not shared with this config). An IP as name leaves the peer
+        ///unnamed.
+        ///
+        pub fn for_peer(&self, peer_socket_address: SocketAddr, server_name: &str) -> ClientConfig {
+            let mut client_config = self.clone();
+            client_config.connexion_info = ConnexionInfos::new();
+            client_config.server_name = None;
+            if server_name.parse::<std::net::IpAddr>().is_err() {
+                client_config.set_server_name(server_name);
+            }
+            client_config
+                .connexion_info
+                .set_peer_address(peer_socket_address.to_string().as_str())
+                .set_local_address(if peer_socket_address.is_ipv6() {
+                    "[::]:0"
+                } else {
+                    "0.0.0.0:0"
+                })
+                .build_connexion_infos();
+            client_config
+        }
     }
 }
 
@@ -229,6 +460,18 @@ mod test_client_config {
     #[allow(warnings)]
     use super::*;
 
+    #[test]
+    fn key_log_path() {
+        let mut client_configuration = ClientConfig::new();
+        assert!(client_configuration.key_log().is_none());
+
+        client_configuration.set_key_log(KeyLog::File("/tmp/keys.log".into()));
+        assert_eq!(
+            client_configuration.key_log().unwrap().path(),
+            Some("/tmp/keys.log".into())
+        );
+    }
+
     #[test]
     fn client_config_test() {
         let client_configuration = ClientConfig::new();
@@ -1,5 +1,7 @@
+mod impaired_socket;
 mod quiche_http3_client;
 pub use http3_client::Http3Client;
+pub use impaired_socket::{ClientSocket, LinkImpairment, NetworkImpairment};
 
 mod http3_client {
     use std::sync::{Arc, Mutex};
@@ -8,7 +10,9 @@ mod http3_client {
 
     use crate::{
         client_config::ClientConfig,
-        client_manager::{BodyQueue, RequestQueue, ResponseHead},
+        client_manager::{
+            BodyQueue, ConnectionStats, RequestQueue, ResponseError, ResponseHead, StatsChannel,
+        },
     };
 
     use super::*;
@@ -19,6 +23,8 @@ mod http3_client {
         response_head: ResponseHead,
         body_queue: BodyQueue,
         connexion_opened: Arc<Mutex<bool>>,
+        stats_channel: StatsChannel,
+        last_stats: Arc<Mutex<Option<ConnectionStats>>>,
     }
 
     impl Http3Client {
@@ -34,6 +40,8 @@ mod http3_client {
                 body_queue,
                 response_head,
                 connexion_opened: Arc::new(Mutex::new(false)),
+                stats_channel: StatsChannel::new(),
+                last_stats: Arc::new(Mutex::new(None)),
             }
         }
 
@@ -44,30 +52,42 @@ mod http3_client {
             !*self.connexion_opened.lock().unwrap()
         }
         ///
+        ///Last snapshot published by the client loop, None before the first connexion.
+        ///
+        pub fn stats(&self) -> Option<ConnectionStats> {
+            let last_stats = &mut *self.last_stats.lock().unwrap();
+            if let Some(stats) = self.stats_channel.get_queue().pop_stats() {
+                *last_stats = Some(stats);
+            }
+            last_stats.clone()
+        }
+        ///
         ///Block and wait for the connexion making.
         ///return the connexion id String.
         ///
+        ///Fails with ResponseError::PinningFailed if the peer key is not pinned, with
+        ///Disconnected if the connexion could not be established.
         ///
-        pub fn connect(&self) -> Result<(String, Waker), ()> {
-            if let Ok((conn_id, waker)) = self.run() {
-                *self.connexion_opened.lock().unwrap() = true;
-                Ok((conn_id, waker))
-            } else {
-                Err(())
-            }
+        pub fn connect(&self) -> Result<(String, Waker), ResponseError> {
+            let (conn_id, waker) = self.run()?;
+            *self.connexion_opened.lock().unwrap() = true;
+            Ok((conn_id, waker))
         }
 
         ///
         ///Run the http3 client in a separate Os thread with the client_config.
         ///
-        pub fn run(&self) -> Result<(String, Waker), crossbeam::channel::RecvError> {
+        pub fn run(&self) -> Result<(String, Waker), ResponseError> {
             let configuration_clone = self.client_config.clone();
             let req_queue = self.request_queue.clone();
             let resp_head = self.response_head.clone();
             let body_queue = self.body_queue.clone();
             let connexion_opened = self.connexion_opened.clone();
-            let confirm_connexion_chan = crossbeam::channel::bounded::<(String, Waker)>(1);
-            let confirmation_sender = confirm_connexion_chan.0.clone();
+            let stats_head = self.stats_channel.get_head();
+            // the thread holds the only sender : if the client stops before confirming, recv()
+            // fails instead of waiting forever
+            let (confirmation_sender, confirmation_receiver) =
+                crossbeam::channel::bounded::<Result<(String, Waker), ResponseError>>(1);
 
             std::thread::spawn(move || {
                 if let Ok(finished) = quiche_http3_client::run(
@@ -76,11 +96,250 @@ mod http3_client {
                     resp_head,
                     body_queue,
                     confirmation_sender,
+                    stats_head,
                 ) {
                     *connexion_opened.lock().unwrap() = false;
                 };
             });
-            confirm_connexion_chan.1.recv()
+            confirmation_receiver.recv()?
         }
     }
 }
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::{
+        net::{SocketAddr, UdpSocket},
+        time::{Duration, Instant},
+    };
+
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    use crate::{
+        client_config::{ClientConfig, ClientIdentity},
+        client_manager::{BodyChannel, RequestChannel, ResponseChannel, ResponseError},
+    };
+
+    const TEST_CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_certs/");
+
+    ///
+    ///Loopback QUIC server requesting a client certificate signed by the test CA. Sends true
+    ///once a handshake completes with a verified client certificate, false otherwise.
+    ///
+    fn client_auth_server() -> (SocketAddr, crossbeam::channel::Receiver<bool>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let (sender, receiver) = crossbeam::channel::bounded(1);
+
+        std::thread::spawn(move || {
+            let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION).unwrap();
+            config
+                .load_cert_chain_from_pem_file(&format!("{TEST_CERTS}server.crt"))
+                .unwrap();
+            config
+                .load_priv_key_from_pem_file(&format!("{TEST_CERTS}server.key"))
+                .unwrap();
+            config
+                .load_verify_locations_from_file(&format!("{TEST_CERTS}ca.crt"))
+                .unwrap();
+            config.verify_peer(true);
+            config
+                .set_application_protos(quiche::h3::APPLICATION_PROTOCOL)
+                .unwrap();
+            config.set_max_idle_timeout(5000);
+            config.set_initial_max_data(1_000_000);
+            config.set_initial_max_stream_data_bidi_local(1_000_000);
+            config.set_initial_max_stream_data_bidi_remote(1_000_000);
+            config.set_initial_max_stream_data_uni(1_000_000);
+            config.set_initial_max_streams_bidi(10);
+            config.set_initial_max_streams_uni(10);
+
+            let mut buf = [0; 65535];
+            let mut out = [0; 1350];
+            let mut conn: Option<quiche::Connection> = None;
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let authenticated = loop {
+                if Instant::now() > deadline {
+                    break false;
+                }
+                socket
+                    .set_read_timeout(Some(Duration::from_millis(50)))
+                    .unwrap();
+                match socket.recv_from(&mut buf) {
+                    Ok((len, from)) => {
+                        let conn = conn.get_or_insert_with(|| {
+                            let mut scid = [0; quiche::MAX_CONN_ID_LEN];
+                            SystemRandom::new().fill(&mut scid).unwrap();
+                            let scid = quiche::ConnectionId::from_ref(&scid);
+                            quiche::accept(&scid, None, server_addr, from, &mut config).unwrap()
+                        });
+                        let recv_info = quiche::RecvInfo {
+                            from,
+                            to: server_addr,
+                        };
+                        let _ = conn.recv(&mut buf[..len], recv_info);
+                    }
+                    Err(_) => {
+                        if let Some(conn) = conn.as_mut() {
+                            conn.on_timeout();
+                        }
+                    }
+                }
+                let Some(conn) = conn.as_mut() else {
+                    continue;
+                };
+                while let Ok((write, send_info)) = conn.send(&mut out) {
+                    let _ = socket.send_to(&out[..write], send_info.to);
+                }
+                if conn.is_established() {
+                    break conn.peer_cert().is_some();
+                }
+                if conn.is_closed() {
+                    break false;
+                }
+            };
+            let _ = sender.send(authenticated);
+        });
+        (server_addr, receiver)
+    }
+
+    fn connect_to(
+        server_addr: SocketAddr,
+        client_identity: Option<ClientIdentity>,
+        spki_pin: Option<&str>,
+    ) -> crossbeam::channel::Receiver<Result<String, ResponseError>> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .connexion_infos()
+            .set_peer_address(server_addr.to_string().as_str())
+            .set_local_address("127.0.0.1:0")
+            .build_connexion_infos();
+        if let Some(client_identity) = client_identity {
+            client_config.set_client_identity(client_identity);
+        }
+        if let Some(spki_pin) = spki_pin {
+            client_config.add_spki_pin(spki_pin);
+        }
+        connect_with(client_config)
+    }
+
+    fn connect_with(
+        client_config: ClientConfig,
+    ) -> crossbeam::channel::Receiver<Result<String, ResponseError>> {
+        let http3_client = Http3Client::new(
+            client_config,
+            RequestChannel::new().get_queue(),
+            ResponseChannel::new().get_head(),
+            BodyChannel::new().get_queue(),
+        );
+        let (sender, receiver) = crossbeam::channel::bounded(1);
+        std::thread::spawn(move || {
+            let _ = sender.send(http3_client.connect().map(|(conn_id, _)| conn_id));
+        });
+        receiver
+    }
+
+    #[test]
+    fn client_certificate_is_presented() {
+        let (server_addr, authenticated) = client_auth_server();
+        connect_to(
+            server_addr,
+            Some(ClientIdentity::from_pem_files(
+                format!("{TEST_CERTS}client.crt"),
+                format!("{TEST_CERTS}client.key"),
+            )),
+            None,
+        );
+        assert!(authenticated.recv_timeout(Duration::from_secs(10)).unwrap());
+
+        let (server_addr, authenticated) = client_auth_server();
+        connect_to(
+            server_addr,
+            Some(ClientIdentity::from_pem(
+                &std::fs::read(format!("{TEST_CERTS}client.crt")).unwrap(),
+                &std::fs::read(format!("{TEST_CERTS}client.key")).unwrap(),
+            )),
+            None,
+        );
+        assert!(authenticated.recv_timeout(Duration::from_secs(10)).unwrap());
+
+        let (server_addr, authenticated) = client_auth_server();
+        connect_to(server_addr, None, None);
+        assert!(!authenticated.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn unloadable_client_identity_fails_connect() {
+        let (server_addr, _) = client_auth_server();
+        let connected = connect_to(
+            server_addr,
+            Some(ClientIdentity::from_pem_files(
+                format!("{TEST_CERTS}client.crt"),
+                format!("{TEST_CERTS}missing.key"),
+            )),
+            None,
+        );
+        assert_eq!(
+            connected.recv_timeout(Duration::from_secs(10)).unwrap(),
+            Err(ResponseError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn server_not_signed_by_ca_file_is_refused() {
+        let ca_config = |server_addr: SocketAddr, ca_file: &str| {
+            let mut client_config = ClientConfig::new();
+            client_config
+                .connexion_infos()
+                .set_peer_address(server_addr.to_string().as_str())
+                .set_local_address("127.0.0.1:0")
+                .build_connexion_infos();
+            client_config
+                .set_server_name("localhost")
+                .set_ca_file(format!("{TEST_CERTS}{ca_file}"));
+            client_config
+        };
+
+        let (server_addr, _) = client_auth_server();
+        let connected = connect_with(ca_config(server_addr, "other_ca.crt"));
+        assert_eq!(
+            connected.recv_timeout(Duration::from_secs(10)).unwrap(),
+            Err(ResponseError::Disconnected)
+        );
+
+        let (server_addr, _) = client_auth_server();
+        let connected = connect_with(ca_config(server_addr, "missing_ca.crt"));
+        assert_eq!(
+            connected.recv_timeout(Duration::from_secs(10)).unwrap(),
+            Err(ResponseError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn unpinned_server_key_is_refused() {
+        let (server_addr, _) = client_auth_server();
+        let connected = connect_to(
+            server_addr,
+            None,
+            Some("sha256/ZNX/OhoH1LjOQ6N9MY0szvUO0dglaLmlN6qamYNca3Q="),
+        );
+        assert!(connected
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap()
+            .is_ok());
+
+        // the pin of the client key
+        let (server_addr, _) = client_auth_server();
+        let connected = connect_to(
+            server_addr,
+            None,
+            Some("BFz269onJCI6r3Jp3p9CbaiRXrSWq/sFQ/ViI083azg="),
+        );
+        assert_eq!(
+            connected.recv_timeout(Duration::from_secs(10)).unwrap(),
+            Err(ResponseError::PinningFailed)
+        );
+    }
+}
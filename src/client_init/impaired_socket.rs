@@ -0,0 +1,458 @@
+pub use impairment::{ClientSocket, LinkImpairment, NetworkImpairment};
+
+mod impairment {
+    use std::{
+        cell::RefCell,
+        cmp::{Ordering, Reverse},
+        collections::BinaryHeap,
+        io,
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+
+    use log::debug;
+
+    ///
+    ///Impairments of one direction of the link, applied to each datagram. Nothing is impaired
+    ///by default.
+    ///
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct LinkImpairment {
+        loss: f64,
+        duplication: f64,
+        reordering: f64,
+        reorder_delay: Duration,
+        delay: Duration,
+        bandwidth: Option<u64>,
+        queue_limit: Option<usize>,
+    }
+
+    impl LinkImpairment {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        ///
+        ///Probability, from 0 to 1, that a datagram is dropped.
+        ///
+        pub fn loss(mut self, probability: f64) -> Self {
+            self.loss = probability.clamp(0.0, 1.0);
+            self
+        }
+        ///
+        ///Probability that a datagram is delivered twice.
+        ///
+        pub fn duplication(mut self, probability: f64) -> Self {
+            self.duplication = probability.clamp(0.0, 1.0);
+            self
+        }
+        ///
+        ///Probability that a datagram is held `extra_delay` longer, letting the next ones
+        ///overtake it.
+        ///
+        pub fn reordering(mut self, probability: f64, extra_delay: Duration) -> Self {
+            self.reordering = probability.clamp(0.0, 1.0);
+            self.reorder_delay = extra_delay;
+            self
+        }
+        ///
+        ///One way delay added to every datagram.
+        ///
+        pub fn delay(mut self, delay: Duration) -> Self {
+            self.delay = delay;
+            self
+        }
+        ///
+        ///Link rate in bytes per second : the datagrams queue up behind the previous ones.
+        ///
+        pub fn bandwidth(mut self, bytes_per_sec: u64) -> Self {
+            self.bandwidth = Some(bytes_per_sec.max(1));
+            self
+        }
+        ///
+        ///With a bandwidth, the bytes that can wait for the link before the next datagrams are
+        ///dropped. Unbounded by default.
+        ///
+        pub fn queue_limit(mut self, bytes: usize) -> Self {
+            self.queue_limit = Some(bytes);
+            self
+        }
+    }
+
+    ///
+    ///Simulated network conditions between the client and its peer, see
+    ///ClientConfig::set_network_impairment(). The random decisions come from `seed` : the
+    ///same datagrams get the same fate from one run to the next.
+    ///
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct NetworkImpairment {
+        seed: u64,
+        outgoing: LinkImpairment,
+        incoming: LinkImpairment,
+    }
+
+    impl NetworkImpairment {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        ///
+        ///The same impairments in both directions.
+        ///
+        pub fn symmetric(link: LinkImpairment) -> Self {
+            Self::new().outgoing(link.clone()).incoming(link)
+        }
+        pub fn seed(mut self, seed: u64) -> Self {
+            self.seed = seed;
+            self
+        }
+        ///
+        ///Datagrams sent to the peer.
+        ///
+        pub fn outgoing(mut self, link: LinkImpairment) -> Self {
+            self.outgoing = link;
+            self
+        }
+        ///
+        ///Datagrams received from the peer.
+        ///
+        pub fn incoming(mut self, link: LinkImpairment) -> Self {
+            self.incoming = link;
+            self
+        }
+    }
+
+    ///
+    ///SplitMix64 : small, seedable and good enough to pick the fate of datagrams.
+    ///
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_u64(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+        fn chance(&mut self, probability: f64) -> bool {
+            ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+        }
+    }
+
+    struct Delayed {
+        at: Instant,
+        seq: u64,
+        data: Vec<u8>,
+        addr: SocketAddr,
+    }
+
+    impl PartialEq for Delayed {
+        fn eq(&self, other: &Self) -> bool {
+            (self.at, self.seq) == (other.at, other.seq)
+        }
+    }
+    impl Eq for Delayed {}
+    impl PartialOrd for Delayed {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Delayed {
+        fn cmp(&self, other: &Self) -> Ordering {
+            (self.at, self.seq).cmp(&(other.at, other.seq))
+        }
+    }
+
+    ///
+    ///One direction of the simulated link : the datagrams wait there until their release.
+    ///
+    struct Link {
+        impairment: LinkImpairment,
+        rng: Rng,
+        busy_until: Instant,
+        queue: BinaryHeap<Reverse<Delayed>>,
+        next_seq: u64,
+    }
+
+    impl Link {
+        fn new(impairment: LinkImpairment, seed: u64) -> Self {
+            Self {
+                impairment,
+                rng: Rng(seed),
+                busy_until: Instant::now(),
+                queue: BinaryHeap::new(),
+                next_seq: 0,
+            }
+        }
+        fn push(&mut self, data: &[u8], addr: SocketAddr, now: Instant) {
+            // always draw the three, so a fate doesn't depend on the other settings
+            let lost = self.rng.chance(self.impairment.loss);
+            let duplicated = self.rng.chance(self.impairment.duplication);
+            let reordered = self.rng.chance(self.impairment.reordering);
+            if lost {
+                debug!("Impairment : datagram of [{}] bytes lost", data.len());
+                return;
+            }
+            let copies = if duplicated { 2 } else { 1 };
+            for _ in 0..copies {
+                let Some(departure) = self.departure(data.len(), now) else {
+                    debug!("Impairment : link queue full, datagram dropped");
+                    return;
+                };
+                let mut at = departure + self.impairment.delay;
+                if reordered {
+                    at += self.impairment.reorder_delay;
+                }
+                self.queue.push(Reverse(Delayed {
+                    at,
+                    seq: self.next_seq,
+                    data: data.to_vec(),
+                    addr,
+                }));
+                self.next_seq += 1;
+            }
+        }
+        ///
+        ///When the datagram is fully on the link, None if the link queue is full.
+        ///
+        fn departure(&mut self, len: usize, now: Instant) -> Option<Instant> {
+            let Some(bandwidth) = self.impairment.bandwidth else {
+                return Some(now);
+            };
+            let start = self.busy_until.max(now);
+            if let Some(queue_limit) = self.impairment.queue_limit {
+                let backlog = (start - now).as_secs_f64() * bandwidth as f64;
+                if backlog as usize + len > queue_limit {
+                    return None;
+                }
+            }
+            self.busy_until = start + Duration::from_secs_f64(len as f64 / bandwidth as f64);
+            Some(self.busy_until)
+        }
+        fn pop_due(&mut self, now: Instant) -> Option<Delayed> {
+            if self.queue.peek()?.0.at > now {
+                return None;
+            }
+            self.queue.pop().map(|Reverse(delayed)| delayed)
+        }
+        fn next_release(&self) -> Option<Instant> {
+            self.queue.peek().map(|Reverse(delayed)| delayed.at)
+        }
+    }
+
+    struct Impairer {
+        outgoing: Link,
+        incoming: Link,
+    }
+
+    ///
+    ///The UDP socket of the client loop. With a NetworkImpairment, the datagrams go through
+    ///the simulated link in both directions : the loop has to wake up at next_release() and
+    ///call flush_due() to let the delayed ones through.
+    ///
+    pub struct ClientSocket {
+        socket: mio::net::UdpSocket,
+        impairer: Option<RefCell<Impairer>>,
+    }
+
+    impl ClientSocket {
+        pub fn new(socket: mio::net::UdpSocket, impairment: Option<&NetworkImpairment>) -> Self {
+            Self {
+                socket,
+                impairer: impairment.map(|impairment| {
+                    RefCell::new(Impairer {
+                        outgoing: Link::new(impairment.outgoing.clone(), impairment.seed),
+                        incoming: Link::new(
+                            impairment.incoming.clone(),
+                            impairment.seed ^ 0x5bd1_e995_5bd1_e995,
+                        ),
+                    })
+                }),
+            }
+        }
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.socket.local_addr()
+        }
+        pub fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+            let Some(impairer) = &self.impairer else {
+                return self.socket.send_to(buf, target);
+            };
+            impairer
+                .borrow_mut()
+                .outgoing
+                .push(buf, target, Instant::now());
+            self.flush_due()?;
+            Ok(buf.len())
+        }
+        pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+            let Some(impairer) = &self.impairer else {
+                return self.socket.recv_from(buf);
+            };
+            let mut impairer = impairer.borrow_mut();
+            // the readiness is edge triggered : take everything from the socket
+            loop {
+                match self.socket.recv_from(buf) {
+                    Ok((len, from)) => impairer.incoming.push(&buf[..len], from, Instant::now()),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            match impairer.incoming.pop_due(Instant::now()) {
+                Some(delayed) => {
+                    let len = delayed.data.len().min(buf.len());
+                    buf[..len].copy_from_slice(&delayed.data[..len]);
+                    Ok((len, delayed.addr))
+                }
+                None => Err(io::ErrorKind::WouldBlock.into()),
+            }
+        }
+        ///
+        ///Send the outgoing datagrams whose release time is reached.
+        ///
+        pub fn flush_due(&self) -> io::Result<()> {
+            let Some(impairer) = &self.impairer else {
+                return Ok(());
+            };
+            let mut impairer = impairer.borrow_mut();
+            let outgoing = &mut impairer.outgoing;
+            while let Some(delayed) = outgoing.pop_due(Instant::now()) {
+                match self.socket.send_to(&delayed.data, delayed.addr) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        outgoing.queue.push(Reverse(delayed));
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+        ///
+        ///Incoming datagrams released and not read yet.
+        ///
+        pub fn has_due_packets(&self) -> bool {
+            self.impairer.as_ref().is_some_and(|impairer| {
+                impairer
+                    .borrow()
+                    .incoming
+                    .next_release()
+                    .is_some_and(|at| at <= Instant::now())
+            })
+        }
+        ///
+        ///The next time a delayed datagram is released, in either direction.
+        ///
+        pub fn next_release(&self) -> Option<Instant> {
+            let impairer = self.impairer.as_ref()?.borrow();
+            match (
+                impairer.outgoing.next_release(),
+                impairer.incoming.next_release(),
+            ) {
+                (Some(outgoing), Some(incoming)) => Some(outgoing.min(incoming)),
+                (outgoing, incoming) => outgoing.or(incoming),
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::{
+        net::{SocketAddr, UdpSocket},
+        time::{Duration, Instant},
+    };
+
+    ///
+    ///Send `count` numbered datagrams through an impaired socket and return the numbers
+    ///received by the peer, in order.
+    ///
+    fn send_through(impairment: NetworkImpairment, count: u16) -> Vec<u16> {
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let socket = ClientSocket::new(
+            mio::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap(),
+            Some(&impairment),
+        );
+        let peer_addr: SocketAddr = peer.local_addr().unwrap();
+        for i in 0..count {
+            socket.send_to(&i.to_be_bytes(), peer_addr).unwrap();
+        }
+        while let Some(release) = socket.next_release() {
+            std::thread::sleep(release.saturating_duration_since(Instant::now()));
+            socket.flush_due().unwrap();
+        }
+        let mut received = vec![];
+        let mut buf = [0; 2];
+        while let Ok((2, _)) = peer.recv_from(&mut buf) {
+            received.push(u16::from_be_bytes(buf));
+        }
+        received
+    }
+
+    #[test]
+    fn datagrams_are_impaired_deterministically() {
+        let untouched = send_through(NetworkImpairment::new(), 50);
+        assert_eq!(untouched, (0..50).collect::<Vec<u16>>());
+
+        let lossy = NetworkImpairment::new()
+            .seed(7)
+            .outgoing(LinkImpairment::new().loss(0.3));
+        let received = send_through(lossy.clone(), 200);
+        assert!(received.len() > 100 && received.len() < 180);
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(send_through(lossy, 200), received);
+
+        let duplicated = NetworkImpairment::new().outgoing(LinkImpairment::new().duplication(1.0));
+        assert_eq!(send_through(duplicated, 10).len(), 20);
+
+        let reordered = NetworkImpairment::new()
+            .seed(3)
+            .outgoing(LinkImpairment::new().reordering(0.2, Duration::from_millis(20)));
+        let received = send_through(reordered, 100);
+        let mut sorted = received.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..100).collect::<Vec<u16>>());
+        assert_ne!(received, sorted);
+    }
+
+    #[test]
+    fn bandwidth_cap_paces_and_drops_on_full_queue() {
+        // 20 datagrams of 1000 bytes at 100kB/s : 200ms on the link
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = ClientSocket::new(
+            mio::net::UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap(),
+            Some(
+                &NetworkImpairment::new().outgoing(
+                    LinkImpairment::new()
+                        .bandwidth(100_000)
+                        .delay(Duration::from_millis(10)),
+                ),
+            ),
+        );
+        let start = Instant::now();
+        for _ in 0..20 {
+            socket
+                .send_to(&[0; 1000], peer.local_addr().unwrap())
+                .unwrap();
+        }
+        let last_release = std::iter::from_fn(|| {
+            let release = socket.next_release()?;
+            std::thread::sleep(release.saturating_duration_since(Instant::now()));
+            socket.flush_due().unwrap();
+            Some(release)
+        })
+        .last()
+        .unwrap();
+        assert!(last_release - start >= Duration::from_millis(210));
+
+        let capped = send_through(
+            NetworkImpairment::new()
+                .outgoing(LinkImpairment::new().bandwidth(1_000).queue_limit(10)),
+            20,
+        );
+        assert!(capped.len() < 20);
+        assert_eq!(capped[0], 0);
+    }
+}
@@ -4,26 +4,37 @@ use mio::{event::Event, Events, Poll, Token, Waker};
 use quiche::h3::{self};
 use ring::rand::*;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    io::Write,
     net::SocketAddr,
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
 };
 
 use crate::{
-    client_config::ClientConfig,
-    client_manager::{BodyQueue, Http3Request, Http3Response, RequestQueue, ResponseHead},
+    client_config::{ClientConfig, ClientIdentity, PemSource},
+    client_init::ClientSocket,
+    client_manager::{
+        BodyQueue, ConnectionStats, Http3Request, Http3Response, RequestQueue, ResponseError,
+        ResponseHead, StatsHead, TrailersRequest, STATS_INTERVAL,
+    },
     my_log,
 };
 const MAX_DATAGRAM_SIZE: usize = 1350;
 const WAKER_TOKEN: Token = Token(1);
 const WAKER_TOKEN_1: Token = Token(2);
+///
+///H3_REQUEST_CANCELLED, the error code of the streams reset by the client.
+///
+const H3_REQUEST_CANCELLED: u64 = 0x10c;
 pub fn run(
     client_config: Arc<ClientConfig>,
     request_queue: RequestQueue,
     response_queue: ResponseHead,
     _body_queue: BodyQueue,
-    confirm_connexion: crossbeam::channel::Sender<(String, Waker)>,
+    confirm_connexion: crossbeam::channel::Sender<Result<(String, Waker), ResponseError>>,
+    stats_head: StatsHead,
 ) -> Result<String, ()> {
     let mut buf = [0; 65535];
     let mut out = [0; MAX_DATAGRAM_SIZE];
@@ -33,6 +44,8 @@ pub fn run(
         u64,
         Vec<(Vec<u8>, crossbeam::channel::Sender<Instant>, bool)>,
     > = HashMap::new();
+    // Trailer sections waiting for stream capacity
+    let mut pending_trailers: Vec<TrailersRequest> = vec![];
     //    let url = url::Url::parse(&args.next().unwrap()).unwrap();
     // Setup the event loop.
     let mut poll = mio::Poll::new().unwrap();
@@ -59,10 +72,23 @@ pub fn run(
             mio::Interest::READABLE | mio::Interest::WRITABLE,
         )
         .unwrap();
+    // Datagrams go through the simulated link, if any, from now on.
+    let socket = ClientSocket::new(socket, client_config.network_impairment());
     // Create the configuration for the QUIC connection.
     let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION).unwrap();
-    // *CAUTION*: this should not be set to `false` in production!!!
-    config.verify_peer(false);
+    match client_config.ca_file() {
+        Some(ca_file) => {
+            config.verify_peer(true);
+            let ca_file = ca_file
+                .to_str()
+                .ok_or_else(|| error!("CA file path [{:?}] is not valid UTF-8", ca_file))?;
+            config
+                .load_verify_locations_from_file(ca_file)
+                .map_err(|e| error!("Failed loading CA file [{}] [{:?}]", ca_file, e))?;
+        }
+        // *CAUTION*: the peer certificate is not verified, the SPKI pins are the only check
+        None => config.verify_peer(false),
+    }
     config
         .set_application_protos(quiche::h3::APPLICATION_PROTOCOL)
         .unwrap();
@@ -77,6 +103,14 @@ pub fn run(
     config.set_initial_max_streams_uni(100);
     config.set_disable_active_migration(true);
     config.set_cc_algorithm(quiche::CongestionControlAlgorithm::BBR2);
+    if let Some(client_identity) = client_config.client_identity() {
+        // without its certificate the connexion would fail later, at the peer's request
+        load_client_identity(&mut config, client_identity)?;
+    }
+    let key_log_path = client_config.key_log().and_then(|key_log| key_log.path());
+    if key_log_path.is_some() {
+        config.log_keys();
+    }
     let mut http3_conn = None;
     // Generate a random source connection ID for the connection.
     let mut scid = [0; quiche::MAX_CONN_ID_LEN];
@@ -85,14 +119,21 @@ pub fn run(
     // Get local address.
     let local_addr = socket.local_addr().unwrap();
     // Create a QUIC connection and initiate handshake.
+    let server_name = client_config.server_name().unwrap_or("quichec");
     let mut conn =
-        quiche::connect(Some("quichec"), &scid, local_addr, peer_addr, &mut config).unwrap();
+        quiche::connect(Some(server_name), &scid, local_addr, peer_addr, &mut config).unwrap();
     info!(
         "connecting to {:} from {:} with scid {}",
         peer_addr,
         socket.local_addr().unwrap(),
         hex_dump(&scid)
     );
+    if let Some(qlog_dir) = client_config.qlog_dir() {
+        set_qlog(&mut conn, qlog_dir, &hex_dump(&scid));
+    }
+    if let Some(key_log_path) = &key_log_path {
+        set_keylog(&mut conn, key_log_path);
+    }
     let (write, send_info) = conn.send(&mut out).expect("initial send failed");
     while let Err(e) = socket.send_to(&out[..write], send_info.to) {
         if e.kind() == std::io::ErrorKind::WouldBlock {
@@ -104,6 +145,7 @@ pub fn run(
     let h3_config = quiche::h3::Config::new().unwrap();
 
     let mut conn_confirmation = false;
+    let mut pinning_failed = false;
     let mut req_start = std::time::Instant::now();
     let mut bodies_send = 0;
     let mut pending_count = 0;
@@ -115,21 +157,30 @@ pub fn run(
     let mut h3_bytes_given = 0;
     let mut round = 0;
     let mut bytes_re = 0;
+    // Request streams waiting for their response, and the last stats publication
+    let mut active_streams: HashSet<u64> = HashSet::new();
+    let mut last_stats = Instant::now();
 
     'main: loop {
-        poll.poll(&mut events, conn.timeout()).unwrap();
+        poll.poll(&mut events, next_timeout(&conn, &socket))
+            .unwrap();
         round += 1;
         // Read incoming UDP packets from the socket and feed them to quiche,
         // until there are no more packets to read.
         //        handle_incoming_packets(&events, &mut conn, &socket, &mut buf, local_addr);
 
+        let timed_out = events.is_empty();
+        if timed_out {
+            conn.on_timeout();
+        }
         'read: loop {
             // If the event loop reported no events, it means that the timeout
             // has expired, so handle it without attempting to read packets. We
             // will then proceed with the send loop.
-            if events.is_empty() {
+            // The timeout can also be the release of datagrams delayed by the
+            // network impairment : those are read.
+            if timed_out && !socket.has_due_packets() {
                 //             debug!("timed out");
-                conn.on_timeout();
                 break 'read;
             }
             let (len, from) = match socket.recv_from(&mut buf) {
@@ -158,10 +209,45 @@ pub fn run(
             };
         }
         if conn.is_closed() {
+            // closed during the handshake (peer certificate refused...) : connect() is waiting
+            if !conn_confirmation {
+                error!(
+                    "Connexion [{}] closed before being established",
+                    conn.trace_id()
+                );
+                if let Err(e) = confirm_connexion.send(Err(ResponseError::Disconnected)) {
+                    debug!(
+                        "Error : failed to send connexion failure for [{:?}]   [{:?}]",
+                        conn.trace_id(),
+                        e
+                    );
+                }
+            }
+            stats_head.publish(connection_stats(&conn, &active_streams, &pending_bodies));
+            notify_connexion_closed(&response_queue, conn.trace_id());
             break Ok(conn.trace_id().to_owned());
         }
+        // The pinned keys are checked before the connexion is used : on a mismatch it is
+        // closed and the waiting requests get PinningFailed instead of the confirmation.
+        if conn.is_established() && !conn_confirmation {
+            if let Err(e) = client_config.spki_pins().verify(conn.peer_cert()) {
+                error!("Closing connexion [{}] : {}", conn.trace_id(), e);
+                // CRYPTO_ERROR with the bad_certificate alert
+                conn.close(false, 0x100 + 42, b"certificate pinning failed")
+                    .ok();
+                if let Err(e) = confirm_connexion.send(Err(e)) {
+                    debug!(
+                        "Error : failed to send pinning failure for [{:?}]   [{:?}]",
+                        conn.trace_id(),
+                        e
+                    );
+                }
+                conn_confirmation = true;
+                pinning_failed = true;
+            }
+        }
         // Create a new HTTP/3 connection once the QUIC connection is established.
-        if conn.is_established() && http3_conn.is_none() {
+        if conn.is_established() && http3_conn.is_none() && !pinning_failed {
             http3_conn = Some(
                 quiche::h3::Connection::with_transport(&mut conn, &h3_config)
                 .expect("Unable to create HTTP/3 connection, check the server's uni stream limit and window size"),
@@ -169,7 +255,7 @@ pub fn run(
 
             if !conn_confirmation {
                 if let Err(e) =
-                    confirm_connexion.send((conn.trace_id().to_string(), waker.take().unwrap()))
+                    confirm_connexion.send(Ok((conn.trace_id().to_string(), waker.take().unwrap())))
                 {
                     debug!(
                         "Error : failed to send connxion confirmation for [{:?}]   [{:?}]",
@@ -218,6 +304,7 @@ pub fn run(
                         }
                     }
                     Ok((stream_id, quiche::h3::Event::Finished)) => {
+                        active_streams.remove(&stream_id);
                         debug!("Finished stream [{stream_id}]!");
                         debug!("response received in {:?}, closing...", req_start.elapsed());
                         if let Err(e) = response_queue.send_response(Http3Response::new_body_data(
@@ -231,9 +318,17 @@ pub fn run(
                         let _ = waker_1.wake();
                         //     conn.close(true, 0x00, b"kthxbye").unwrap();
                     }
-                    Ok((_stream_id, quiche::h3::Event::Reset(e))) => {
-                        warn!("request was reset by peer with {}, closing...", e);
-                        conn.close(true, 0x00, b"kthxbye").unwrap();
+                    Ok((stream_id, quiche::h3::Event::Reset(e))) => {
+                        active_streams.remove(&stream_id);
+                        warn!("stream [{stream_id}] was reset by peer with {}", e);
+                        if let Err(e) = response_queue.send_response(Http3Response::Reset {
+                            stream_id,
+                            connexion_id: trace_id.clone(),
+                            error_code: e,
+                        }) {
+                            info!("Error failed  [{}]   [{:?}]", stream_id, e);
+                        };
+                        let _ = waker_1.wake();
                     }
                     Ok((_, quiche::h3::Event::PriorityUpdate)) => unreachable!(),
                     Ok((goaway_id, quiche::h3::Event::GoAway)) => {
@@ -275,6 +370,9 @@ pub fn run(
                 }
             }
 
+            pending_trailers
+                .retain(|trailers_req| !send_trailers(h3_conn, &mut conn, trailers_req));
+
             // Send HTTP requests once the QUIC connection is established, and until
             // all requests have been sent.
             let trace_id = conn.trace_id().to_string();
@@ -290,6 +388,7 @@ pub fn run(
                                 header_req.is_end(),
                             ) {
                                 req_start = std::time::Instant::now();
+                                active_streams.insert(stream_id);
                                 let _ = waker_1.wake();
 
                                 if let Err(e) = header_req.send_ids(stream_id, trace_id.as_str()) {
@@ -303,6 +402,7 @@ pub fn run(
                                 h3_conn.send_request(&mut conn, ping_status.headers(), true)
                             {
                                 req_start = std::time::Instant::now();
+                                active_streams.insert(stream_id);
                                 let _ = waker_1.wake();
                             }
                         }
@@ -383,6 +483,35 @@ pub fn run(
                             }*/
                         }
 
+                        Http3Request::Trailers(trailers_req) => {
+                            if !send_trailers(h3_conn, &mut conn, &trailers_req) {
+                                pending_trailers.push(trailers_req);
+                            }
+                            let _ = waker_1.wake();
+                        }
+                        Http3Request::ResetStream { stream_id } => {
+                            // the peer stops waiting for the body, and the response for the
+                            // stream
+                            let _ = conn.stream_shutdown(
+                                stream_id,
+                                quiche::Shutdown::Write,
+                                H3_REQUEST_CANCELLED,
+                            );
+                            let _ = conn.stream_shutdown(
+                                stream_id,
+                                quiche::Shutdown::Read,
+                                H3_REQUEST_CANCELLED,
+                            );
+                            active_streams.remove(&stream_id);
+                            if let Err(e) = response_queue.send_response(Http3Response::Reset {
+                                stream_id,
+                                connexion_id: trace_id.clone(),
+                                error_code: H3_REQUEST_CANCELLED,
+                            }) {
+                                info!("Error failed  [{}]   [{:?}]", stream_id, e);
+                            };
+                            let _ = waker_1.wake();
+                        }
                         Http3Request::BodyFromFile => {}
                     }
                 }
@@ -410,13 +539,191 @@ pub fn run(
             &mut packet_send,
             &mut last_instant,
         );
+        if let Err(e) = socket.flush_due() {
+            error!("Failed to send delayed datagrams [{:?}]", e);
+        }
+        if last_stats.elapsed() >= STATS_INTERVAL {
+            stats_head.publish(connection_stats(&conn, &active_streams, &pending_bodies));
+            last_stats = Instant::now();
+        }
         if conn.is_closed() {
             warn!("connection closed, {:?}", conn.stats());
+            stats_head.publish(connection_stats(&conn, &active_streams, &pending_bodies));
+            notify_connexion_closed(&response_queue, conn.trace_id());
             break Ok(conn.trace_id().to_owned());
         }
     }
 }
 
+///
+///The poll timeout : the next quiche timer, or the next release of a datagram delayed by the
+///network impairment if it comes first.
+///
+fn next_timeout(conn: &quiche::Connection, socket: &ClientSocket) -> Option<Duration> {
+    let release = socket
+        .next_release()
+        .map(|at| at.saturating_duration_since(Instant::now()));
+    match (conn.timeout(), release) {
+        (Some(timeout), Some(release)) => Some(timeout.min(release)),
+        (timeout, release) => timeout.or(release),
+    }
+}
+
+///
+///Tell the response manager that the pending requests of this connexion won't be answered.
+///
+fn notify_connexion_closed(response_queue: &ResponseHead, trace_id: &str) {
+    if let Err(e) = response_queue.notify_connexion_closed(trace_id) {
+        info!(
+            "Error failed notifying closed connexion [{}]   [{:?}]",
+            trace_id, e
+        );
+    }
+}
+
+///
+///Snapshot of the connexion for Http3ClientManager::stats().
+///
+fn connection_stats(
+    conn: &quiche::Connection,
+    active_streams: &HashSet<u64>,
+    pending_bodies: &HashMap<u64, Vec<(Vec<u8>, crossbeam::channel::Sender<Instant>, bool)>>,
+) -> ConnectionStats {
+    let pending = pending_bodies.values().flatten();
+    ConnectionStats::new(
+        conn.trace_id(),
+        &conn.stats(),
+        conn.path_stats().next().as_ref(),
+        active_streams.len(),
+        pending.clone().count(),
+        pending.map(|(body, _, _)| body.len()).sum(),
+    )
+}
+
+///
+///Trace the connexion in `<qlog_dir>/client-<id>.sqlog` before the handshake starts.
+///
+fn set_qlog(conn: &mut quiche::Connection, qlog_dir: &Path, id: &str) {
+    let path = qlog_dir.join(format!("client-{id}.sqlog"));
+    match std::fs::create_dir_all(qlog_dir).and_then(|_| std::fs::File::create(&path)) {
+        Ok(file) => {
+            info!("qlog of connexion [{id}] written to [{:?}]", path);
+            conn.set_qlog(
+                Box::new(std::io::BufWriter::new(file)),
+                "faces_quic_client qlog".to_string(),
+                format!("faces_quic_client qlog id={id}"),
+            );
+        }
+        Err(e) => error!("Failed to create qlog file [{:?}] [{:?}]", path, e),
+    }
+}
+
+///
+///A PEM document as a file quiche can load. Documents given as bytes are written to a
+///temporary file, only readable by the user and removed once loaded.
+///
+///The document is on disk meanwhile, and stays there if the process dies before the removal.
+///Off unix the file can't be restricted to the user : a private key is then refused, see
+///load_client_identity().
+///
+struct PemFile {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl PemFile {
+    fn new(pem_source: &PemSource) -> Result<Self, ()> {
+        let bytes = match pem_source {
+            PemSource::File(path) => {
+                return Ok(Self {
+                    path: path.clone(),
+                    temporary: false,
+                })
+            }
+            PemSource::Bytes(bytes) => bytes,
+        };
+        let mut random = [0; 8];
+        SystemRandom::new()
+            .fill(&mut random)
+            .map_err(|e| error!("Failed naming temporary PEM file [{:?}]", e))?;
+        let path =
+            std::env::temp_dir().join(format!("faces_quic_client-{}.pem", hex_dump(&random)));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&path)
+            .map_err(|e| error!("Failed creating [{:?}] [{:?}]", path, e))?;
+        let pem_file = Self {
+            path,
+            temporary: true,
+        };
+        file.write_all(bytes)
+            .map_err(|e| error!("Failed writing [{:?}] [{:?}]", pem_file.path, e))?;
+        Ok(pem_file)
+    }
+    fn path(&self) -> Result<&str, ()> {
+        self.path
+            .to_str()
+            .ok_or_else(|| error!("PEM path [{:?}] is not valid UTF-8", self.path))
+    }
+}
+
+impl Drop for PemFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+///
+///Present the client certificate chain and key to peers requesting client authentication.
+///
+fn load_client_identity(
+    config: &mut quiche::Config,
+    client_identity: &ClientIdentity,
+) -> Result<(), ()> {
+    #[cfg(not(unix))]
+    {
+        if let PemSource::Bytes(_) = client_identity.priv_key() {
+            error!("Private key given as bytes : give a key file on this platform");
+            return Err(());
+        }
+    }
+    let cert_chain = PemFile::new(client_identity.cert_chain())?;
+    config
+        .load_cert_chain_from_pem_file(cert_chain.path()?)
+        .map_err(|e| error!("Failed loading client certificate chain [{:?}]", e))?;
+    let priv_key = PemFile::new(client_identity.priv_key())?;
+    config
+        .load_priv_key_from_pem_file(priv_key.path()?)
+        .map_err(|e| error!("Failed loading client private key [{:?}]", e))
+}
+
+///
+///Append the TLS secrets of the connexion to `key_log_path` (NSS key log format), before the
+///handshake starts.
+///
+fn set_keylog(conn: &mut quiche::Connection, key_log_path: &Path) {
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+    // the secrets decrypt the whole session : only the owner may read them
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    match options.open(key_log_path) {
+        Ok(file) => {
+            warn!("TLS secrets written to [{:?}]", key_log_path);
+            conn.set_keylog(Box::new(file));
+        }
+        Err(e) => error!("Failed to open key log [{:?}] [{:?}]", key_log_path, e),
+    }
+}
+
 fn hex_dump(buf: &[u8]) -> String {
     let vec: Vec<String> = buf.iter().map(|b| format!("{b:02x}")).collect();
     vec.join("")
@@ -434,7 +741,7 @@ pub fn hdrs_to_strings(hdrs: &[quiche::h3::Header]) -> Vec<(String, String)> {
 */
 fn handle_incoming_packets_purge(
     conn: &mut quiche::Connection,
-    socket: &mio::net::UdpSocket,
+    socket: &ClientSocket,
     buf: &mut [u8],
     local_addr: SocketAddr,
 ) {
@@ -471,7 +778,7 @@ fn handle_incoming_packets_purge(
 fn handle_incoming_packets(
     events: &Events,
     conn: &mut quiche::Connection,
-    socket: &mio::net::UdpSocket,
+    socket: &ClientSocket,
     buf: &mut [u8],
     local_addr: SocketAddr,
 ) {
@@ -515,7 +822,7 @@ fn handle_incoming_packets(
 }
 fn handle_outgoing_packets_purge(
     conn: &mut quiche::Connection,
-    socket: &mio::net::UdpSocket,
+    socket: &ClientSocket,
     out: &mut [u8],
     last_sending_time: &mut Duration,
     packet_send: &mut i32,
@@ -577,7 +884,7 @@ fn handle_outgoing_packets(
     poll: &mut Poll,
     events: &mut Events,
     conn: &mut quiche::Connection,
-    socket: &mio::net::UdpSocket,
+    socket: &ClientSocket,
     out: &mut [u8],
     waker_1: &Waker,
     last_sending_time: &mut Duration,
@@ -651,7 +958,7 @@ fn handle_writable(
     stream_id: u64,
     conn: &mut quiche::Connection,
     pending_map: &mut HashMap<u64, Vec<(Vec<u8>, crossbeam::channel::Sender<Instant>, bool)>>,
-    socket: &mio::net::UdpSocket,
+    socket: &ClientSocket,
     out: &mut [u8],
     last_sending_time: &mut Duration,
     _waker_1: &Waker,
@@ -667,7 +974,8 @@ fn handle_writable(
         let len = bodies_coll.len();
         let mut can_write = true;
         for (i, (body, send_confirmation_to_reader, is_end)) in bodies_coll.iter_mut().enumerate() {
-            if body.is_empty() {
+            // An empty body is only sent when it carries the FIN of a stream of unknown length.
+            if body.is_empty() && !*is_end {
                 continue;
             }
 
@@ -691,6 +999,7 @@ fn handle_writable(
                         );
                         if v == body.len() {
                             *body = vec![];
+                            *is_end = false;
                         }
                         if v < body.len() {
                             new_position_index = Some(i);
@@ -742,6 +1051,37 @@ fn handle_writable(
     // }
     will_break_main_loop
 }
+///
+///Send the trailer section of a request, which closes its stream. Returns false if the stream
+///is blocked and the trailers have to be sent again later.
+///
+fn send_trailers(
+    h3_conn: &mut h3::Connection,
+    conn: &mut quiche::Connection,
+    trailers_req: &TrailersRequest,
+) -> bool {
+    match h3_conn.send_additional_headers(
+        conn,
+        trailers_req.stream_id(),
+        trailers_req.headers(),
+        true,
+        true,
+    ) {
+        Ok(()) => {
+            debug!("Trailers sent on stream [{}]", trailers_req.stream_id());
+            true
+        }
+        Err(h3::Error::StreamBlocked) => false,
+        Err(e) => {
+            error!(
+                "Failed to send trailers on stream [{}] [{:?}]",
+                trailers_req.stream_id(),
+                e
+            );
+            true
+        }
+    }
+}
 fn measure_output_bandwitdth(bytes_written: u64, time_since_start: Instant) -> f64 {
     let duration = time_since_start.elapsed().as_secs_f64() * 1_000_000.0;
 
@@ -1,42 +1,82 @@
+mod authentication;
 mod body_manager;
+mod certificate_pinning;
 mod client_request_manager;
+mod connection_stats;
+mod content_digest;
+mod content_encoding;
+mod cookie_jar;
+mod event_stream;
+mod middleware;
+mod ndjson;
 mod persistant_stream;
+mod ranged_download;
+mod redirect;
 mod request_manager;
+mod response_cache;
+mod response_error;
 mod response_manager;
+mod retry_policy;
+mod status_code;
+mod stream_framing;
+pub use authentication::{Authenticator, BasicAuth, BearerAuth};
 pub use body_manager::{BodyChannel, BodyHead, BodyQueue};
+pub use certificate_pinning::SpkiPins;
 pub use client_request_manager::ClientRequestManager;
+pub use connection_stats::{ConnectionStats, StatsChannel, StatsHead, StatsQueue, STATS_INTERVAL};
+pub use content_encoding::Encoding;
+pub use cookie_jar::CookieJar;
+pub use event_stream::SseEvent;
+pub use middleware::Middleware;
+pub use ndjson::NdjsonItems;
+pub use persistant_stream::SseHandle;
+pub use ranged_download::RangedDownload;
+pub use redirect::RedirectPolicy;
 pub use request_manager::{
-    BodyType, ContentType, H3Method, Http3Request, ProgressTracker, RequestChannel, RequestEvent,
-    RequestEventListener, RequestQueue,
+    BodyType, ContentType, H3Method, Http3Request, Http3RequestBuilder, ProgressTracker,
+    RequestChannel, RequestEvent, RequestEventListener, RequestQueue, RequestTrailers,
+    TrailersRequest,
 };
+pub use response_cache::ResponseCache;
+pub use response_error::ResponseError;
 pub use response_manager::{
-    Http3Response, ReqStatus, ResponseChannel, ResponseHead, ResponseQueue, UploadProgressStatus,
+    CompletedResponse, Http3Response, ReqStatus, ResponseChannel, ResponseHead, ResponseQueue,
+    UploadProgressStatus, WaitPeerResponse,
+};
+pub use retry_policy::RetryPolicy;
+pub use status_code::{StatusCode, StatusError};
+pub use stream_framing::{
+    DefaultFramer, FixedSizeFramer, NewlineFramer, RawFramer, StreamFramer, VarintFramer,
 };
 
 pub use super::client_config::ConnexionInfos;
 use super::client_init::Http3Client;
-pub use client_management::Http3ClientManager;
+pub use client_management::{Http3ClientManager, Http3ClientManagerBuilder};
 
 mod client_management {
 
     use std::{
         collections::HashMap,
         hash::Hash,
+        io::Read,
         path::Path,
         sync::{Arc, Mutex},
     };
 
-    use log::{error, info};
+    use log::info;
     use ring::error;
+    use serde::{de::DeserializeOwned, Serialize};
     use uuid::Uuid;
 
     use crate::{
-        client_config::{self, ClientConfig},
+        client_config::{self, ClientConfig, ClientIdentity, KeyLog},
+        client_init::NetworkImpairment,
         client_traits::IntoBodyReq,
         my_log,
     };
 
     use self::{
+        ndjson::{ndjson_sink, NdjsonItems},
         persistant_stream::{StreamBuilder, StreamEvent},
         request_manager::{Http3RequestBuilder, Http3RequestPrep},
         response_manager::WaitPeerResponse,
@@ -76,7 +116,12 @@ mod client_management {
         ///ConnexionInfos can be modified with new_connect_infos()
         ///
         pub fn new(peer_socket_address: &str) -> Self {
-            let client_config = ClientConfig::new();
+            Self::with_config(peer_socket_address, ClientConfig::new())
+        }
+        ///
+        ///Create the Http3ClientManager with client wide options set in `client_config`.
+        ///
+        pub fn with_config(peer_socket_address: &str, client_config: ClientConfig) -> Self {
             my_log::init();
             client_config
                 .connexion_infos()
@@ -100,7 +145,7 @@ mod client_management {
                 request_channel.get_head(),
                 response_channel.get_queue(),
                 body_channel.get_head(),
-                client_config.connexion_infos(),
+                client_config.clone(),
                 http3_client_arc,
             );
 
@@ -115,13 +160,28 @@ mod client_management {
             }
         }
 
-        pub fn builder() -> () {
-            ()
+        ///
+        ///Configure client wide options before creating the Http3ClientManager.
+        ///
+        pub fn builder(peer_socket_address: &str) -> Http3ClientManagerBuilder {
+            Http3ClientManagerBuilder {
+                peer_socket_address: peer_socket_address.to_string(),
+                client_config: ClientConfig::new(),
+            }
         }
 
         pub fn request_manager_ref(&self) -> &ClientRequestManager {
             &self.request_manager
         }
+        ///
+        ///Last snapshot of the connexion : RTT, congestion window, bytes sent, received and
+        ///lost, retransmissions, active streams and bodies waiting for stream capacity. The
+        ///client loop publishes one every STATS_INTERVAL while the connexion is active, and a
+        ///last one when it closes. None before the first connexion.
+        ///
+        pub fn stats(&self) -> Option<ConnectionStats> {
+            self.http3_client.stats()
+        }
         pub fn down_stream(&self, path: &str, data: impl IntoBodyReq) -> ReqBuilderOutput {
             let reqbuild_uuid = uuid::Uuid::new_v4();
             let mut http3_request_builder = Http3RequestPrep::new(
@@ -155,6 +215,35 @@ mod client_management {
 
             ReqBuilderOutput(reqbuild_uuid, self)
         }
+        pub fn head(&self, path: &str) -> ReqBuilderOutput {
+            let reqbuild_uuid = uuid::Uuid::new_v4();
+            let mut http3_request_builder = Http3RequestPrep::new(
+                self.connexion_infos.get_peer_socket_address(),
+                reqbuild_uuid,
+            );
+            http3_request_builder.head(path.to_owned());
+
+            self.request_builder
+                .lock()
+                .unwrap()
+                .entry(reqbuild_uuid)
+                .insert_entry(http3_request_builder);
+
+            ReqBuilderOutput(reqbuild_uuid, self)
+        }
+        ///
+        ///Download `path` into the file `dest` with up to `segments` `range` requests at the
+        ///same time, each of at most 8 MiB. The size is first asked with a HEAD; a peer that
+        ///doesn't accept byte ranges gets a single GET.
+        ///
+        pub fn download_ranged(
+            &self,
+            path: &str,
+            dest: impl AsRef<Path>,
+            segments: usize,
+        ) -> RangedDownload {
+            RangedDownload::new(self, path, dest.as_ref().to_path_buf(), segments)
+        }
         pub fn post_data(&self, path: &str, data: impl IntoBodyReq) -> ReqBuilderOutput {
             let reqbuild_uuid = uuid::Uuid::new_v4();
             let mut http3_request_builder = Http3RequestPrep::new(
@@ -193,13 +282,41 @@ mod client_management {
 
             ReqBuilderOutput(reqbuild_uuid, self)
         }
-        pub fn delete(&self, path: String, auth_token: String) -> ReqBuilderOutput {
+        ///
+        ///Upload everything produced by `stream` (a pipe, a microphone...) without announcing a
+        ///`content-length`. Chunks are sent as soon as they are read and the stream is finished
+        ///when the reader returns EOF.
+        ///
+        pub fn post_stream(
+            &self,
+            path: &str,
+            stream: impl Read + Send + 'static,
+        ) -> ReqBuilderOutput {
+            let reqbuild_uuid = uuid::Uuid::new_v4();
+            let mut http3_request_builder = Http3RequestPrep::new(
+                self.connexion_infos.get_peer_socket_address(),
+                reqbuild_uuid,
+            );
+            http3_request_builder.post_stream(path.to_string(), Box::new(stream));
+
+            self.request_builder
+                .lock()
+                .unwrap()
+                .entry(reqbuild_uuid)
+                .insert_entry(http3_request_builder);
+
+            ReqBuilderOutput(reqbuild_uuid, self)
+        }
+        ///
+        ///Credentials come from the client's authenticator, like any other request.
+        ///
+        pub fn delete(&self, path: String) -> ReqBuilderOutput {
             let reqbuild_uuid = uuid::Uuid::new_v4();
             let mut http3_request_builder = Http3RequestPrep::new(
                 self.connexion_infos.get_peer_socket_address(),
                 reqbuild_uuid,
             );
-            http3_request_builder.delete(path, auth_token);
+            http3_request_builder.delete(path);
 
             self.request_builder
                 .lock()
@@ -217,6 +334,52 @@ mod client_management {
             self.request_manager.new_request(request_builder)
         }
 
+        ///
+        ///Put a response cache in front of the GET requests (see ResponseCache).
+        ///
+        pub fn set_response_cache(&self, response_cache: ResponseCache) -> &Self {
+            self.request_manager.set_response_cache(response_cache);
+            self
+        }
+        ///
+        ///Keep the cookies set by the peer and send them back on the next requests.
+        ///
+        pub fn set_cookie_jar(&self, cookie_jar: CookieJar) -> &Self {
+            self.request_manager.set_cookie_jar(cookie_jar);
+            self
+        }
+        ///
+        ///Add the credentials of `authenticator` to every request. On a 401, the credentials
+        ///are refreshed once and the request is sent again.
+        ///
+        pub fn set_authenticator(&self, authenticator: impl Authenticator) -> &Self {
+            self.request_manager.set_authenticator(authenticator);
+            self
+        }
+        ///
+        ///Register a middleware. They run in registration order on the requests, and in the
+        ///reverse order on the responses.
+        ///
+        pub fn add_middleware(&self, middleware: impl Middleware) -> &Self {
+            self.request_manager.add_middleware(middleware);
+            self
+        }
+        ///
+        ///Send failed requests again (stream reset, connexion lost, retryable status) as
+        ///described by `retry_policy`.
+        ///
+        pub fn set_retry_policy(&self, retry_policy: RetryPolicy) -> &Self {
+            self.request_manager.set_retry_policy(retry_policy);
+            self
+        }
+        ///
+        ///Follow the redirections (301, 302, 303, 307, 308) as described by
+        ///`redirect_policy`. Without it, redirect responses are handed back as is.
+        ///
+        pub fn set_redirect_policy(&self, redirect_policy: RedirectPolicy) -> &Self {
+            self.request_manager.set_redirect_policy(redirect_policy);
+            self
+        }
         pub fn new_connect_infos(&self, new_client_config: ClientConfig) -> &Self {
             self.connexion_infos
                 .update(&new_client_config.connexion_infos());
@@ -254,6 +417,94 @@ mod client_management {
         */
     }
 
+    ///
+    ///Builder for client wide options. Call build() to get the Http3ClientManager.
+    ///
+    pub struct Http3ClientManagerBuilder {
+        peer_socket_address: String,
+        client_config: ClientConfig,
+    }
+
+    impl Http3ClientManagerBuilder {
+        ///
+        ///Name of the peer, see ClientConfig::set_server_name().
+        ///
+        pub fn server_name(&mut self, server_name: &str) -> &mut Self {
+            self.client_config.set_server_name(server_name);
+            self
+        }
+        ///
+        ///Compress every request body of at least `min_size` bytes with `encoding`.
+        ///
+        pub fn compression_threshold(&mut self, min_size: usize, encoding: Encoding) -> &mut Self {
+            self.client_config
+                .set_compression_threshold(min_size, encoding);
+            self
+        }
+        ///
+        ///Write a qlog trace of each connexion in `dir`, see ClientConfig::set_qlog_dir().
+        ///
+        pub fn qlog_dir(&mut self, dir: impl AsRef<std::path::Path>) -> &mut Self {
+            self.client_config.set_qlog_dir(dir);
+            self
+        }
+        ///
+        ///Append the TLS secrets to the file named by `SSLKEYLOGFILE`, if it is set.
+        ///
+        pub fn key_log_from_env(&mut self) -> &mut Self {
+            self.client_config.set_key_log(KeyLog::FromEnv);
+            self
+        }
+        ///
+        ///Authenticate with a client certificate when the peer asks for one (mutual TLS).
+        ///
+        pub fn client_identity(&mut self, client_identity: ClientIdentity) -> &mut Self {
+            self.client_config.set_client_identity(client_identity);
+            self
+        }
+        ///
+        ///Only accept peers whose certificate public key has this SHA-256 (base64, optionally
+        ///prefixed by `sha256/`). Without ca_file() the peer certificate is not verified and
+        ///the pins are the only check. Can be called for each accepted key (current and
+        ///backup keys).
+        ///
+        pub fn pin_spki_sha256(&mut self, pin: &str) -> &mut Self {
+            self.client_config.add_spki_pin(pin);
+            self
+        }
+        ///
+        ///Verify the peer certificate against the CA certificates (PEM) of `ca_file`. The
+        ///certificate must be issued for the server_name().
+        ///
+        pub fn ca_file(&mut self, ca_file: impl AsRef<std::path::Path>) -> &mut Self {
+            self.client_config.set_ca_file(ca_file);
+            self
+        }
+        ///
+        ///Append the TLS secrets to `path`, whatever `SSLKEYLOGFILE` is.
+        ///
+        pub fn key_log_file(&mut self, path: impl AsRef<std::path::Path>) -> &mut Self {
+            self.client_config
+                .set_key_log(KeyLog::File(path.as_ref().to_path_buf()));
+            self
+        }
+        ///
+        ///Simulate a lossy or slow network between the client and the peer, see
+        ///NetworkImpairment.
+        ///
+        pub fn network_impairment(&mut self, network_impairment: NetworkImpairment) -> &mut Self {
+            self.client_config
+                .set_network_impairment(network_impairment);
+            self
+        }
+        pub fn build(&self) -> Http3ClientManager {
+            Http3ClientManager::with_config(
+                self.peer_socket_address.as_str(),
+                self.client_config.clone(),
+            )
+        }
+    }
+
     pub struct ReqBuilderOutput<'a>(Uuid, &'a Http3ClientManager);
 
     impl<'a> ReqBuilderOutput<'a> {
@@ -266,6 +517,37 @@ mod client_management {
             }
             Err(())
         }
+        ///
+        ///Send the request and deserialize each line of a newline-delimited JSON response
+        ///(`application/x-ndjson`, JSON lines) as soon as it is received. Blank lines are
+        ///skipped. The response then has no body, unless its status is not a success.
+        ///
+        pub fn send_ndjson<T: DeserializeOwned>(
+            &self,
+            on_item: impl Fn(Result<T, serde_json::Error>) + Send + Sync + 'static,
+        ) -> Result<WaitPeerResponse, ()> {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                if entry.header("accept").is_none() {
+                    entry.set_header("accept".to_string(), "application/x-ndjson".to_string());
+                }
+                entry.set_line_sink(ndjson_sink(on_item));
+            }
+            self.send()
+        }
+        ///
+        ///Like send_ndjson(), the items being iterated as they arrive. The iteration ends
+        ///with the response.
+        ///
+        pub fn ndjson_items<T: DeserializeOwned + Send + 'static>(
+            &self,
+        ) -> Result<NdjsonItems<T>, ()> {
+            let (sender, receiver) = crossbeam::channel::unbounded();
+            let peer_response = self.send_ndjson(move |item| {
+                let _ = sender.send(item);
+            })?;
+            Ok(NdjsonItems::new(receiver, peer_response))
+        }
         pub fn stream(&self) -> StreamBuilder {
             let uuid = self.0;
             my_log::debug("build STREAM BUILDER");
@@ -278,6 +560,82 @@ mod client_management {
             }
             self
         }
+        ///
+        ///Append a percent-encoded `key=value` pair to the request query string.
+        ///
+        pub fn query(&self, key: &str, value: &str) -> &Self {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                entry.add_query(key, value);
+            }
+            self
+        }
+        ///
+        ///Serialize `value` into query parameters (struct fields, map entries or tuples). On
+        ///error, send() fails too.
+        ///
+        pub fn query_serde<T: Serialize + ?Sized>(
+            &self,
+            value: &T,
+        ) -> Result<&Self, serde_urlencoded::ser::Error> {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                entry.add_query_serde(value)?;
+            }
+            Ok(self)
+        }
+        ///
+        ///Compress the request body with `encoding` (sets `content-encoding`).
+        ///
+        pub fn compress(&self, encoding: Encoding) -> &Self {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                entry.compress(encoding);
+            }
+            self
+        }
+        ///
+        ///Let the retry policy send this request again even if it is a POST.
+        ///
+        pub fn retry_safe(&self) -> &Self {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                entry.mark_retry_safe();
+            }
+            self
+        }
+        ///
+        ///Send a trailer section after the request body, e.g. a checksum computed while the
+        ///body is streamed.
+        ///
+        pub fn trailers(&self, trailers: impl RequestTrailers) -> &Self {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                entry.set_trailers(trailers);
+            }
+            self
+        }
+        ///
+        ///Send the SHA-256 of the body in `content-digest`, so the peer can detect a corrupted
+        ///upload.
+        ///
+        pub fn content_digest(&self) -> &Self {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                entry.set_content_digest();
+            }
+            self
+        }
+        ///
+        ///Hand back the response body as received, without decoding its `content-encoding`.
+        ///
+        pub fn disable_decompression(&self) -> &Self {
+            let uuid = self.0;
+            if let Some(entry) = self.1.request_builder.lock().unwrap().get_mut(&uuid) {
+                entry.disable_decompression();
+            }
+            self
+        }
         pub fn set_user_agent(&self, user_agent: &str) -> &Self {
             let uuid = self.0;
 
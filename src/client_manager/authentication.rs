@@ -0,0 +1,233 @@
+pub use auth_layer::AuthLayer;
+pub use authenticators::{Authenticator, BasicAuth, BearerAuth};
+
+mod authenticators {
+    use std::sync::{Arc, Mutex};
+
+    use base64::Engine;
+
+    ///
+    ///Provides the credentials added to every request, and a way to renew them when the peer
+    ///answers `401 Unauthorized`.
+    ///
+    pub trait Authenticator: Send + Sync + 'static {
+        ///
+        ///Value of the `authorization` header, None to send the request without credentials.
+        ///
+        fn authorization(&self) -> Option<String>;
+        ///
+        ///Renew the credentials after a 401. Returns true if the request is worth retrying.
+        ///
+        fn refresh(&self) -> bool {
+            false
+        }
+    }
+
+    ///
+    ///`authorization: Bearer <token>`, with an optional function that fetches a new token when
+    ///the current one is rejected.
+    ///
+    pub struct BearerAuth {
+        token: Mutex<String>,
+        refresh_token: Option<Arc<dyn Fn() -> Option<String> + Send + Sync + 'static>>,
+    }
+
+    impl BearerAuth {
+        pub fn new(token: impl Into<String>) -> Self {
+            Self {
+                token: Mutex::new(token.into()),
+                refresh_token: None,
+            }
+        }
+        ///
+        ///`refresh_token` is called once on a 401 and returns the new token (None if it could
+        ///not get one).
+        ///
+        pub fn with_refresh(
+            mut self,
+            refresh_token: impl Fn() -> Option<String> + Send + Sync + 'static,
+        ) -> Self {
+            self.refresh_token = Some(Arc::new(refresh_token));
+            self
+        }
+        pub fn set_token(&self, token: impl Into<String>) {
+            *self.token.lock().unwrap() = token.into();
+        }
+    }
+
+    impl Authenticator for BearerAuth {
+        fn authorization(&self) -> Option<String> {
+            Some(format!("Bearer {}", self.token.lock().unwrap()))
+        }
+        fn refresh(&self) -> bool {
+            let Some(refresh_token) = &self.refresh_token else {
+                return false;
+            };
+            match refresh_token() {
+                Some(token) => {
+                    self.set_token(token);
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    ///
+    ///`authorization: Basic <base64(user:password)>`.
+    ///
+    pub struct BasicAuth {
+        credentials: String,
+    }
+
+    impl BasicAuth {
+        pub fn new(user: &str, password: &str) -> Self {
+            Self {
+                credentials: base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", user, password)),
+            }
+        }
+    }
+
+    impl Authenticator for BasicAuth {
+        fn authorization(&self) -> Option<String> {
+            Some(format!("Basic {}", self.credentials))
+        }
+    }
+}
+
+mod auth_layer {
+    use std::sync::{Arc, Mutex};
+
+    use log::{debug, warn};
+
+    use crate::client_manager::{
+        request_manager::Http3RequestBuilder, response_manager::WaitPeerResponse,
+    };
+
+    use super::Authenticator;
+
+    struct RefreshState {
+        generation: u64,
+        refreshed: bool,
+    }
+
+    ///
+    ///Adds the credentials of the Authenticator to the requests and retries once after a
+    ///refresh when the peer answers 401. Requests failing at the same time share a single
+    ///refresh : the first one refreshes, the others wait for it and reuse its outcome.
+    ///
+    #[derive(Clone)]
+    pub struct AuthLayer {
+        authenticator: Arc<dyn Authenticator>,
+        refresh_state: Arc<Mutex<RefreshState>>,
+    }
+
+    impl AuthLayer {
+        pub fn new(authenticator: impl Authenticator) -> Self {
+            Self {
+                authenticator: Arc::new(authenticator),
+                refresh_state: Arc::new(Mutex::new(RefreshState {
+                    generation: 0,
+                    refreshed: false,
+                })),
+            }
+        }
+        fn authorize(&self, http3_request_builder: &mut Http3RequestBuilder) {
+            if let Some(authorization) = self.authenticator.authorization() {
+                http3_request_builder.replace_header("authorization", authorization);
+            }
+        }
+        fn generation(&self) -> u64 {
+            self.refresh_state.lock().unwrap().generation
+        }
+        ///
+        ///Refresh the credentials, unless it has already been done since the request was sent.
+        ///The lock is held during the refresh so concurrent requests wait for it.
+        ///
+        fn refresh_once(&self, sent_with_generation: u64) -> bool {
+            let mut refresh_state = self.refresh_state.lock().unwrap();
+            if refresh_state.generation == sent_with_generation {
+                refresh_state.refreshed = self.authenticator.refresh();
+                refresh_state.generation += 1;
+            }
+            refresh_state.refreshed
+        }
+
+        ///
+        ///Send the request with `dispatch`, authenticated. An `authorization` header set by the
+        ///user on the request is left as is and disables the retry.
+        ///
+        pub fn send_through(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            dispatch: impl Fn(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()> + Send + 'static,
+        ) -> Result<WaitPeerResponse, ()> {
+            if http3_request_builder.header("authorization").is_some() {
+                return dispatch(http3_request_builder);
+            }
+            let replay = http3_request_builder.replay_source();
+            let sent_with_generation = self.generation();
+            self.authorize(http3_request_builder);
+
+            let peer_response = dispatch(http3_request_builder)?;
+            let Some(replay) = replay else {
+                debug!("request body can't be replayed, no retry on 401");
+                return Ok(peer_response);
+            };
+
+            let auth_layer = self.clone();
+            Ok(peer_response.intercept(move |response| {
+                let response = response?;
+                if response.http_status() != Some(401)
+                    || !auth_layer.refresh_once(sent_with_generation)
+                {
+                    return Ok(response);
+                }
+                let Some(mut retry) = replay.request() else {
+                    return Ok(response);
+                };
+                auth_layer.authorize(&mut retry);
+                match dispatch(&mut retry).map(|retry| retry.wait_response()) {
+                    Ok(Ok(retried)) => Ok(retried),
+                    _ => {
+                        warn!("Retry after authentication refresh failed");
+                        Ok(response)
+                    }
+                }
+            }))
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn bearer_and_basic_credentials() {
+        let refresh_count = Arc::new(AtomicUsize::new(0));
+        let count = refresh_count.clone();
+        let bearer = BearerAuth::new("expired").with_refresh(move || {
+            count.fetch_add(1, Ordering::SeqCst);
+            Some("fresh".to_string())
+        });
+        assert_eq!(bearer.authorization().unwrap(), "Bearer expired");
+        assert!(bearer.refresh());
+        assert_eq!(bearer.authorization().unwrap(), "Bearer fresh");
+        assert_eq!(refresh_count.load(Ordering::SeqCst), 1);
+
+        assert!(!BearerAuth::new("static").refresh());
+
+        let basic = BasicAuth::new("Aladdin", "open sesame");
+        assert_eq!(
+            basic.authorization().unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+}
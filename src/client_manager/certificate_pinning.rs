@@ -0,0 +1,158 @@
+pub use pinning::{spki_sha256, SpkiPins};
+
+mod pinning {
+    use base64::Engine;
+    use log::error;
+    use ring::digest::{digest, SHA256};
+
+    use crate::client_manager::response_error::ResponseError;
+
+    ///
+    ///The DER element at the start of `input` : its tag, the whole element, its content and
+    ///what follows it.
+    ///
+    fn read_der(input: &[u8]) -> Option<(u8, &[u8], &[u8], &[u8])> {
+        let tag = *input.first()?;
+        let first_len = *input.get(1)?;
+        let (len, header_len) = if first_len < 0x80 {
+            (first_len as usize, 2)
+        } else {
+            let len_bytes = (first_len & 0x7f) as usize;
+            if len_bytes == 0 || len_bytes > 4 {
+                return None;
+            }
+            let bytes = input.get(2..2 + len_bytes)?;
+            (
+                bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize),
+                2 + len_bytes,
+            )
+        };
+        let end = header_len.checked_add(len)?;
+        let element = input.get(..end)?;
+        Some((tag, element, &element[header_len..], &input[end..]))
+    }
+
+    ///
+    ///SHA-256 of the DER SubjectPublicKeyInfo of an X.509 certificate, None if `cert_der` is
+    ///not a certificate.
+    ///
+    pub fn spki_sha256(cert_der: &[u8]) -> Option<Vec<u8>> {
+        let (_, _, certificate, _) = read_der(cert_der)?;
+        let (_, _, mut tbs_fields, _) = read_der(certificate)?;
+        // version [0] is optional
+        if tbs_fields.first() == Some(&0xa0) {
+            tbs_fields = read_der(tbs_fields)?.3;
+        }
+        // serialNumber, signature, issuer, validity, subject
+        for _ in 0..5 {
+            tbs_fields = read_der(tbs_fields)?.3;
+        }
+        let (tag, spki, _, _) = read_der(tbs_fields)?;
+        if tag != 0x30 {
+            return None;
+        }
+        Some(digest(&SHA256, spki).as_ref().to_vec())
+    }
+
+    ///
+    ///SHA-256 hashes of the accepted server public keys. They are checked after the CA
+    ///verification when a CA file is set, and are the only check of the peer otherwise. No
+    ///pin means no pinning.
+    ///
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct SpkiPins {
+        pins: Vec<Vec<u8>>,
+    }
+
+    impl SpkiPins {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        ///
+        ///Add a base64 SHA-256 of a SubjectPublicKeyInfo, optionally prefixed by `sha256/`.
+        ///An invalid pin is kept and never matches, so the connexions fail instead of running
+        ///unpinned.
+        ///
+        pub fn add(&mut self, pin: &str) -> &mut Self {
+            let pin = pin.trim();
+            let pin = pin.strip_prefix("sha256/").unwrap_or(pin);
+            let decoded = match base64::engine::general_purpose::STANDARD.decode(pin) {
+                Ok(decoded) if decoded.len() == 32 => decoded,
+                _ => {
+                    error!("Invalid SHA-256 pin [{}], it won't match any key", pin);
+                    vec![]
+                }
+            };
+            self.pins.push(decoded);
+            self
+        }
+        pub fn is_empty(&self) -> bool {
+            self.pins.is_empty()
+        }
+        ///
+        ///Check the leaf certificate of the peer against the pins.
+        ///
+        pub fn verify(&self, peer_cert: Option<&[u8]>) -> Result<(), ResponseError> {
+            if self.is_empty() {
+                return Ok(());
+            }
+            let Some(spki_hash) = peer_cert.and_then(spki_sha256) else {
+                return Err(ResponseError::PinningFailed);
+            };
+            if self.pins.iter().any(|pin| *pin == spki_hash) {
+                Ok(())
+            } else {
+                Err(ResponseError::PinningFailed)
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use base64::Engine;
+
+    use crate::client_manager::response_error::ResponseError;
+
+    fn pem_to_der(pem: &str) -> Vec<u8> {
+        let body: String = pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        base64::engine::general_purpose::STANDARD
+            .decode(body)
+            .unwrap()
+    }
+
+    #[test]
+    fn peer_key_is_checked_against_pins() {
+        let server_cert = pem_to_der(include_str!("../../test_certs/server.crt"));
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD.encode(spki_sha256(&server_cert).unwrap()),
+            "ZNX/OhoH1LjOQ6N9MY0szvUO0dglaLmlN6qamYNca3Q="
+        );
+
+        let mut pins = SpkiPins::new();
+        assert_eq!(pins.verify(None), Ok(()));
+
+        pins.add("sha256/BFz269onJCI6r3Jp3p9CbaiRXrSWq/sFQ/ViI083azg=");
+        assert_eq!(
+            pins.verify(Some(&server_cert)),
+            Err(ResponseError::PinningFailed)
+        );
+        assert_eq!(pins.verify(None), Err(ResponseError::PinningFailed));
+
+        pins.add("ZNX/OhoH1LjOQ6N9MY0szvUO0dglaLmlN6qamYNca3Q=");
+        assert_eq!(pins.verify(Some(&server_cert)), Ok(()));
+
+        let mut invalid = SpkiPins::new();
+        invalid.add("not a pin");
+        assert!(!invalid.is_empty());
+        assert_eq!(
+            invalid.verify(Some(&server_cert)),
+            Err(ResponseError::PinningFailed)
+        );
+    }
+}
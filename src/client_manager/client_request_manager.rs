@@ -2,6 +2,8 @@ pub use client_request_mngr::ClientRequestManager;
 
 mod client_request_mngr {
     use std::{
+        collections::HashMap,
+        net::SocketAddr,
         sync::{Arc, Mutex},
         time::{Duration, Instant},
     };
@@ -11,19 +13,38 @@ mod client_request_mngr {
     use uuid::Uuid;
 
     use crate::{
-        client_config::ConnexionInfos,
+        client_config::{ClientConfig, ConnexionInfos},
         client_init::Http3Client,
         client_manager::{
+            authentication::{AuthLayer, Authenticator},
+            cookie_jar::CookieJar,
+            middleware::{Middleware, MiddlewareChain},
             persistant_stream::{
                 KeepAlive, PingEmitter, StreamControlFlow, StreamEvent, StreamSub,
             },
-            request_manager::{Http3Request, Http3RequestBuilder, Http3RequestPrep, RequestHead},
+            redirect::RedirectPolicy,
+            request_manager::{
+                H3Method, Http3Request, Http3RequestBuilder, Http3RequestPrep, RequestHead,
+            },
+            response_cache::ResponseCache,
+            response_error::ResponseError,
             response_manager::{PartialResponse, ResponseManager, WaitPeerResponse},
-            BodyHead, ResponseQueue,
+            retry_policy::RetryPolicy,
+            BodyChannel, BodyHead, RequestChannel, ResponseChannel, ResponseQueue,
         },
         my_log,
     };
 
+    ///
+    ///Connexions opened to follow redirections that are kept at once, the least recently used
+    ///is dropped first.
+    ///
+    const MAX_REDIRECT_PEERS: usize = 8;
+    ///
+    ///A connexion opened to follow redirections is dropped after this long without request.
+    ///
+    const REDIRECT_PEER_IDLE: Duration = Duration::from_secs(60);
+
     ///
     ///Interface with the client. Create a new request, send data from here.
     ///
@@ -32,9 +53,22 @@ mod client_request_mngr {
         response_queue: ResponseQueue,
         body_head: BodyHead,
         connexion_infos: ConnexionInfos,
+        client_config: ClientConfig,
         response_manager: ResponseManager,
         http3_client: Arc<Http3Client>,
         waker: Arc<Mutex<Option<Waker>>>,
+        response_cache: Arc<Mutex<Option<ResponseCache>>>,
+        cookie_jar: Arc<Mutex<Option<CookieJar>>>,
+        auth_layer: Arc<Mutex<Option<AuthLayer>>>,
+        middlewares: MiddlewareChain,
+        retry_policy: Arc<Mutex<Option<RetryPolicy>>>,
+        redirect_policy: Arc<Mutex<Option<RedirectPolicy>>>,
+        redirect_managers: Arc<Mutex<HashMap<(SocketAddr, String), RedirectPeer>>>,
+    }
+
+    struct RedirectPeer {
+        request_manager: ClientRequestManager,
+        last_used: Instant,
     }
 
     impl Clone for ClientRequestManager {
@@ -44,9 +78,17 @@ mod client_request_mngr {
                 response_queue: self.response_queue.clone(),
                 body_head: self.body_head.clone(),
                 connexion_infos: self.connexion_infos.clone(),
+                client_config: self.client_config.clone(),
                 response_manager: self.response_manager.clone(),
                 http3_client: self.http3_client.clone(),
                 waker: self.waker.clone(),
+                response_cache: self.response_cache.clone(),
+                cookie_jar: self.cookie_jar.clone(),
+                auth_layer: self.auth_layer.clone(),
+                middlewares: self.middlewares.clone(),
+                retry_policy: self.retry_policy.clone(),
+                redirect_policy: self.redirect_policy.clone(),
+                redirect_managers: self.redirect_managers.clone(),
             }
         }
     }
@@ -56,7 +98,7 @@ mod client_request_mngr {
             request_head: RequestHead,
             response_queue: ResponseQueue,
             body_head: BodyHead,
-            connexion_infos: ConnexionInfos,
+            client_config: ClientConfig,
             http3_client: Arc<Http3Client>,
         ) -> Self {
             let resp_queue = response_queue.clone();
@@ -67,11 +109,100 @@ mod client_request_mngr {
                 request_head,
                 response_queue,
                 body_head,
-                connexion_infos,
+                connexion_infos: client_config.connexion_infos(),
+                client_config,
                 response_manager,
                 http3_client,
                 waker: Arc::new(Mutex::new(None)),
+                response_cache: Arc::new(Mutex::new(None)),
+                cookie_jar: Arc::new(Mutex::new(None)),
+                auth_layer: Arc::new(Mutex::new(None)),
+                middlewares: MiddlewareChain::new(),
+                retry_policy: Arc::new(Mutex::new(None)),
+                redirect_policy: Arc::new(Mutex::new(None)),
+                redirect_managers: Arc::new(Mutex::new(HashMap::new())),
+            }
+        }
+        pub fn set_response_cache(&self, response_cache: ResponseCache) {
+            *self.response_cache.lock().unwrap() = Some(response_cache);
+        }
+        pub fn set_cookie_jar(&self, cookie_jar: CookieJar) {
+            *self.cookie_jar.lock().unwrap() = Some(cookie_jar);
+        }
+        pub fn set_authenticator(&self, authenticator: impl Authenticator) {
+            *self.auth_layer.lock().unwrap() = Some(AuthLayer::new(authenticator));
+        }
+        pub fn add_middleware(&self, middleware: impl Middleware) {
+            self.middlewares.push(middleware);
+        }
+        pub fn set_retry_policy(&self, retry_policy: RetryPolicy) {
+            *self.retry_policy.lock().unwrap() = Some(retry_policy);
+        }
+        pub fn set_redirect_policy(&self, redirect_policy: RedirectPolicy) {
+            *self.redirect_policy.lock().unwrap() = Some(redirect_policy);
+        }
+        ///
+        ///Request manager for a redirection to another peer, named `host`. The connexion is
+        ///opened through the usual connect logic on its first request, and shares the client
+        ///layers, except the authentication. At most MAX_REDIRECT_PEERS are kept, and none
+        ///that is unused for REDIRECT_PEER_IDLE : a dropped manager's connexion ends with its
+        ///idle timeout.
+        ///
+        fn manager_for(
+            &self,
+            peer_socket_address: SocketAddr,
+            host: String,
+        ) -> ClientRequestManager {
+            let redirect_managers = &mut *self.redirect_managers.lock().unwrap();
+            let now = Instant::now();
+            redirect_managers
+                .retain(|_, redirect_peer| now - redirect_peer.last_used < REDIRECT_PEER_IDLE);
+            let key = (peer_socket_address, host);
+            if let Some(redirect_peer) = redirect_managers.get_mut(&key) {
+                redirect_peer.last_used = now;
+                return redirect_peer.request_manager.clone();
             }
+            if redirect_managers.len() >= MAX_REDIRECT_PEERS {
+                let least_recent = redirect_managers
+                    .iter()
+                    .min_by_key(|(_, redirect_peer)| redirect_peer.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(least_recent) = least_recent {
+                    redirect_managers.remove(&least_recent);
+                }
+            }
+            let client_config = self.client_config.for_peer(peer_socket_address, &key.1);
+            let request_channel = RequestChannel::new();
+            let response_channel = ResponseChannel::new();
+            let body_channel = BodyChannel::new();
+            let http3_client = Http3Client::new(
+                client_config.clone(),
+                request_channel.get_queue(),
+                response_channel.get_head(),
+                body_channel.get_queue(),
+            );
+            let mut request_manager = ClientRequestManager::new(
+                request_channel.get_head(),
+                response_channel.get_queue(),
+                body_channel.get_head(),
+                client_config,
+                Arc::new(http3_client),
+            );
+            request_manager.response_cache = self.response_cache.clone();
+            request_manager.cookie_jar = self.cookie_jar.clone();
+            request_manager.middlewares = self.middlewares.clone();
+            request_manager.retry_policy = self.retry_policy.clone();
+            // its own redirections get their own connexions : sharing the map would make the
+            // manager hold itself and never be dropped
+
+            redirect_managers.insert(
+                key,
+                RedirectPeer {
+                    request_manager: request_manager.clone(),
+                    last_used: now,
+                },
+            );
+            request_manager
         }
         pub fn wake_client(&self) {
             if let Some(waker) = &*self.waker.lock().unwrap() {
@@ -80,13 +211,98 @@ mod client_request_mngr {
                 }
             }
         }
+        ///
+        ///Apply client wide options to a request before it is built.
+        ///
+        fn apply_client_config(&self, http3_request_builder: &mut Http3RequestBuilder) {
+            if let Some((min_size, encoding)) = self.client_config.compression_threshold() {
+                http3_request_builder.compress_above(min_size, encoding);
+            }
+            if let Some(server_name) = self.client_config.server_name() {
+                http3_request_builder.default_host(server_name);
+            }
+        }
         pub fn new_stream_with_builder(
             &self,
             http3_request_builder: &mut Http3RequestBuilder,
             keep_alive: &Option<KeepAlive>,
             stream_cb: impl Fn(StreamEvent, StreamControlFlow) + Send + Sync + 'static,
+        ) -> Result<WaitPeerResponse, ()> {
+            self.new_stream_with_sub(
+                http3_request_builder,
+                keep_alive,
+                StreamSub::Downstream(Arc::new(stream_cb)),
+            )
+        }
+        ///
+        ///Open a stream whose body packets are handed to `stream_sub` as they arrive.
+        ///
+        ///The stream goes through the middlewares, the authenticator and the cookie jar like a
+        ///request. It is not redirected, retried or cached. When the authenticator sends it again
+        ///after a 401, the stream framer set on the request is not carried over.
+        ///
+        pub fn new_stream_with_sub(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            keep_alive: &Option<KeepAlive>,
+            stream_sub: StreamSub,
+        ) -> Result<WaitPeerResponse, ()> {
+            self.apply_client_config(http3_request_builder);
+            if self.middlewares.is_empty() {
+                return self.stream_through_auth(http3_request_builder, keep_alive, stream_sub);
+            }
+            self.middlewares
+                .send_through(http3_request_builder, |http3_request_builder| {
+                    self.stream_through_auth(http3_request_builder, keep_alive, stream_sub)
+                })
+        }
+        fn stream_through_auth(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            keep_alive: &Option<KeepAlive>,
+            stream_sub: StreamSub,
+        ) -> Result<WaitPeerResponse, ()> {
+            let auth_layer = self.auth_layer.lock().unwrap().clone();
+            match auth_layer {
+                Some(auth_layer) => {
+                    let request_manager = self.clone();
+                    let keep_alive = keep_alive.clone();
+                    auth_layer.send_through(http3_request_builder, move |http3_request_builder| {
+                        request_manager.stream_through_cookies(
+                            http3_request_builder,
+                            &keep_alive,
+                            stream_sub.clone(),
+                        )
+                    })
+                }
+                None => self.stream_through_cookies(http3_request_builder, keep_alive, stream_sub),
+            }
+        }
+        fn stream_through_cookies(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            keep_alive: &Option<KeepAlive>,
+            stream_sub: StreamSub,
+        ) -> Result<WaitPeerResponse, ()> {
+            let cookie_jar = self.cookie_jar.lock().unwrap().clone();
+            match cookie_jar {
+                Some(jar) => jar.send_through(http3_request_builder, |http3_request_builder| {
+                    self.dispatch_stream(http3_request_builder, keep_alive, stream_sub)
+                }),
+                None => self.dispatch_stream(http3_request_builder, keep_alive, stream_sub),
+            }
+        }
+        ///
+        ///Build the stream request and send it on the connexion (opened if needed).
+        ///
+        fn dispatch_stream(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            keep_alive: &Option<KeepAlive>,
+            stream_sub: StreamSub,
         ) -> Result<WaitPeerResponse, ()> {
             let path = http3_request_builder.get_path();
+            let stream_framer = http3_request_builder.take_stream_framer();
             my_log::debug("ici connexion ping");
             match http3_request_builder.build_down_stream(keep_alive) {
                 Ok((http3_request, event_subscriber, http3_confirm)) => {
@@ -96,8 +312,15 @@ mod client_request_mngr {
                      *
                      * */
                     if self.http3_client.is_off() {
-                        if let Ok((_conn_id, waker)) = self.http3_client.connect() {
-                            *self.waker.lock().unwrap() = Some(waker);
+                        match self.http3_client.connect() {
+                            Ok((_conn_id, waker)) => {
+                                *self.waker.lock().unwrap() = Some(waker);
+                            }
+                            // nothing would pop the request from the queue
+                            Err(e) => {
+                                error!("No connexion for [{:?}] : {}", path, e);
+                                return Ok(WaitPeerResponse::failed(e));
+                            }
                         }
                     }
 
@@ -142,8 +365,8 @@ mod client_request_mngr {
                         match req {
                             Http3RequestPrep::Body(body_req) => {
                                 my_log::debug(&body_req);
-                                self.request_head
-                                    .send_body(stream_id, 8192, body_req.take());
+                                let (body, trailers) = body_req.take_with_trailers();
+                                self.request_head.send_body(stream_id, 8192, body, trailers);
                             }
                             _ => my_log::log("no body"),
                         }
@@ -152,7 +375,6 @@ mod client_request_mngr {
                     let response_manager_submission = self.response_manager.submitter();
                     let response_chan = crossbeam::channel::bounded::<WaitPeerResponse>(1);
                     let response_sender = response_chan.0.clone();
-                    let stream_cb_syncable = Arc::new(stream_cb);
 
                     std::thread::spawn(move || {
                         /*
@@ -161,13 +383,16 @@ mod client_request_mngr {
                          *
                          * */
                         if let Ok(stream_ids) = stream_ids {
-                            let (partial_response, completed_channel, progress_channel) =
+                            let (mut partial_response, completed_channel, progress_channel) =
                                 PartialResponse::new_streamable(
                                     path.unwrap().as_str(),
                                     event_subscriber,
-                                    StreamSub::Downstream(stream_cb_syncable),
+                                    stream_sub,
                                     &stream_ids,
                                 );
+                            if let Some(stream_framer) = stream_framer {
+                                partial_response.set_framer(stream_framer);
+                            }
 
                             let peer_response = WaitPeerResponse::new(
                                 &stream_ids,
@@ -203,11 +428,143 @@ mod client_request_mngr {
                 Err(()) => Err(()),
             }
         }
+        ///
+        ///Send the request built with `http3_request_builder`, going through the optional
+        ///client layers (middlewares, redirections, retries, authentication, cookie jar,
+        ///response cache) before reaching the peer.
+        ///
         pub fn new_request_with_builder(
             &self,
             http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            self.apply_client_config(http3_request_builder);
+            self.send_through_middlewares(http3_request_builder)
+        }
+        ///
+        ///The middlewares wrap all the other layers : they see the request once, and its final
+        ///response (after redirections and retries, or from the cache).
+        ///
+        fn send_through_middlewares(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            if self.middlewares.is_empty() {
+                return self.send_through_redirects(http3_request_builder);
+            }
+            self.middlewares
+                .send_through(http3_request_builder, |http3_request_builder| {
+                    self.send_through_redirects(http3_request_builder)
+                })
+        }
+        fn send_through_redirects(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            let redirect_policy = self.redirect_policy.lock().unwrap().clone();
+            match redirect_policy {
+                Some(redirect_policy) => {
+                    let request_manager = self.clone();
+                    redirect_policy.send_through(
+                        http3_request_builder,
+                        move |http3_request_builder| {
+                            request_manager.route_by_authority(http3_request_builder)
+                        },
+                    )
+                }
+                None => self.send_through_retry(http3_request_builder),
+            }
+        }
+        ///
+        ///Send a redirected request on the connexion of its authority and host.
+        ///
+        fn route_by_authority(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            let peer_socket_address = self.connexion_infos.get_peer_socket_address();
+            let (Some(authority), Some(host)) = (
+                http3_request_builder.authority(),
+                http3_request_builder.host(),
+            ) else {
+                return self.send_through_retry(http3_request_builder);
+            };
+            let peer_host = self
+                .client_config
+                .server_name()
+                .map(|server_name| server_name.to_string())
+                .or_else(|| peer_socket_address.map(|address| address.ip().to_string()));
+            if Some(authority) == peer_socket_address && Some(&host) == peer_host.as_ref() {
+                return self.send_through_retry(http3_request_builder);
+            }
+            self.manager_for(authority, host)
+                .send_through_retry(http3_request_builder)
+        }
+        fn send_through_retry(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            let retry_policy = self.retry_policy.lock().unwrap().clone();
+            match retry_policy {
+                Some(retry_policy) => {
+                    let request_manager = self.clone();
+                    retry_policy.send_through(http3_request_builder, move |http3_request_builder| {
+                        request_manager.send_through_auth(http3_request_builder)
+                    })
+                }
+                None => self.send_through_auth(http3_request_builder),
+            }
+        }
+        fn send_through_auth(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            let auth_layer = self.auth_layer.lock().unwrap().clone();
+            match auth_layer {
+                Some(auth_layer) => {
+                    let request_manager = self.clone();
+                    auth_layer.send_through(http3_request_builder, move |http3_request_builder| {
+                        request_manager.send_through_cookies(http3_request_builder)
+                    })
+                }
+                None => self.send_through_cookies(http3_request_builder),
+            }
+        }
+        fn send_through_cookies(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            let cookie_jar = self.cookie_jar.lock().unwrap().clone();
+            match cookie_jar {
+                Some(jar) => jar.send_through(http3_request_builder, |http3_request_builder| {
+                    self.send_through_cache(http3_request_builder)
+                }),
+                None => self.send_through_cache(http3_request_builder),
+            }
+        }
+        fn send_through_cache(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Result<WaitPeerResponse, ()> {
+            let response_cache = self.response_cache.lock().unwrap().clone();
+            match response_cache {
+                Some(cache) => cache.send_through(http3_request_builder, |http3_request_builder| {
+                    self.dispatch_request(http3_request_builder)
+                }),
+                None => self.dispatch_request(http3_request_builder),
+            }
+        }
+        ///
+        ///Build the request and send it on the connexion (opened if needed). Returns once the
+        ///stream is created, with the handle to wait for the peer response.
+        ///
+        fn dispatch_request(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
         ) -> Result<WaitPeerResponse, ()> {
             let path = http3_request_builder.get_path();
+            let decompress = http3_request_builder.decompress();
+            let head_request = matches!(http3_request_builder.method(), Some(H3Method::HEAD));
+            let line_sink = http3_request_builder.take_line_sink();
             match http3_request_builder.build() {
                 Ok((http3_request, event_subscriber, http3_confirm)) => {
                     /*
@@ -216,8 +573,15 @@ mod client_request_mngr {
                      *
                      * */
                     if self.http3_client.is_off() {
-                        if let Ok((_conn_id, waker)) = self.http3_client.connect() {
-                            *self.waker.lock().unwrap() = Some(waker);
+                        match self.http3_client.connect() {
+                            Ok((_conn_id, waker)) => {
+                                *self.waker.lock().unwrap() = Some(waker);
+                            }
+                            // nothing would pop the request from the queue
+                            Err(e) => {
+                                error!("No connexion for [{:?}] : {}", path, e);
+                                return Ok(WaitPeerResponse::failed(e));
+                            }
                         }
                     }
 
@@ -239,14 +603,20 @@ mod client_request_mngr {
                         }
                     }
                     let stream_ids = http3_confirm.unwrap().wait_stream_ids();
-                    let stream_id = stream_ids.as_ref().unwrap().0;
+                    let stream_id = match stream_ids.as_ref() {
+                        Ok(ids) => ids.0,
+                        Err(e) => {
+                            error!("No stream created for [{:?}] [{:?}]", path, e);
+                            return Ok(WaitPeerResponse::failed(ResponseError::Disconnected));
+                        }
+                    };
 
                     for req in http3_request {
                         match req {
                             Http3RequestPrep::Body(body_req) => {
                                 my_log::debug(&body_req);
-                                self.request_head
-                                    .send_body(stream_id, 8192, body_req.take());
+                                let (body, trailers) = body_req.take_with_trailers();
+                                self.request_head.send_body(stream_id, 8192, body, trailers);
                             }
                             _ => my_log::log("no body"),
                         }
@@ -263,12 +633,15 @@ mod client_request_mngr {
                          *
                          * */
                         if let Ok(stream_ids) = stream_ids {
-                            let (partial_response, completed_channel, progress_channel) =
+                            let (mut partial_response, completed_channel, progress_channel) =
                                 PartialResponse::new(
                                     path.unwrap().as_str(),
                                     event_subscriber,
                                     &stream_ids,
                                 );
+                            partial_response.set_decompress(decompress);
+                            partial_response.set_head_request(head_request);
+                            partial_response.set_line_sink(line_sink);
 
                             let peer_response = WaitPeerResponse::new(
                                 &stream_ids,
@@ -319,101 +692,7 @@ mod client_request_mngr {
             );
             request_builder(&mut http3_request_builder);
 
-            let path = http3_request_builder.get_path();
-            match http3_request_builder.build() {
-                Ok((http3_request, event_subscriber, http3_confirm)) => {
-                    /*
-                     *
-                     * if connexion is closed, open it :
-                     *
-                     * */
-                    if self.http3_client.is_off() {
-                        if let Ok((_conn_id, waker)) = self.http3_client.connect() {
-                            *self.waker.lock().unwrap() = Some(waker);
-                        }
-                    }
-
-                    for req in &http3_request {
-                        match req {
-                            Http3RequestPrep::Header(header_req) => {
-                                let adjust_sending_duration =
-                                    crossbeam::channel::bounded::<Instant>(1);
-                                if let Err(e) = self.request_head.send_request((
-                                    Http3Request::Header(header_req.clone()),
-                                    adjust_sending_duration.0,
-                                )) {
-                                    println!("Error sending header request [{:?}]", e)
-                                } else {
-                                    self.wake_client();
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                    let stream_ids = http3_confirm.unwrap().wait_stream_ids();
-                    let stream_id = stream_ids.as_ref().unwrap().0;
-
-                    for req in http3_request {
-                        match req {
-                            Http3RequestPrep::Body(body_req) => {
-                                self.request_head
-                                    .send_body(stream_id, 8192, body_req.take());
-                            }
-                            _ => {}
-                        }
-                    }
-
-                    let response_manager_submission = self.response_manager.submitter();
-                    let response_chan = crossbeam::channel::bounded::<WaitPeerResponse>(1);
-                    let response_sender = response_chan.0.clone();
-
-                    std::thread::spawn(move || {
-                        /*
-                         *
-                         * wait here the stream_id with the response confirm
-                         *
-                         * */
-                        if let Ok(stream_ids) = stream_ids {
-                            let (partial_response, completed_channel, progress_channel) =
-                                PartialResponse::new(
-                                    path.unwrap().as_str(),
-                                    event_subscriber,
-                                    &stream_ids,
-                                );
-
-                            let peer_response = WaitPeerResponse::new(
-                                &stream_ids,
-                                completed_channel,
-                                progress_channel,
-                            );
-                            if let Err(e) = response_sender.send(peer_response) {
-                                println!("Error: sending back WaitPeerResponse failed stream_id[{:?}] [{:?}]",stream_ids,e);
-                            }
-
-                            //send partial response to the reponse manager
-                            if let Err(e) = response_manager_submission.submit(partial_response) {
-                                println!("Error: failed to submit Partial response for stream_id[{:?}]   [{:?}]", stream_ids,e );
-                            }
-                        }
-
-                        /*
-                         *
-                         *
-                         * Get the response back -> ask the response table in the response worker
-                         * with the stream_id that is unique per connexion
-                         *
-                         *
-                         * */
-                    });
-
-                    if let Ok(response) = response_chan.1.recv() {
-                        Ok(response)
-                    } else {
-                        Err(())
-                    }
-                }
-                Err(()) => Err(()),
-            }
+            self.new_request_with_builder(&mut http3_request_builder)
         }
     }
 }
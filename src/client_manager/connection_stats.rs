@@ -0,0 +1,217 @@
+pub use stats::{ConnectionStats, StatsChannel, StatsHead, StatsQueue, STATS_INTERVAL};
+
+mod stats {
+    use std::time::{Duration, Instant};
+
+    ///
+    ///How often the client loop publishes a ConnectionStats while the connexion is active.
+    ///
+    pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+    ///
+    ///Snapshot of a QUIC connexion and of its active path, see Http3ClientManager::stats().
+    ///
+    #[derive(Debug, Clone)]
+    pub struct ConnectionStats {
+        connexion_id: String,
+        taken_at: Instant,
+        rtt: Duration,
+        min_rtt: Option<Duration>,
+        rttvar: Duration,
+        cwnd: usize,
+        delivery_rate: u64,
+        packets_sent: usize,
+        packets_received: usize,
+        packets_lost: usize,
+        retransmissions: usize,
+        bytes_sent: u64,
+        bytes_received: u64,
+        bytes_lost: u64,
+        active_streams: usize,
+        pending_bodies: usize,
+        pending_body_bytes: usize,
+    }
+
+    impl ConnectionStats {
+        pub fn new(
+            connexion_id: &str,
+            stats: &quiche::Stats,
+            path_stats: Option<&quiche::PathStats>,
+            active_streams: usize,
+            pending_bodies: usize,
+            pending_body_bytes: usize,
+        ) -> Self {
+            Self {
+                connexion_id: connexion_id.to_string(),
+                taken_at: Instant::now(),
+                rtt: path_stats.map_or(Duration::ZERO, |path| path.rtt),
+                min_rtt: path_stats.and_then(|path| path.min_rtt),
+                rttvar: path_stats.map_or(Duration::ZERO, |path| path.rttvar),
+                cwnd: path_stats.map_or(0, |path| path.cwnd),
+                delivery_rate: path_stats.map_or(0, |path| path.delivery_rate),
+                packets_sent: stats.sent,
+                packets_received: stats.recv,
+                packets_lost: stats.lost,
+                retransmissions: stats.retrans,
+                bytes_sent: stats.sent_bytes,
+                bytes_received: stats.recv_bytes,
+                bytes_lost: stats.lost_bytes,
+                active_streams,
+                pending_bodies,
+                pending_body_bytes,
+            }
+        }
+        pub fn connexion_id(&self) -> &str {
+            self.connexion_id.as_str()
+        }
+        ///
+        ///When the client loop took this snapshot.
+        ///
+        pub fn taken_at(&self) -> Instant {
+            self.taken_at
+        }
+        ///
+        ///Smoothed round trip time of the active path.
+        ///
+        pub fn rtt(&self) -> Duration {
+            self.rtt
+        }
+        pub fn min_rtt(&self) -> Option<Duration> {
+            self.min_rtt
+        }
+        pub fn rttvar(&self) -> Duration {
+            self.rttvar
+        }
+        ///
+        ///Congestion window of the active path, in bytes.
+        ///
+        pub fn cwnd(&self) -> usize {
+            self.cwnd
+        }
+        ///
+        ///Delivery rate estimate of the active path, in bytes per second.
+        ///
+        pub fn delivery_rate(&self) -> u64 {
+            self.delivery_rate
+        }
+        pub fn packets_sent(&self) -> usize {
+            self.packets_sent
+        }
+        pub fn packets_received(&self) -> usize {
+            self.packets_received
+        }
+        pub fn packets_lost(&self) -> usize {
+            self.packets_lost
+        }
+        ///
+        ///Packets sent again after being declared lost.
+        ///
+        pub fn retransmissions(&self) -> usize {
+            self.retransmissions
+        }
+        pub fn bytes_sent(&self) -> u64 {
+            self.bytes_sent
+        }
+        pub fn bytes_received(&self) -> u64 {
+            self.bytes_received
+        }
+        pub fn bytes_lost(&self) -> u64 {
+            self.bytes_lost
+        }
+        ///
+        ///Request streams opened and not finished or reset yet.
+        ///
+        pub fn active_streams(&self) -> usize {
+            self.active_streams
+        }
+        ///
+        ///Body chunks waiting for stream capacity, and their size.
+        ///
+        pub fn pending_bodies(&self) -> usize {
+            self.pending_bodies
+        }
+        pub fn pending_body_bytes(&self) -> usize {
+            self.pending_body_bytes
+        }
+    }
+
+    ///
+    ///Carries the last ConnectionStats from the client loop : a snapshot replaces the one not
+    ///read yet.
+    ///
+    #[derive(Clone)]
+    pub struct StatsChannel {
+        channel: (
+            crossbeam::channel::Sender<ConnectionStats>,
+            crossbeam::channel::Receiver<ConnectionStats>,
+        ),
+    }
+
+    #[derive(Clone)]
+    pub struct StatsHead {
+        channel: (
+            crossbeam::channel::Sender<ConnectionStats>,
+            crossbeam::channel::Receiver<ConnectionStats>,
+        ),
+    }
+
+    #[derive(Clone)]
+    pub struct StatsQueue {
+        queue: crossbeam::channel::Receiver<ConnectionStats>,
+    }
+
+    impl StatsChannel {
+        pub fn new() -> Self {
+            Self {
+                channel: crossbeam::channel::bounded(1),
+            }
+        }
+        pub fn get_head(&self) -> StatsHead {
+            StatsHead {
+                channel: self.channel.clone(),
+            }
+        }
+        pub fn get_queue(&self) -> StatsQueue {
+            StatsQueue {
+                queue: self.channel.1.clone(),
+            }
+        }
+    }
+
+    impl StatsHead {
+        pub fn publish(&self, stats: ConnectionStats) {
+            // drop the snapshot nobody read
+            let _ = self.channel.1.try_recv();
+            let _ = self.channel.0.try_send(stats);
+        }
+    }
+
+    impl StatsQueue {
+        ///
+        ///The snapshot published since the last call, if any.
+        ///
+        pub fn pop_stats(&self) -> Option<ConnectionStats> {
+            self.queue.try_recv().ok()
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    #[test]
+    fn only_the_last_snapshot_is_kept() {
+        let channel = StatsChannel::new();
+        let head = channel.get_head();
+        let queue = channel.get_queue();
+        let stats = quiche::Stats::default();
+        head.publish(ConnectionStats::new("conn", &stats, None, 1, 0, 0));
+        head.publish(ConnectionStats::new("conn", &stats, None, 3, 2, 2400));
+
+        let last = queue.pop_stats().unwrap();
+        assert_eq!(last.active_streams(), 3);
+        assert_eq!(last.pending_body_bytes(), 2400);
+        assert!(queue.pop_stats().is_none());
+    }
+}
@@ -0,0 +1,187 @@
+pub use digest::{sha256_field, ContentDigest, DigestCheck};
+
+mod digest {
+    use base64::Engine;
+    use log::debug;
+    use quiche::h3::{self, NameValue};
+    use ring::digest::{Context, SHA256};
+
+    use crate::client_manager::{request_manager::RequestTrailers, response_error::ResponseError};
+
+    ///
+    ///`content-digest` field value (RFC 9530) for a SHA-256 digest : `sha-256=:<base64>:`.
+    ///
+    pub fn sha256_field(digest: &[u8]) -> String {
+        format!(
+            "sha-256=:{}:",
+            base64::engine::general_purpose::STANDARD.encode(digest)
+        )
+    }
+
+    ///
+    ///The SHA-256 digest announced in a `content-digest` value, None if the peer used another
+    ///algorithm only.
+    ///
+    fn parse_sha256(value: &[u8]) -> Option<Vec<u8>> {
+        String::from_utf8_lossy(value)
+            .split(',')
+            .find_map(|member| {
+                let (algorithm, digest) = member.trim().split_once('=')?;
+                if !algorithm.trim().eq_ignore_ascii_case("sha-256") {
+                    return None;
+                }
+                let digest = digest.trim().strip_prefix(':')?.strip_suffix(':')?;
+                base64::engine::general_purpose::STANDARD
+                    .decode(digest)
+                    .ok()
+            })
+    }
+
+    ///
+    ///Request trailers carrying the SHA-256 of the body as it is sent, after the trailers of
+    ///`inner` if the request had some.
+    ///
+    pub struct ContentDigest {
+        context: Context,
+        inner: Option<Box<dyn RequestTrailers>>,
+    }
+
+    impl ContentDigest {
+        pub fn new(inner: Option<Box<dyn RequestTrailers>>) -> Self {
+            Self {
+                context: Context::new(&SHA256),
+                inner,
+            }
+        }
+    }
+
+    impl RequestTrailers for ContentDigest {
+        fn on_body_chunk(&mut self, chunk: &[u8]) {
+            self.context.update(chunk);
+            if let Some(inner) = self.inner.as_mut() {
+                inner.on_body_chunk(chunk);
+            }
+        }
+        fn trailers(self: Box<Self>) -> Vec<(String, String)> {
+            let mut trailers = match self.inner {
+                Some(inner) => inner.trailers(),
+                None => vec![],
+            };
+            trailers.push((
+                "content-digest".to_string(),
+                sha256_field(self.context.finish().as_ref()),
+            ));
+            trailers
+        }
+    }
+
+    ///
+    ///SHA-256 of a response body, computed on the bytes as received (before any content
+    ///decoding) and checked against the `content-digest` of the peer.
+    ///
+    pub struct DigestCheck {
+        context: Context,
+    }
+
+    impl DigestCheck {
+        ///
+        ///A check for the response with these headers, None if the peer announces no
+        ///`content-digest`, neither as header nor as trailer.
+        ///
+        pub fn for_headers(headers: &[h3::Header]) -> Option<DigestCheck> {
+            let announced = headers.iter().any(|hdr| {
+                hdr.name() == b"content-digest"
+                    || (hdr.name() == b"trailer"
+                        && String::from_utf8_lossy(hdr.value())
+                            .split(',')
+                            .any(|name| name.trim().eq_ignore_ascii_case("content-digest")))
+            });
+            if announced {
+                Some(DigestCheck {
+                    context: Context::new(&SHA256),
+                })
+            } else {
+                None
+            }
+        }
+        pub fn update(&mut self, packet: &[u8]) {
+            self.context.update(packet);
+        }
+        ///
+        ///Compare with the `content-digest` of the trailers, or of the headers. A response
+        ///whose digest uses another algorithm is not checked.
+        ///
+        pub fn verify(
+            self,
+            headers: &[h3::Header],
+            trailers: &[h3::Header],
+        ) -> Result<(), ResponseError> {
+            let Some(field) = trailers
+                .iter()
+                .chain(headers.iter())
+                .find(|hdr| hdr.name() == b"content-digest")
+            else {
+                debug!("content-digest announced in trailer but not received");
+                return Ok(());
+            };
+            let Some(expected) = parse_sha256(field.value()) else {
+                debug!(
+                    "No sha-256 in content-digest [{}], body not verified",
+                    String::from_utf8_lossy(field.value())
+                );
+                return Ok(());
+            };
+            let received = self.context.finish();
+            if received.as_ref() == expected.as_slice() {
+                Ok(())
+            } else {
+                Err(ResponseError::DigestMismatch {
+                    expected: String::from_utf8_lossy(field.value()).to_string(),
+                    received: sha256_field(received.as_ref()),
+                })
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use quiche::h3;
+
+    use crate::client_manager::{request_manager::RequestTrailers, response_error::ResponseError};
+
+    #[test]
+    fn digest_trailer_and_verification() {
+        let mut content_digest: Box<dyn RequestTrailers> = Box::new(ContentDigest::new(Some(
+            Box::new(vec![("x-upload-id".to_string(), "7".to_string())]),
+        )));
+        content_digest.on_body_chunk(b"hello ");
+        content_digest.on_body_chunk(b"world");
+        let trailers = content_digest.trailers();
+        assert_eq!(trailers[0].0, "x-upload-id");
+        assert_eq!(
+            trailers[1].1,
+            "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:"
+        );
+
+        let headers = vec![
+            h3::Header::new(b":status", b"200"),
+            h3::Header::new(b"trailer", b"content-digest"),
+        ];
+        let mut check = DigestCheck::for_headers(&headers).unwrap();
+        check.update(b"hello world");
+        let valid = vec![h3::Header::new(b"content-digest", trailers[1].1.as_bytes())];
+        assert_eq!(check.verify(&headers, &valid), Ok(()));
+
+        let mut corrupted = DigestCheck::for_headers(&headers).unwrap();
+        corrupted.update(b"hello w0rld");
+        assert!(matches!(
+            corrupted.verify(&headers, &valid),
+            Err(ResponseError::DigestMismatch { .. })
+        ));
+
+        assert!(DigestCheck::for_headers(&[h3::Header::new(b":status", b"200")]).is_none());
+    }
+}
@@ -0,0 +1,209 @@
+pub use decoders::ContentDecoder;
+pub use encoders::{compress_bytes, compress_reader};
+pub use encoding_types::{Encoding, ACCEPT_ENCODING};
+
+mod encoding_types {
+    ///
+    ///Value of the `accept-encoding` header sent when response decompression is enabled.
+    ///
+    pub const ACCEPT_ENCODING: &str = "gzip, br, zstd";
+
+    ///
+    /// Content codings the client can apply to request bodies (`content-encoding`).
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Encoding {
+        Gzip,
+        Brotli,
+        Zstd,
+    }
+
+    impl Encoding {
+        ///
+        ///Token used in `content-encoding` / `accept-encoding` header values.
+        ///
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Self::Gzip => "gzip",
+                Self::Brotli => "br",
+                Self::Zstd => "zstd",
+            }
+        }
+        ///
+        ///Parse a single content coding token, case insensitive.
+        ///
+        pub fn parse(token: &[u8]) -> Option<Encoding> {
+            match String::from_utf8_lossy(token)
+                .trim()
+                .to_ascii_lowercase()
+                .as_str()
+            {
+                "gzip" | "x-gzip" => Some(Self::Gzip),
+                "br" => Some(Self::Brotli),
+                "zstd" => Some(Self::Zstd),
+                _ => None,
+            }
+        }
+    }
+}
+
+mod encoders {
+    use std::io::{Cursor, Read};
+
+    use super::Encoding;
+
+    const BROTLI_BUFFER_SIZE: usize = 4096;
+    const BROTLI_QUALITY: u32 = 5;
+    const BROTLI_WINDOW: u32 = 22;
+
+    ///
+    ///Wrap a reader in a streaming encoder : compressed bytes are produced while the request
+    ///body is read chunk by chunk, so the whole payload is never held in memory.
+    ///
+    pub fn compress_reader(
+        reader: Box<dyn Read + Send + 'static>,
+        encoding: Encoding,
+    ) -> Result<Box<dyn Read + Send + 'static>, std::io::Error> {
+        let encoder: Box<dyn Read + Send + 'static> = match encoding {
+            Encoding::Gzip => Box::new(flate2::read::GzEncoder::new(
+                reader,
+                flate2::Compression::default(),
+            )),
+            Encoding::Brotli => Box::new(brotli::CompressorReader::new(
+                reader,
+                BROTLI_BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            )),
+            Encoding::Zstd => Box::new(zstd::stream::read::Encoder::new(
+                reader,
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+            )?),
+        };
+        Ok(encoder)
+    }
+
+    ///
+    ///Compress an in-memory payload at once, so its compressed length can still be announced.
+    ///
+    pub fn compress_bytes(data: Vec<u8>, encoding: Encoding) -> Result<Vec<u8>, std::io::Error> {
+        let mut compressed = vec![];
+        compress_reader(Box::new(Cursor::new(data)), encoding)?.read_to_end(&mut compressed)?;
+        Ok(compressed)
+    }
+}
+
+mod decoders {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use super::Encoding;
+
+    const BROTLI_BUFFER_SIZE: usize = 4096;
+
+    ///
+    ///Collects what the decoders write, so decoded bytes can be taken after each packet.
+    ///
+    #[derive(Clone)]
+    struct DecodedSink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for DecodedSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    enum DecoderInner {
+        Gzip(flate2::write::GzDecoder<DecodedSink>),
+        Brotli(brotli::DecompressorWriter<DecodedSink>),
+        Zstd(zstd::stream::write::Decoder<'static, DecodedSink>),
+    }
+
+    ///
+    ///Streaming decoder for a response body. Packets are decoded as they arrive from the peer.
+    ///
+    pub struct ContentDecoder {
+        encoding: Encoding,
+        sink: DecodedSink,
+        inner: DecoderInner,
+    }
+
+    impl ContentDecoder {
+        pub fn new(encoding: Encoding) -> Result<Self, std::io::Error> {
+            let sink = DecodedSink(Arc::new(Mutex::new(vec![])));
+            let inner = match encoding {
+                Encoding::Gzip => DecoderInner::Gzip(flate2::write::GzDecoder::new(sink.clone())),
+                Encoding::Brotli => DecoderInner::Brotli(brotli::DecompressorWriter::new(
+                    sink.clone(),
+                    BROTLI_BUFFER_SIZE,
+                )),
+                Encoding::Zstd => {
+                    DecoderInner::Zstd(zstd::stream::write::Decoder::new(sink.clone())?)
+                }
+            };
+            Ok(Self {
+                encoding,
+                sink,
+                inner,
+            })
+        }
+        pub fn encoding(&self) -> Encoding {
+            self.encoding
+        }
+        ///
+        ///Feed an encoded packet, return the bytes decoded so far.
+        ///
+        pub fn decode(&mut self, packet: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+            match &mut self.inner {
+                DecoderInner::Gzip(decoder) => decoder.write_all(packet)?,
+                DecoderInner::Brotli(decoder) => decoder.write_all(packet)?,
+                DecoderInner::Zstd(decoder) => decoder.write_all(packet)?,
+            }
+            Ok(self.take_decoded())
+        }
+        ///
+        ///End of body : flush the decoder and return the remaining decoded bytes.
+        ///
+        pub fn finish(&mut self) -> Result<Vec<u8>, std::io::Error> {
+            match &mut self.inner {
+                DecoderInner::Gzip(decoder) => decoder.try_finish()?,
+                DecoderInner::Brotli(decoder) => decoder.flush()?,
+                DecoderInner::Zstd(decoder) => decoder.flush()?,
+            }
+            Ok(self.take_decoded())
+        }
+        fn take_decoded(&self) -> Vec<u8> {
+            std::mem::take(&mut *self.sink.0.lock().unwrap())
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    #[test]
+    fn compress_then_decode_by_packets() {
+        let payload = b"{\"faces\": \"quic\"}".repeat(500);
+
+        for encoding in [Encoding::Gzip, Encoding::Brotli, Encoding::Zstd] {
+            let compressed = compress_bytes(payload.clone(), encoding).unwrap();
+            assert!(compressed.len() < payload.len());
+
+            let mut decoder = ContentDecoder::new(encoding).unwrap();
+            let mut decoded = vec![];
+            for packet in compressed.chunks(100) {
+                decoded.extend(decoder.decode(packet).unwrap());
+            }
+            decoded.extend(decoder.finish().unwrap());
+
+            assert_eq!(decoded, payload);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+pub use cookie_store::CookieJar;
+
+mod cookie_entry {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserialize, Serialize};
+
+    pub fn now_as_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0)
+    }
+
+    ///
+    ///A cookie received with `set-cookie`, with the scope it applies to.
+    ///
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Cookie {
+        pub name: String,
+        pub value: String,
+        pub domain: String,
+        pub host_only: bool,
+        pub path: String,
+        pub expires: Option<u64>,
+        pub secure: bool,
+    }
+
+    impl Cookie {
+        ///
+        ///Parse a `set-cookie` header value received for `request_host` / `request_path`.
+        ///Returns None if the cookie is malformed or its domain does not cover the request host.
+        ///
+        pub fn parse(set_cookie: &str, request_host: &str, request_path: &str) -> Option<Cookie> {
+            let mut parts = set_cookie.split(';');
+            let (name, value) = parts.next()?.split_once('=')?;
+            let name = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let request_host = request_host.to_ascii_lowercase();
+            let mut cookie = Cookie {
+                name: name.to_string(),
+                value: value.trim().trim_matches('"').to_string(),
+                domain: request_host.clone(),
+                host_only: true,
+                path: default_path(request_path),
+                expires: None,
+                secure: false,
+            };
+            let mut max_age: Option<i64> = None;
+
+            for attribute in parts {
+                let (attr_name, attr_value) = match attribute.split_once('=') {
+                    Some((attr_name, attr_value)) => (attr_name.trim(), attr_value.trim()),
+                    None => (attribute.trim(), ""),
+                };
+                match attr_name.to_ascii_lowercase().as_str() {
+                    "domain" if !attr_value.is_empty() => {
+                        let domain = attr_value.trim_start_matches('.').to_ascii_lowercase();
+                        if !domain_match(&request_host, &domain) {
+                            return None;
+                        }
+                        // a public suffix (com, co.uk...) would share the cookie with every
+                        // site under it : only kept as a host only cookie of that very host
+                        if psl::suffix_str(&domain) == Some(domain.as_str()) {
+                            if domain != request_host {
+                                return None;
+                            }
+                            continue;
+                        }
+                        cookie.host_only = false;
+                        cookie.domain = domain;
+                    }
+                    "path" if attr_value.starts_with('/') => cookie.path = attr_value.to_string(),
+                    "max-age" => max_age = attr_value.parse::<i64>().ok().or(max_age),
+                    "expires" => {
+                        if let Ok(date) = httpdate::parse_http_date(attr_value) {
+                            cookie.expires = Some(
+                                date.duration_since(UNIX_EPOCH)
+                                    .map(|since| since.as_secs())
+                                    .unwrap_or(0),
+                            );
+                        }
+                    }
+                    "secure" => cookie.secure = true,
+                    _ => {}
+                }
+            }
+            // Max-Age takes precedence over Expires
+            if let Some(max_age) = max_age {
+                cookie.expires = Some(if max_age <= 0 {
+                    0
+                } else {
+                    now_as_secs().saturating_add(max_age as u64)
+                });
+            }
+            Some(cookie)
+        }
+        pub fn is_expired(&self, now: u64) -> bool {
+            self.expires.map_or(false, |expires| expires <= now)
+        }
+        ///
+        ///The cookie is sent on a request to `host` / `path`.
+        ///
+        pub fn matches(&self, host: &str, path: &str, secure_channel: bool) -> bool {
+            let host = host.to_ascii_lowercase();
+            let domain_ok = if self.host_only {
+                host == self.domain
+            } else {
+                domain_match(&host, &self.domain)
+            };
+            domain_ok && path_match(path, &self.path) && (!self.secure || secure_channel)
+        }
+        pub fn same_scope(&self, other: &Cookie) -> bool {
+            self.name == other.name && self.domain == other.domain && self.path == other.path
+        }
+    }
+
+    ///
+    ///`host` is `domain` or one of its sub domains. IP addresses only match themselves.
+    ///
+    pub fn domain_match(host: &str, domain: &str) -> bool {
+        if host == domain {
+            return true;
+        }
+        host.parse::<std::net::IpAddr>().is_err()
+            && host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+    }
+
+    pub fn path_match(request_path: &str, cookie_path: &str) -> bool {
+        let request_path = request_path.split('?').next().unwrap_or("/");
+        request_path == cookie_path
+            || (request_path.starts_with(cookie_path)
+                && (cookie_path.ends_with('/')
+                    || request_path[cookie_path.len()..].starts_with('/')))
+    }
+
+    ///
+    ///Directory of the request path, used when `set-cookie` has no Path attribute.
+    ///
+    fn default_path(request_path: &str) -> String {
+        let request_path = request_path.split('?').next().unwrap_or("");
+        match request_path.rfind('/') {
+            Some(0) | None => "/".to_string(),
+            Some(last_slash) => request_path[..last_slash].to_string(),
+        }
+    }
+}
+
+mod cookie_store {
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use log::debug;
+    use quiche::h3::NameValue;
+
+    use crate::client_manager::{
+        request_manager::Http3RequestBuilder, response_manager::WaitPeerResponse,
+    };
+
+    use super::cookie_entry::{now_as_secs, Cookie};
+
+    ///
+    ///Keeps the cookies set by the peer and sends them back on the matching requests.
+    ///Clones share the same cookies.
+    ///
+    #[derive(Clone, Default)]
+    pub struct CookieJar {
+        cookies: Arc<Mutex<Vec<Cookie>>>,
+    }
+
+    impl CookieJar {
+        pub fn new() -> Self {
+            Self {
+                cookies: Arc::new(Mutex::new(vec![])),
+            }
+        }
+        ///
+        ///Restore a jar written with save().
+        ///
+        pub fn load(file_path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+            let content = std::fs::read(file_path)?;
+            let now = now_as_secs();
+            let cookies = serde_json::from_slice::<Vec<Cookie>>(&content)?
+                .into_iter()
+                .filter(|cookie| !cookie.is_expired(now))
+                .collect();
+            Ok(Self {
+                cookies: Arc::new(Mutex::new(cookies)),
+            })
+        }
+        ///
+        ///Write the cookies that are not expired to `file_path`, as json.
+        ///
+        pub fn save(&self, file_path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+            self.remove_expired();
+            let content = serde_json::to_vec_pretty(&*self.cookies.lock().unwrap())?;
+            std::fs::write(file_path, content)
+        }
+        pub fn clear(&self) {
+            self.cookies.lock().unwrap().clear();
+        }
+        ///
+        ///Store a cookie from a `set-cookie` value received for `request_host` / `request_path`.
+        ///An already expired cookie removes the one it replaces.
+        ///
+        pub fn set_cookie(&self, set_cookie: &str, request_host: &str, request_path: &str) {
+            let Some(cookie) = Cookie::parse(set_cookie, request_host, request_path) else {
+                debug!(
+                    "Ignored set-cookie [{}] from [{}]",
+                    set_cookie, request_host
+                );
+                return;
+            };
+            let mut cookies = self.cookies.lock().unwrap();
+            cookies.retain(|stored| !stored.same_scope(&cookie));
+            if !cookie.is_expired(now_as_secs()) {
+                cookies.push(cookie);
+            }
+        }
+        ///
+        ///Value of the `cookie` header for a request to `host` / `path`, most specific path
+        ///first.
+        ///
+        pub fn cookie_header(
+            &self,
+            host: &str,
+            path: &str,
+            secure_channel: bool,
+        ) -> Option<String> {
+            self.remove_expired();
+            let cookies = self.cookies.lock().unwrap();
+            let mut matching: Vec<&Cookie> = cookies
+                .iter()
+                .filter(|cookie| cookie.matches(host, path, secure_channel))
+                .collect();
+            if matching.is_empty() {
+                return None;
+            }
+            matching.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+            Some(
+                matching
+                    .iter()
+                    .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                    .collect::<Vec<String>>()
+                    .join("; "),
+            )
+        }
+        fn remove_expired(&self) {
+            let now = now_as_secs();
+            self.cookies
+                .lock()
+                .unwrap()
+                .retain(|cookie| !cookie.is_expired(now));
+        }
+
+        ///
+        ///Add the matching cookies to the request, send it with `dispatch` and store the
+        ///cookies of the response. A `cookie` header set by the user is left as is.
+        ///
+        pub fn send_through(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            dispatch: impl FnOnce(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()>,
+        ) -> Result<WaitPeerResponse, ()> {
+            let Some(host) = http3_request_builder.host() else {
+                return dispatch(http3_request_builder);
+            };
+            let path = http3_request_builder.get_path().unwrap_or("/".to_string());
+
+            // requests always go over QUIC, so the channel is secure
+            if http3_request_builder.header("cookie").is_none() {
+                if let Some(cookie_header) = self.cookie_header(&host, &path, true) {
+                    http3_request_builder.set_header("cookie".to_string(), cookie_header);
+                }
+            }
+
+            let cookie_jar = self.clone();
+            Ok(dispatch(http3_request_builder)?.intercept(move |response| {
+                let response = response?;
+                for hdr in response
+                    .headers()
+                    .iter()
+                    .filter(|hdr| hdr.name() == b"set-cookie")
+                {
+                    cookie_jar.set_cookie(&String::from_utf8_lossy(hdr.value()), &host, &path);
+                }
+                Ok(response)
+            }))
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use super::cookie_entry::Cookie;
+
+    #[test]
+    fn parse_set_cookie_attributes() {
+        let cookie = Cookie::parse(
+            "session=abc; Domain=.example.org; Path=/api; Max-Age=60; Secure; HttpOnly",
+            "www.example.org",
+            "/login",
+        )
+        .unwrap();
+        assert_eq!(cookie.domain, "example.org");
+        assert!(!cookie.host_only);
+        assert_eq!(cookie.path, "/api");
+        assert!(cookie.secure);
+        assert!(cookie.matches("api.example.org", "/api/users", true));
+        assert!(!cookie.matches("api.example.org", "/apis", true));
+        assert!(!cookie.matches("api.example.org", "/api", false));
+
+        let default_scope = Cookie::parse("id=1", "127.0.0.1", "/users/login?next=1").unwrap();
+        assert!(default_scope.host_only);
+        assert_eq!(default_scope.path, "/users");
+        assert_eq!(default_scope.expires, None);
+
+        assert!(Cookie::parse("id=1; Domain=other.org", "example.org", "/").is_none());
+        assert!(Cookie::parse("id=1; Domain=co.uk", "shop.co.uk", "/").is_none());
+        assert!(Cookie::parse("id=1; Domain=com", "example.com", "/").is_none());
+        assert!(
+            Cookie::parse("id=1; Domain=localhost", "localhost", "/")
+                .unwrap()
+                .host_only
+        );
+        assert!(Cookie::parse("=1", "example.org", "/").is_none());
+    }
+
+    #[test]
+    fn jar_sends_matching_cookies_and_persists() {
+        let jar = CookieJar::new();
+        jar.set_cookie("a=1; Path=/", "127.0.0.1", "/");
+        jar.set_cookie("b=2; Path=/files", "127.0.0.1", "/files/up");
+        jar.set_cookie(
+            "old=3; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            "127.0.0.1",
+            "/",
+        );
+
+        assert_eq!(
+            jar.cookie_header("127.0.0.1", "/files/1", true),
+            Some("b=2; a=1".to_string())
+        );
+        assert_eq!(
+            jar.cookie_header("127.0.0.1", "/", true),
+            Some("a=1".to_string())
+        );
+        assert_eq!(jar.cookie_header("127.0.0.2", "/", true), None);
+
+        jar.set_cookie("a=1; Path=/; Max-Age=0", "127.0.0.1", "/");
+        assert_eq!(jar.cookie_header("127.0.0.1", "/", true), None);
+
+        let file_path = std::env::temp_dir().join(format!(
+            "faces_quic_client_cookie_jar_{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        jar.save(&file_path).unwrap();
+        let restored = CookieJar::load(&file_path).unwrap();
+        std::fs::remove_file(&file_path).unwrap();
+        assert_eq!(
+            restored.cookie_header("127.0.0.1", "/files", true),
+            Some("b=2".to_string())
+        );
+    }
+}
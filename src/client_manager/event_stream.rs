@@ -0,0 +1,240 @@
+pub use sse::{SseEvent, SseParser};
+
+mod sse {
+    use std::time::Duration;
+
+    const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+    ///
+    ///One event of a `text/event-stream` response.
+    ///
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SseEvent {
+        id: Option<String>,
+        event: String,
+        data: String,
+        retry: Option<Duration>,
+    }
+
+    impl SseEvent {
+        ///
+        ///Last event id of the stream when this event was dispatched, sent back in
+        ///`last-event-id` on reconnection.
+        ///
+        pub fn id(&self) -> Option<&str> {
+            self.id.as_deref()
+        }
+        ///
+        ///Event type, `message` when the peer didn't name it.
+        ///
+        pub fn event(&self) -> &str {
+            self.event.as_str()
+        }
+        pub fn data(&self) -> &str {
+            self.data.as_str()
+        }
+        ///
+        ///Reconnection delay requested by the peer in this event.
+        ///
+        pub fn retry(&self) -> Option<Duration> {
+            self.retry
+        }
+    }
+
+    ///
+    ///Incremental `text/event-stream` parser : body packets are pushed as they arrive, lines
+    ///and events can be split across packets. The last event id and the reconnection delay
+    ///are kept across reconnections.
+    ///
+    #[derive(Debug)]
+    pub struct SseParser {
+        buffer: Vec<u8>,
+        stream_start: bool,
+        event: Option<String>,
+        data: Option<String>,
+        event_retry: Option<Duration>,
+        id: Option<String>,
+        last_event_id: Option<String>,
+        retry: Duration,
+    }
+
+    impl Default for SseParser {
+        fn default() -> Self {
+            Self {
+                buffer: vec![],
+                stream_start: true,
+                event: None,
+                data: None,
+                event_retry: None,
+                id: None,
+                last_event_id: None,
+                retry: DEFAULT_RETRY,
+            }
+        }
+    }
+
+    impl SseParser {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        ///
+        ///Id of the last dispatched event, None until the peer sets one or after an empty
+        ///`id:`.
+        ///
+        pub fn last_event_id(&self) -> Option<&str> {
+            self.last_event_id.as_deref()
+        }
+        ///
+        ///Delay before reconnecting, 3 seconds until the peer sets one with `retry:`.
+        ///
+        pub fn retry(&self) -> Duration {
+            self.retry
+        }
+        ///
+        ///Forget the incomplete line and event of the previous response, before a
+        ///reconnection. An `id:` of the incomplete event is not kept.
+        ///
+        pub fn reset_stream(&mut self) {
+            self.id = self.last_event_id.clone();
+            self.buffer.clear();
+            self.stream_start = true;
+            self.event = None;
+            self.data = None;
+            self.event_retry = None;
+        }
+
+        ///
+        ///Parse a body packet, returning the events it completes.
+        ///
+        pub fn push(&mut self, packet: &[u8]) -> Vec<SseEvent> {
+            self.buffer.extend_from_slice(packet);
+            if self.stream_start {
+                if self.buffer.len() < 3 && b"\xEF\xBB\xBF".starts_with(&self.buffer) {
+                    return vec![];
+                }
+                if self.buffer.starts_with(b"\xEF\xBB\xBF") {
+                    self.buffer.drain(..3);
+                }
+                self.stream_start = false;
+            }
+
+            let mut events = vec![];
+            let mut line_start = 0;
+            let mut i = 0;
+            while i < self.buffer.len() {
+                let line_end = match self.buffer[i] {
+                    b'\n' => i + 1,
+                    b'\r' if i + 1 == self.buffer.len() => break, // \r\n may be split
+                    b'\r' if self.buffer[i + 1] == b'\n' => i + 2,
+                    b'\r' => i + 1,
+                    _ => {
+                        i += 1;
+                        continue;
+                    }
+                };
+                let line = String::from_utf8_lossy(&self.buffer[line_start..i]).to_string();
+                if let Some(event) = self.process_line(&line) {
+                    events.push(event);
+                }
+                line_start = line_end;
+                i = line_end;
+            }
+            self.buffer.drain(..line_start);
+            events
+        }
+
+        fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+            if line.is_empty() {
+                return self.dispatch();
+            }
+            if line.starts_with(':') {
+                return None;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => {
+                    let data = self.data.get_or_insert_with(String::new);
+                    data.push_str(value);
+                    data.push('\n');
+                }
+                "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+                "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                    if let Ok(millis) = value.parse::<u64>() {
+                        self.retry = Duration::from_millis(millis);
+                        self.event_retry = Some(self.retry);
+                    }
+                }
+                _ => {}
+            }
+            None
+        }
+
+        ///
+        ///End of an event : its id becomes the last event id even when it has no data.
+        ///
+        fn dispatch(&mut self) -> Option<SseEvent> {
+            self.last_event_id = self.id.clone().filter(|id| !id.is_empty());
+            let event = self.event.take();
+            let retry = self.event_retry.take();
+            let mut data = self.data.take()?;
+            if data.ends_with('\n') {
+                data.pop();
+            }
+            Some(SseEvent {
+                id: self.last_event_id.clone(),
+                event: event
+                    .filter(|event| !event.is_empty())
+                    .unwrap_or("message".to_string()),
+                data,
+                retry,
+            })
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn parse_events_split_across_packets() {
+        let mut parser = SseParser::new();
+        let mut events = vec![];
+        for packet in [
+            &b"\xEF\xBB\xBF: keep-alive\r\nretry: 1500\r"[..],
+            &b"\nevent: price\ndata: {\"eur\""[..],
+            &b": 12}\nid: 42\n\ndata: first\ndata:second\n"[..],
+            &b"\nid\ndata: anonymous\n\nevent: ignored\n\n"[..],
+        ] {
+            events.extend(parser.push(packet));
+        }
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].event(), "price");
+        assert_eq!(events[0].data(), "{\"eur\": 12}");
+        assert_eq!(events[0].id(), Some("42"));
+        assert_eq!(events[0].retry(), Some(Duration::from_millis(1500)));
+        assert_eq!(events[1].event(), "message");
+        assert_eq!(events[1].data(), "first\nsecond");
+        assert_eq!(events[1].id(), Some("42"));
+        assert_eq!(events[1].retry(), None);
+        assert_eq!(events[2].id(), None);
+        assert_eq!(parser.last_event_id(), None);
+        assert_eq!(parser.retry(), Duration::from_millis(1500));
+
+        assert!(parser.push(b"id: 43\n\n").is_empty());
+        assert_eq!(parser.last_event_id(), Some("43"));
+        parser.push(b"id: 44\ndata: incomplete");
+        assert_eq!(parser.last_event_id(), Some("43"));
+        parser.reset_stream();
+        assert_eq!(parser.last_event_id(), Some("43"));
+        assert!(parser.push(b"\n\n").is_empty());
+        assert_eq!(parser.last_event_id(), Some("43"));
+    }
+}
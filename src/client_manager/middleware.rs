@@ -0,0 +1,176 @@
+pub use middleware_chain::{Middleware, MiddlewareChain};
+
+mod middleware_chain {
+    use std::sync::{Arc, Mutex};
+
+    use crate::client_manager::{
+        request_manager::Http3RequestBuilder,
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    ///
+    ///Hook on every request sent by the manager : inject headers, sign, log, rewrite the
+    ///responses...
+    ///
+    ///Middlewares are the outermost layer : a request goes through them once, before the
+    ///redirections, retries, authentication, cookies and cache, and they get its final
+    ///response.
+    ///
+    pub trait Middleware: Send + Sync + 'static {
+        ///
+        ///Called before the request is built. Returning a response answers the request without
+        ///sending it : the next middlewares are skipped.
+        ///
+        fn on_request(
+            &self,
+            _http3_request_builder: &mut Http3RequestBuilder,
+        ) -> Option<CompletedResponse> {
+            None
+        }
+        ///
+        ///Called with the response before it reaches WaitPeerResponse.
+        ///
+        fn on_response(&self, response: CompletedResponse) -> CompletedResponse {
+            response
+        }
+    }
+
+    ///
+    ///Middlewares in registration order. Requests go through them first to last, responses
+    ///come back last to first.
+    ///
+    #[derive(Clone, Default)]
+    pub struct MiddlewareChain {
+        middlewares: Arc<Mutex<Vec<Arc<dyn Middleware>>>>,
+    }
+
+    impl MiddlewareChain {
+        pub fn new() -> Self {
+            Self {
+                middlewares: Arc::new(Mutex::new(vec![])),
+            }
+        }
+        pub fn push(&self, middleware: impl Middleware) {
+            self.middlewares.lock().unwrap().push(Arc::new(middleware));
+        }
+        pub fn is_empty(&self) -> bool {
+            self.middlewares.lock().unwrap().is_empty()
+        }
+
+        pub fn send_through(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            dispatch: impl FnOnce(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()>,
+        ) -> Result<WaitPeerResponse, ()> {
+            let middlewares = self.middlewares.lock().unwrap().clone();
+
+            for (i, middleware) in middlewares.iter().enumerate() {
+                if let Some(response) = middleware.on_request(http3_request_builder) {
+                    return Ok(WaitPeerResponse::ready(respond_through(
+                        &middlewares[..i],
+                        response,
+                    )));
+                }
+            }
+
+            Ok(dispatch(http3_request_builder)?
+                .intercept(move |response| Ok(respond_through(&middlewares, response?))))
+        }
+    }
+
+    fn respond_through(
+        middlewares: &[Arc<dyn Middleware>],
+        response: CompletedResponse,
+    ) -> CompletedResponse {
+        middlewares
+            .iter()
+            .rev()
+            .fold(response, |response, middleware| {
+                middleware.on_response(response)
+            })
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use quiche::h3;
+    use uuid::Uuid;
+
+    use crate::client_manager::{
+        request_manager::{Http3RequestBuilder, Http3RequestPrep},
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    struct Trace(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl Middleware for Trace {
+        fn on_request(&self, builder: &mut Http3RequestBuilder) -> Option<CompletedResponse> {
+            self.1.lock().unwrap().push(format!("request {}", self.0));
+            builder.set_header("x-trace".to_string(), self.0.to_string());
+            None
+        }
+        fn on_response(&self, response: CompletedResponse) -> CompletedResponse {
+            self.1.lock().unwrap().push(format!("response {}", self.0));
+            response
+        }
+    }
+
+    struct Offline;
+
+    impl Middleware for Offline {
+        fn on_request(&self, _builder: &mut Http3RequestBuilder) -> Option<CompletedResponse> {
+            Some(CompletedResponse::new(
+                0,
+                vec![h3::Header::new(b":status", b"503")],
+                b"offline".to_vec(),
+            ))
+        }
+    }
+
+    #[test]
+    fn middlewares_run_in_order_and_short_circuit() {
+        let trace = Arc::new(Mutex::new(vec![]));
+        let chain = MiddlewareChain::new();
+        chain.push(Trace("a", trace.clone()));
+        chain.push(Trace("b", trace.clone()));
+
+        let mut builder =
+            Http3RequestPrep::new(Some("127.0.0.1:3000".parse().unwrap()), Uuid::new_v4());
+        builder.get("/".to_string());
+        let response = chain
+            .send_through(&mut builder, |builder| {
+                assert_eq!(builder.header("x-trace"), Some("a"));
+                Ok(WaitPeerResponse::ready(CompletedResponse::new(
+                    0,
+                    vec![h3::Header::new(b":status", b"200")],
+                    vec![],
+                )))
+            })
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(
+            *trace.lock().unwrap(),
+            vec!["request a", "request b", "response b", "response a"]
+        );
+
+        trace.lock().unwrap().clear();
+        chain.push(Offline);
+        chain.push(Trace("c", trace.clone()));
+        let response = chain
+            .send_through(&mut builder, |_| panic!("short circuited request was sent"))
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.as_data(), b"offline");
+        assert_eq!(
+            *trace.lock().unwrap(),
+            vec!["request a", "request b", "response b", "response a"]
+        );
+    }
+}
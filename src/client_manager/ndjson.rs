@@ -0,0 +1,137 @@
+pub use json_lines::{ndjson_sink, split_lines, LineSink, NdjsonItems};
+
+mod json_lines {
+    use std::sync::Arc;
+
+    use serde::de::DeserializeOwned;
+
+    use crate::client_manager::{
+        response_error::ResponseError,
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    ///
+    ///Receives each line of a response body as soon as it is complete, without its line
+    ///ending.
+    ///
+    pub type LineSink = Arc<dyn Fn(&[u8]) + Send + Sync + 'static>;
+
+    ///
+    ///Hand the complete lines at the start of `buffer` to `sink` and remove them. With
+    ///`flush`, what remains after the last line ending is handed over as a last line.
+    ///
+    pub fn split_lines(buffer: &mut Vec<u8>, mut sink: impl FnMut(&[u8]), flush: bool) {
+        let consumed = match buffer.iter().rposition(|b| *b == b'\n') {
+            Some(last_line_end) => {
+                for line in buffer[..last_line_end].split(|b| *b == b'\n') {
+                    sink(line.strip_suffix(b"\r").unwrap_or(line));
+                }
+                last_line_end + 1
+            }
+            None => 0,
+        };
+        buffer.drain(..consumed);
+        if flush && !buffer.is_empty() {
+            sink(buffer.strip_suffix(b"\r").unwrap_or(buffer));
+            buffer.clear();
+        }
+    }
+
+    ///
+    ///A LineSink deserializing each non blank line into `T`.
+    ///
+    pub fn ndjson_sink<T: DeserializeOwned>(
+        on_item: impl Fn(Result<T, serde_json::Error>) + Send + Sync + 'static,
+    ) -> LineSink {
+        Arc::new(move |line: &[u8]| {
+            if line.iter().all(|b| b.is_ascii_whitespace()) {
+                return;
+            }
+            on_item(serde_json::from_slice::<T>(line));
+        })
+    }
+
+    ///
+    ///Items of a newline-delimited JSON response, in reception order. The iteration ends with
+    ///the response.
+    ///
+    pub struct NdjsonItems<T> {
+        items: crossbeam::channel::Receiver<Result<T, serde_json::Error>>,
+        peer_response: WaitPeerResponse,
+    }
+
+    impl<T> NdjsonItems<T> {
+        pub fn new(
+            items: crossbeam::channel::Receiver<Result<T, serde_json::Error>>,
+            peer_response: WaitPeerResponse,
+        ) -> Self {
+            Self {
+                items,
+                peer_response,
+            }
+        }
+        ///
+        ///The response, without the lines already handed over : its status, headers and
+        ///trailers, or the body of an error status.
+        ///
+        pub fn wait_response(&self) -> Result<CompletedResponse, ResponseError> {
+            self.peer_response.wait_response()
+        }
+    }
+
+    impl<T> Iterator for NdjsonItems<T> {
+        type Item = Result<T, serde_json::Error>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.items.recv().ok()
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct LogLine {
+        level: String,
+        msg: String,
+    }
+
+    #[test]
+    fn lines_split_across_packets() {
+        let items = Arc::new(Mutex::new(vec![]));
+        let received = items.clone();
+        let sink = ndjson_sink::<LogLine>(move |item| received.lock().unwrap().push(item));
+
+        let mut buffer = vec![];
+        for packet in [
+            &b"{\"level\":\"info\",\"msg\":\"sta"[..],
+            &b"rted\"}\r\n\n{\"level\":\"warn\","[..],
+            &b"\"msg\":\"slow\"}\nnot json\n{\"level\":\"info\",\"msg\":\"end\"}"[..],
+        ] {
+            buffer.extend_from_slice(packet);
+            split_lines(&mut buffer, sink.as_ref(), false);
+        }
+        assert_eq!(items.lock().unwrap().len(), 3);
+        split_lines(&mut buffer, sink.as_ref(), true);
+
+        let items = items.lock().unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(
+            items[0].as_ref().unwrap(),
+            &LogLine {
+                level: "info".to_string(),
+                msg: "started".to_string()
+            }
+        );
+        assert_eq!(items[1].as_ref().unwrap().msg, "slow");
+        assert!(items[2].is_err());
+        assert_eq!(items[3].as_ref().unwrap().msg, "end");
+        assert!(buffer.is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 pub use event_stream_types::{KeepAlive, StreamControlFlow, StreamEvent, StreamSub};
 pub use ping_emission::PingEmitter;
-pub use stream_builder::StreamBuilder;
+pub use stream_builder::{SseHandle, StreamBuilder};
 mod ping_emission {
     use std::{
         sync::{Arc, Mutex},
@@ -54,14 +54,27 @@ mod ping_emission {
 }
 
 mod event_stream_types {
-    use std::{sync::Arc, time::Duration};
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
     use quiche::h3;
 
+    use crate::client_manager::event_stream::{SseEvent, SseParser};
+
     #[derive(Clone)]
     pub enum StreamSub {
         UpStream(Arc<dyn Fn(StreamEvent, StreamControlFlow) + Send + Sync + 'static>),
         Downstream(Arc<dyn Fn(StreamEvent, StreamControlFlow) + Send + Sync + 'static>),
+        ///
+        ///`text/event-stream` body, parsed into SseEvents. The parser outlives a response to
+        ///keep the last event id for the reconnections.
+        ///
+        EventStream {
+            parser: Arc<Mutex<SseParser>>,
+            callback: Arc<dyn Fn(SseEvent, StreamControlFlow) + Send + Sync + 'static>,
+        },
         None, //Bidi,
     }
     impl StreamSub {
@@ -103,6 +116,7 @@ mod event_stream_types {
     }
     pub struct StreamControlFlow;
 
+    #[derive(Clone)]
     pub struct KeepAlive {
         duration: Duration,
     }
@@ -121,17 +135,57 @@ mod event_stream_types {
 mod stream_builder {
     use std::{
         collections::HashMap,
-        sync::{Arc, Mutex},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
     };
 
+    use log::{debug, warn};
+    use quiche::h3::NameValue;
     use uuid::Uuid;
 
     use crate::{
-        client_manager::request_manager::{self, Http3RequestBuilder},
+        client_manager::{
+            event_stream::{SseEvent, SseParser},
+            request_manager::{self, Http3RequestBuilder},
+            response_manager::CompletedResponse,
+            stream_framing::StreamFramer,
+        },
         my_log, Http3ClientManager,
     };
 
-    use super::{event_stream_types::KeepAlive, StreamControlFlow, StreamEvent};
+    use super::{event_stream_types::KeepAlive, StreamControlFlow, StreamEvent, StreamSub};
+
+    ///
+    ///Returned by StreamBuilder::open_sse() to stop the reconnections.
+    ///
+    #[derive(Clone)]
+    pub struct SseHandle {
+        closed: Arc<AtomicBool>,
+    }
+
+    impl SseHandle {
+        ///
+        ///No reconnection is made once the current response ends.
+        ///
+        pub fn close(&self) {
+            self.closed.store(true, Ordering::SeqCst);
+        }
+        pub fn is_closed(&self) -> bool {
+            self.closed.load(Ordering::SeqCst)
+        }
+    }
+
+    ///
+    ///A response worth reconnecting to : a 200 serving `text/event-stream`.
+    ///
+    fn is_event_stream(response: &CompletedResponse) -> bool {
+        response.http_status() == Some(200)
+            && response.headers().iter().any(|hdr| {
+                hdr.name() == b"content-type" && hdr.value().starts_with(b"text/event-stream")
+            })
+    }
 
     pub struct StreamBuilder {
         uuid: Uuid,
@@ -158,6 +212,16 @@ mod stream_builder {
             self.keep_alive = Some(KeepAlive::new(freq_as_sec));
             self
         }
+        ///
+        ///Cut the stream body into messages with `framer`, for peers that don't use the
+        ///stream_framer format (DefaultFramer).
+        ///
+        pub fn framer(&mut self, framer: impl StreamFramer) -> &mut Self {
+            if let Some(entry) = self.request_builder.lock().unwrap().get_mut(&self.uuid) {
+                entry.set_stream_framer(Box::new(framer));
+            }
+            self
+        }
 
         pub fn open(&self, cb: impl Fn(StreamEvent, StreamControlFlow) + Send + Sync + 'static) {
             let uuid = self.uuid;
@@ -179,5 +243,79 @@ mod stream_builder {
                 });
             };
         }
+
+        ///
+        ///Open a `text/event-stream` : each event reaches `cb` as soon as it is parsed. When the
+        ///stream ends or fails, the request is sent again after the `retry` delay of the peer,
+        ///with the `last-event-id` received. Reconnection stops when the handle is closed or
+        ///the peer no longer answers with an event stream (a 204...).
+        ///
+        pub fn open_sse(
+            &self,
+            cb: impl Fn(SseEvent, StreamControlFlow) + Send + Sync + 'static,
+        ) -> SseHandle {
+            let handle = SseHandle {
+                closed: Arc::new(AtomicBool::new(false)),
+            };
+            let Some(template) = self
+                .request_builder
+                .lock()
+                .unwrap()
+                .get_mut(&self.uuid)
+                .and_then(|entry| {
+                    entry.replace_header("accept", "text/event-stream".to_string());
+                    entry.replace_header("cache-control", "no-cache".to_string());
+                    entry.disable_decompression();
+                    entry.try_clone()
+                })
+            else {
+                warn!("No replayable request to open the event stream");
+                handle.close();
+                return handle;
+            };
+
+            let parser = Arc::new(Mutex::new(SseParser::new()));
+            let stream_sub = StreamSub::EventStream {
+                parser: parser.clone(),
+                callback: Arc::new(cb),
+            };
+            let request_manager = self.request_manager.clone();
+            let keep_alive = self.keep_alive.clone();
+            let sse_handle = handle.clone();
+
+            std::thread::spawn(move || {
+                while !sse_handle.is_closed() {
+                    let Some(mut request) = template.try_clone() else {
+                        break;
+                    };
+                    {
+                        let mut parser = parser.lock().unwrap();
+                        parser.reset_stream();
+                        if let Some(last_event_id) = parser.last_event_id() {
+                            request.replace_header("last-event-id", last_event_id.to_string());
+                        }
+                    }
+                    let response = request_manager
+                        .request_manager_ref()
+                        .new_stream_with_sub(&mut request, &keep_alive, stream_sub.clone())
+                        .and_then(|response| response.wait_response().map_err(|_| ()));
+                    match response {
+                        Ok(response) if !is_event_stream(&response) => {
+                            debug!(
+                                "Event stream [{}] not reconnected, peer answered [{:?}]",
+                                template.path_with_query(),
+                                response.http_status()
+                            );
+                            break;
+                        }
+                        _ => {}
+                    }
+                    let retry = parser.lock().unwrap().retry();
+                    std::thread::sleep(retry);
+                }
+                sse_handle.close();
+            });
+            handle
+        }
     }
 }
@@ -0,0 +1,295 @@
+pub use segmented::{split_ranges, RangedDownload};
+
+mod segmented {
+    use std::{
+        collections::VecDeque,
+        fs::File,
+        io::{Seek, SeekFrom, Write},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use log::{debug, error, warn};
+    use quiche::h3::NameValue;
+    use uuid::Uuid;
+
+    use crate::client_manager::{
+        request_manager::{RequestEvent, RequestEventListener},
+        response_manager::{CompletedResponse, DownloadProgressStatus, WaitPeerResponse},
+        Http3ClientManager,
+    };
+
+    ///
+    ///Largest range asked in one request. A segment is held in memory until it is written, so
+    ///at most `segments * MAX_SEGMENT_LEN` bytes are buffered whatever the file size.
+    ///
+    const MAX_SEGMENT_LEN: u64 = 8 * 1024 * 1024;
+
+    ///
+    ///Split `total` bytes in `segments` contiguous inclusive ranges, as sent in `range:
+    ///bytes=<start>-<end>`. The last range takes the remainder.
+    ///
+    pub fn split_ranges(total: u64, segments: usize) -> Vec<(u64, u64)> {
+        if total == 0 {
+            return vec![];
+        }
+        let segments = (segments.max(1) as u64).min(total);
+        let segment_len = total / segments;
+        (0..segments)
+            .map(|i| {
+                let start = i * segment_len;
+                let end = if i == segments - 1 {
+                    total - 1
+                } else {
+                    start + segment_len - 1
+                };
+                (start, end)
+            })
+            .collect()
+    }
+
+    ///
+    ///`content-range: bytes <start>-<end>/<total>` of a 206.
+    ///
+    fn content_range(response: &CompletedResponse) -> Option<(u64, u64)> {
+        let headers = response.headers();
+        let value = headers.iter().find(|hdr| hdr.name() == b"content-range")?;
+        let value = String::from_utf8_lossy(value.value()).to_string();
+        let range = value.trim().strip_prefix("bytes ")?.split('/').next()?;
+        let (start, end) = range.split_once('-')?;
+        Some((start.trim().parse().ok()?, end.trim().parse().ok()?))
+    }
+
+    ///
+    ///Sums the download progress of every segment into one DownloadProgressStatus for the
+    ///whole file.
+    ///
+    struct SegmentProgress {
+        index: usize,
+        received: Arc<Vec<AtomicUsize>>,
+        total: usize,
+        req_path: String,
+        uuid: Uuid,
+        listeners: Vec<Arc<dyn RequestEventListener + 'static + Send + Sync>>,
+    }
+
+    impl RequestEventListener for SegmentProgress {
+        fn on_download_progress(
+            &self,
+            download_progress: DownloadProgressStatus,
+        ) -> Result<(), crossbeam::channel::SendError<RequestEvent>> {
+            self.received[self.index].store(download_progress.received(), Ordering::SeqCst);
+            let received = self
+                .received
+                .iter()
+                .map(|segment| segment.load(Ordering::SeqCst))
+                .sum();
+            for listener in &self.listeners {
+                listener.on_download_progress(DownloadProgressStatus::new(
+                    self.req_path.as_str(),
+                    self.uuid,
+                    received,
+                    Some(self.total),
+                ))?;
+            }
+            Ok(())
+        }
+    }
+
+    ///
+    ///Download of `path` into `dest` with concurrent range requests, see
+    ///Http3ClientManager::download_ranged().
+    ///
+    pub struct RangedDownload<'a> {
+        manager: &'a Http3ClientManager,
+        path: String,
+        dest: PathBuf,
+        segments: usize,
+        listeners: Vec<Arc<dyn RequestEventListener + 'static + Send + Sync>>,
+    }
+
+    impl<'a> RangedDownload<'a> {
+        pub fn new(
+            manager: &'a Http3ClientManager,
+            path: &str,
+            dest: PathBuf,
+            segments: usize,
+        ) -> Self {
+            Self {
+                manager,
+                path: path.to_string(),
+                dest,
+                segments,
+                listeners: vec![],
+            }
+        }
+        ///
+        ///Receive the progress of the whole download, all segments together.
+        ///
+        pub fn subscribe_event(
+            &mut self,
+            event_listener: Arc<dyn RequestEventListener + 'static + Send + Sync>,
+        ) -> &mut Self {
+            self.listeners.push(event_listener);
+            self
+        }
+
+        ///
+        ///Run the download and return the number of bytes written. Falls back to a single GET
+        ///if the peer doesn't announce the size or doesn't accept byte ranges, or if the file
+        ///fits in one segment.
+        ///
+        pub fn send(&self) -> Result<usize, ()> {
+            let head = self.manager.head(self.path.as_str());
+            // the size of the body as sent, which is what the ranges address
+            head.disable_decompression();
+            let head = head
+                .send()?
+                .wait_response()
+                .map_err(|e| error!("HEAD [{}] failed [{}]", self.path, e))?;
+            let headers = head.headers();
+            let total = headers
+                .iter()
+                .find(|hdr| hdr.name() == b"content-length")
+                .and_then(|hdr| String::from_utf8_lossy(hdr.value()).parse::<u64>().ok());
+            let accept_ranges = headers
+                .iter()
+                .any(|hdr| hdr.name() == b"accept-ranges" && hdr.value() == b"bytes");
+
+            let in_flight = self.segments.max(1);
+            let ranges = match total {
+                Some(total) if accept_ranges => {
+                    let bounded = total.div_ceil(MAX_SEGMENT_LEN) as usize;
+                    split_ranges(total, in_flight.max(bounded))
+                }
+                _ => vec![],
+            };
+            if ranges.len() < 2 {
+                debug!("[{}] downloaded in one request", self.path);
+                return self.download_whole();
+            }
+            let total = total.unwrap_or_default();
+
+            let mut dest =
+                File::create(&self.dest).map_err(|e| error!("[{:?}] [{:?}]", self.dest, e))?;
+            dest.set_len(total)
+                .map_err(|e| error!("[{:?}] [{:?}]", self.dest, e))?;
+
+            let uuid = Uuid::new_v4();
+            let received = Arc::new(
+                (0..ranges.len())
+                    .map(|_| AtomicUsize::new(0))
+                    .collect::<Vec<_>>(),
+            );
+            // `segments` streams are multiplexed on the connexion at once, the next range is
+            // requested as soon as the oldest one is written
+            let mut ranges = ranges.into_iter().enumerate();
+            let mut pending: VecDeque<((u64, u64), WaitPeerResponse)> = VecDeque::new();
+            let mut written = 0;
+            loop {
+                while pending.len() < in_flight {
+                    let Some((index, range)) = ranges.next() else {
+                        break;
+                    };
+                    let request = self.manager.get(self.path.as_str());
+                    request
+                        .header("range", format!("bytes={}-{}", range.0, range.1).as_str())
+                        .disable_decompression()
+                        .subscribe_event(Arc::new(SegmentProgress {
+                            index,
+                            received: received.clone(),
+                            total: total as usize,
+                            req_path: self.path.clone(),
+                            uuid,
+                            listeners: self.listeners.clone(),
+                        }));
+                    pending.push_back((range, request.send()?));
+                }
+                let Some(((start, end), peer_response)) = pending.pop_front() else {
+                    break;
+                };
+                let mut response = peer_response.wait_response().map_err(|e| {
+                    error!("Segment [{start}-{end}] of [{}] failed [{}]", self.path, e)
+                })?;
+                match response.http_status() {
+                    Some(206) if content_range(&response) == Some((start, end)) => {
+                        let data = response.raw_data();
+                        if data.len() as u64 != end - start + 1 {
+                            error!(
+                                "Segment [{start}-{end}] of [{}] has [{}] bytes",
+                                self.path,
+                                data.len()
+                            );
+                            return Err(());
+                        }
+                        self.write_at(&mut dest, start, &data)?;
+                        written += data.len();
+                    }
+                    Some(200) if response.as_data().len() as u64 == total => {
+                        warn!("Range ignored by the peer for [{}]", self.path);
+                        let data = response.raw_data();
+                        self.write_at(&mut dest, 0, &data)?;
+                        return Ok(data.len());
+                    }
+                    status => {
+                        error!(
+                            "Segment [{start}-{end}] of [{}] answered [{:?}]",
+                            self.path, status
+                        );
+                        return Err(());
+                    }
+                }
+            }
+            Ok(written)
+        }
+
+        fn download_whole(&self) -> Result<usize, ()> {
+            let request = self.manager.get(self.path.as_str());
+            for listener in &self.listeners {
+                request.subscribe_event(listener.clone());
+            }
+            let mut response = request
+                .send()?
+                .wait_response()
+                .map_err(|e| error!("GET [{}] failed [{}]", self.path, e))?;
+            if response.http_status() != Some(200) {
+                error!(
+                    "GET [{}] answered [{:?}]",
+                    self.path,
+                    response.http_status()
+                );
+                return Err(());
+            }
+            let data = response.raw_data();
+            File::create(&self.dest)
+                .and_then(|mut dest| dest.write_all(&data))
+                .map_err(|e| error!("[{:?}] [{:?}]", self.dest, e))?;
+            Ok(data.len())
+        }
+
+        fn write_at(&self, dest: &mut File, offset: u64, data: &[u8]) -> Result<(), ()> {
+            dest.seek(SeekFrom::Start(offset))
+                .and_then(|_| dest.write_all(data))
+                .map_err(|e| error!("[{:?}] at [{}] [{:?}]", self.dest, offset, e))
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    #[test]
+    fn ranges_cover_the_whole_file() {
+        assert_eq!(
+            split_ranges(1000, 3),
+            vec![(0, 332), (333, 665), (666, 999)]
+        );
+        assert_eq!(split_ranges(2, 8), vec![(0, 0), (1, 1)]);
+        assert_eq!(split_ranges(10, 1), vec![(0, 9)]);
+        assert!(split_ranges(0, 4).is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+pub use redirect_policy::RedirectPolicy;
+
+mod redirect_policy {
+    use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+    use log::{debug, warn};
+    use quiche::h3::NameValue;
+
+    use crate::client_manager::{
+        request_manager::{H3Method, Http3RequestBuilder, ReplaySource},
+        response_error::ResponseError,
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    const DEFAULT_HTTPS_PORT: u16 = 443;
+
+    ///
+    ///Follow the `location` of 301, 302, 303, 307 and 308 responses, up to `max_hops`
+    ///redirections for a request.
+    ///
+    ///307 and 308 send the same method and body again. 303, and a POST redirected by 301 or
+    ///302, become a GET without body.
+    ///
+    ///A `location` on another host name is resolved with the system resolver, in the thread
+    ///calling `wait_response`. That lookup blocks and has no timeout of its own.
+    ///
+    #[derive(Debug, Clone)]
+    pub struct RedirectPolicy {
+        max_hops: usize,
+    }
+
+    impl Default for RedirectPolicy {
+        fn default() -> Self {
+            Self { max_hops: 10 }
+        }
+    }
+
+    impl RedirectPolicy {
+        pub fn new(max_hops: usize) -> Self {
+            Self { max_hops }
+        }
+        pub fn max_hops(&self) -> usize {
+            self.max_hops
+        }
+
+        ///
+        ///Send the request with `dispatch` and follow the redirections of the responses.
+        ///`dispatch` gets requests for other authorities as well.
+        ///
+        pub fn send_through(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            dispatch: impl Fn(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()> + Send + 'static,
+        ) -> Result<WaitPeerResponse, ()> {
+            if self.max_hops == 0 {
+                return dispatch(http3_request_builder);
+            }
+            let Some(replay) = http3_request_builder.replay_source() else {
+                debug!("request body can't be replayed, redirections are not followed");
+                return dispatch(http3_request_builder);
+            };
+            let policy = self.clone();
+            Ok(dispatch(http3_request_builder)?
+                .intercept(move |response| policy.follow(response?, replay, &dispatch)))
+        }
+
+        fn follow(
+            &self,
+            mut response: CompletedResponse,
+            mut current: ReplaySource,
+            dispatch: &impl Fn(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()>,
+        ) -> Result<CompletedResponse, ResponseError> {
+            let mut hops = 0;
+            while let Some(mut next) =
+                current.with_request(|request| self.next_hop(&response, request))
+            {
+                if hops >= self.max_hops {
+                    warn!(
+                        "Too many redirections for [{}], stopped after [{}]",
+                        current.path_with_query(),
+                        hops
+                    );
+                    break;
+                }
+                hops += 1;
+                let Some(next_source) = next.replay_source() else {
+                    break;
+                };
+                response = dispatch(&mut next)
+                    .map_err(|_| ResponseError::Disconnected)?
+                    .wait_response()?;
+                current = next_source;
+            }
+            Ok(response)
+        }
+
+        ///
+        ///The request to send for a redirect response, None if the response is not a
+        ///redirection that can be followed.
+        ///
+        fn next_hop(
+            &self,
+            response: &CompletedResponse,
+            current: &Http3RequestBuilder,
+        ) -> Option<Http3RequestBuilder> {
+            let status = response.http_status()?;
+            let switch_to_get = match status {
+                303 => true,
+                301 | 302 => matches!(current.method(), Some(H3Method::POST { .. })),
+                307 | 308 => false,
+                _ => return None,
+            };
+            let headers = response.headers();
+            let location = headers.iter().find(|hdr| hdr.name() == b"location")?;
+            let location = String::from_utf8_lossy(location.value()).to_string();
+
+            let (authority, host, path) = resolve_location(
+                location.trim(),
+                current.authority()?,
+                current.get_path().unwrap_or("/".to_string()).as_str(),
+            )?;
+            debug!("Redirected [{}] to [{}{}]", status, authority, path);
+            current.redirected(authority, host, path, switch_to_get)
+        }
+    }
+
+    ///
+    ///Authority, host name (None if the location names no host, or an IP) and path (with
+    ///query) targeted by a `location` header, relative to the current request.
+    ///
+    pub fn resolve_location(
+        location: &str,
+        current_authority: SocketAddr,
+        current_path: &str,
+    ) -> Option<(SocketAddr, Option<String>, String)> {
+        let authority_and_path = location
+            .strip_prefix("https://")
+            .or_else(|| location.strip_prefix("http://"))
+            .or_else(|| location.strip_prefix("//"));
+
+        if let Some(authority_and_path) = authority_and_path {
+            let (authority, path) = match authority_and_path.find(['/', '?']) {
+                Some(i) => (&authority_and_path[..i], &authority_and_path[i..]),
+                None => (authority_and_path, "/"),
+            };
+            let path = if path.starts_with('?') {
+                format!("/{}", path)
+            } else {
+                path.to_string()
+            };
+            let (socket_address, host) = resolve_authority(authority)?;
+            return Some((socket_address, host, path));
+        }
+
+        let location = location.split('#').next().unwrap_or("");
+        if location.starts_with('/') {
+            return Some((current_authority, None, location.to_string()));
+        }
+        // relative to the directory of the current path
+        let current_path = current_path.split('?').next().unwrap_or("/");
+        let directory = match current_path.rfind('/') {
+            Some(last_slash) => &current_path[..=last_slash],
+            None => "/",
+        };
+        Some((
+            current_authority,
+            None,
+            format!("{}{}", directory, location),
+        ))
+    }
+
+    ///
+    ///The address to connect to, and the host name kept for the SNI and `:authority`. A host
+    ///name is resolved with a blocking lookup.
+    ///
+    fn resolve_authority(authority: &str) -> Option<(SocketAddr, Option<String>)> {
+        if let Ok(socket_address) = authority.parse::<SocketAddr>() {
+            return Some((socket_address, None));
+        }
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority.ends_with(']') && authority[i + 1..].parse::<u16>().is_ok() => {
+                (&authority[..i], authority[i + 1..].parse::<u16>().ok()?)
+            }
+            _ => (authority, DEFAULT_HTTPS_PORT),
+        };
+        let host = host.trim_matches(['[', ']']);
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Some((SocketAddr::new(ip, port), None));
+        }
+        match (host, port).to_socket_addrs() {
+            Ok(mut addresses) => Some((addresses.next()?, Some(host.to_ascii_lowercase()))),
+            Err(e) => {
+                warn!(
+                    "Failed to resolve redirection authority [{}] [{:?}]",
+                    authority, e
+                );
+                None
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::net::SocketAddr;
+
+    use quiche::h3;
+    use uuid::Uuid;
+
+    use super::redirect_policy::resolve_location;
+    use crate::client_manager::{
+        request_manager::{H3Method, Http3RequestPrep},
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    #[test]
+    fn resolve_redirect_locations() {
+        let current: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+        assert_eq!(
+            resolve_location("/login?next=%2F", current, "/account"),
+            Some((current, None, "/login?next=%2F".to_string()))
+        );
+        assert_eq!(
+            resolve_location("avatar.png", current, "/users/12/profile"),
+            Some((current, None, "/users/12/avatar.png".to_string()))
+        );
+        assert_eq!(
+            resolve_location("https://127.0.0.2:4433/files?id=1", current, "/"),
+            Some((
+                "127.0.0.2:4433".parse().unwrap(),
+                None,
+                "/files?id=1".to_string()
+            ))
+        );
+        assert_eq!(
+            resolve_location("https://127.0.0.2", current, "/"),
+            Some(("127.0.0.2:443".parse().unwrap(), None, "/".to_string()))
+        );
+        let (authority, host, path) =
+            resolve_location("https://LocalHost:4433/feed", current, "/").unwrap();
+        assert_eq!(authority.port(), 4433);
+        assert_eq!(host.as_deref(), Some("localhost"));
+        assert_eq!(path, "/feed");
+    }
+
+    #[test]
+    fn follow_redirects_and_strip_credentials() {
+        let mut builder =
+            Http3RequestPrep::new(Some("127.0.0.1:3000".parse().unwrap()), Uuid::new_v4());
+        builder
+            .post_data("/upload".to_string(), b"avatar".to_vec())
+            .set_header("authorization".to_string(), "Bearer t".to_string());
+
+        let response = RedirectPolicy::new(3)
+            .send_through(&mut builder, |request| {
+                let (status, location): (&[u8], &[u8]) = match request.get_path().unwrap().as_str()
+                {
+                    "/upload" => (b"307", b"/upload/v2"),
+                    "/upload/v2" => {
+                        assert!(matches!(request.method(), Some(H3Method::POST { .. })));
+                        assert!(request.header("authorization").is_some());
+                        (b"303", b"https://127.0.0.2:3000/done")
+                    }
+                    _ => {
+                        assert!(matches!(request.method(), Some(H3Method::GET)));
+                        assert!(request.header("authorization").is_none());
+                        (b"200", b"")
+                    }
+                };
+                Ok(WaitPeerResponse::ready(CompletedResponse::new(
+                    0,
+                    vec![
+                        h3::Header::new(b":status", status),
+                        h3::Header::new(b"location", location),
+                    ],
+                    vec![],
+                )))
+            })
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(200));
+
+        let looping = RedirectPolicy::new(2)
+            .send_through(&mut builder, |_| {
+                Ok(WaitPeerResponse::ready(CompletedResponse::new(
+                    0,
+                    vec![
+                        h3::Header::new(b":status", b"302"),
+                        h3::Header::new(b"location", b"/again"),
+                    ],
+                    vec![],
+                )))
+            })
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(looping.http_status(), Some(302));
+    }
+}
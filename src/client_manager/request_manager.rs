@@ -6,9 +6,12 @@ pub use request_body::ContentType;
 pub use request_body::RequestBody;
 pub use request_builder::{
     Http3Request, Http3RequestBuilder, Http3RequestConfirm, Http3RequestPrep, PingStatus,
+    ReplaySource, TrailersRequest,
 };
 pub use request_format::{BodyType, H3Method};
+pub use request_trailers::RequestTrailers;
 mod event_listener;
+mod request_trailers;
 mod queue_builder {
     use std::time::{Duration, Instant};
 
@@ -17,7 +20,7 @@ mod queue_builder {
 
     use self::{
         request_body::RequestBody,
-        request_builder::{BodyRequest, PingStatus},
+        request_builder::{BodyRequest, PingStatus, TrailersRequest},
     };
 
     use super::*;
@@ -47,12 +50,24 @@ mod queue_builder {
         ///Here we split the given body if necessary : Send body in chunks. Open a thread that read the buffer in loop, sending chunk by chunk
         ///the body with the stream_id.
         ///
+        ///With `trailers`, the last chunk leaves the stream open and the trailer section sent
+        ///after it carries the FIN.
         ///
-        pub fn send_body(&self, stream_id: u64, chunk_size: usize, mut body: RequestBody) {
+        ///A read error, or a body shorter than its announced length, resets the stream : the
+        ///peer stops waiting for the body and the request fails.
+        ///
+        pub fn send_body(
+            &self,
+            stream_id: u64,
+            chunk_size: usize,
+            mut body: RequestBody,
+            mut trailers: Option<Box<dyn RequestTrailers>>,
+        ) {
             let body_sender = self.head.clone();
             std::thread::spawn(move || {
                 let mut body = body;
-                let body_total_len = body.len();
+                let mut body_completed = false;
+                let body_total_len = body.known_len();
                 let mut byte_send = 0;
                 let mut packet_send = 0;
 
@@ -69,21 +84,50 @@ mod queue_builder {
                 let mut sending_duration = Duration::from_micros(13);
                 let mut last_send = Instant::now();
                 let mut read_buffer = &mut vec![0; chunk_size].into_boxed_slice();
-
-                while let Ok(n) = body.read(&mut read_buffer) {
+                let mut body_failed = false;
+
+                loop {
+                    let n = match body.read(&mut read_buffer) {
+                        Ok(n) => n,
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(e) => {
+                            warn!("Body on stream [{stream_id}] failed reading [{:?}]", e);
+                            body_failed = true;
+                            break;
+                        }
+                    };
                     let now = Instant::now();
 
                     // std::thread::sleep(sending_duration);
                     let adjust_duration = crossbeam::channel::bounded::<Instant>(1);
 
                     let end = n + byte_send;
+                    // Without a known length, the body ends when the reader reaches EOF : the
+                    // last (empty) chunk carries the FIN.
+                    let is_end = match body_total_len {
+                        Some(total_len) => end == total_len,
+                        None => n == 0,
+                    };
+                    if n == 0 && !is_end {
+                        warn!("Body on stream [{stream_id}] ended before its announced length [{byte_send}/{:?}]", body_total_len);
+                        body_failed = true;
+                        break;
+                    }
+                    if n == 0 && trailers.is_some() {
+                        // the trailer section ends the stream, no empty chunk needed
+                        body_completed = true;
+                        break;
+                    }
                     let data = read_buffer[..n].to_vec();
+                    if let Some(trailers) = trailers.as_mut() {
+                        trailers.on_body_chunk(&data);
+                    }
 
                     let body_request = Http3Request::Body(BodyRequest::new(
                         stream_id,
                         packet_count as usize,
                         data,
-                        if end == body_total_len { true } else { false },
+                        is_end && trailers.is_none(),
                     ));
 
                     if let Err(e) = body_sender.send((body_request, adjust_duration.0)) {
@@ -94,7 +138,8 @@ mod queue_builder {
                     byte_send += n;
                     packet_count += 1;
                     last_send = Instant::now();
-                    if byte_send == body_total_len {
+                    if is_end {
+                        body_completed = true;
                         break;
                     }
                 }
@@ -102,6 +147,25 @@ mod queue_builder {
                     "Body [{}] bytes send succesfully on stream [{stream_id}] in [{}] packets in [{:?}]",
                     byte_send, packet_count, send_duration.elapsed()
                 );
+                if body_failed {
+                    let adjust_duration = crossbeam::channel::bounded::<Instant>(1);
+                    if let Err(e) = body_sender
+                        .send((Http3Request::ResetStream { stream_id }, adjust_duration.0))
+                    {
+                        debug!("Error : failed resetting stream [{stream_id}]");
+                    }
+                    return;
+                }
+                if let (true, Some(trailers)) = (body_completed, trailers) {
+                    let adjust_duration = crossbeam::channel::bounded::<Instant>(1);
+                    let trailers_request = Http3Request::Trailers(TrailersRequest::new(
+                        stream_id,
+                        trailers.trailers(),
+                    ));
+                    if let Err(e) = body_sender.send((trailers_request, adjust_duration.0)) {
+                        debug!("Error : failed sending trailers on stream [{stream_id}]");
+                    }
+                }
             });
         }
         ///
@@ -244,7 +308,7 @@ mod request_builder {
         io::Read,
         net::SocketAddr,
         path::{Path, PathBuf},
-        sync::Arc,
+        sync::{Arc, Mutex},
         thread::panicking,
         time::Duration,
     };
@@ -252,9 +316,19 @@ mod request_builder {
     use log::{debug, error, info, warn};
     use quiche::h3::{self, Header};
     use ring::error;
+    use serde::Serialize;
     use uuid::Uuid;
 
-    use crate::{client_manager::persistant_stream::KeepAlive, my_log};
+    use crate::{
+        client_manager::{
+            content_digest::{sha256_field, ContentDigest},
+            content_encoding::{Encoding, ACCEPT_ENCODING},
+            ndjson::LineSink,
+            persistant_stream::KeepAlive,
+            stream_framing::StreamFramer,
+        },
+        my_log,
+    };
 
     use self::{
         event_listener::RequestEventListener, request_body::RequestBody, request_format::H3Method,
@@ -304,7 +378,18 @@ mod request_builder {
                 event_subscriber: vec![],
                 user_agent: None,
                 authority: peer_socket_address,
+                host: None,
                 custom_headers: None,
+                query: vec![],
+                invalid_query: false,
+                compression: None,
+                decompress: true,
+                retry_safe: false,
+                content_digest: false,
+                trailers: None,
+                line_sink: None,
+                stream_framer: None,
+                replay_source: None,
                 uuid: req_build_uuid,
             }
         }
@@ -324,6 +409,13 @@ mod request_builder {
         Body(BodyRequest),
         Header(HeaderRequest),
         Ping(PingStatus),
+        Trailers(TrailersRequest),
+        ///
+        ///Abort a request whose body can't be sent whole.
+        ///
+        ResetStream {
+            stream_id: u64,
+        },
         BodyFromFile,
     }
 
@@ -341,6 +433,15 @@ mod request_builder {
                 Self::Header(header) => {
                     write!(f, " req = header [{:#?}]", header.headers())
                 }
+                Self::Trailers(trailers) => write!(
+                    f,
+                    "req: trailers stream_id [{}] [{:?}]",
+                    trailers.stream_id(),
+                    trailers.headers()
+                ),
+                Self::ResetStream { stream_id } => {
+                    write!(f, "req: reset stream_id [{}]", stream_id)
+                }
                 Self::BodyFromFile => write!(f, "body from file []"),
                 Self::Ping(ping_status) => write!(f, "Ping! "),
             }
@@ -374,19 +475,63 @@ mod request_builder {
         }
     }
 
-    #[derive(Debug)]
     pub struct Content {
         payload: RequestBody,
+        trailers: Option<Box<dyn RequestTrailers>>,
+    }
+    impl Debug for Content {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "Content {{ payload: {:?}, trailers: {} }}",
+                self.payload,
+                self.trailers.is_some()
+            )
+        }
     }
     impl Content {
         pub fn take(self) -> RequestBody {
             self.payload
         }
+        pub fn take_with_trailers(self) -> (RequestBody, Option<Box<dyn RequestTrailers>>) {
+            (self.payload, self.trailers)
+        }
         pub fn new(request_body: RequestBody) -> Self {
             Self {
                 payload: request_body,
+                trailers: None,
+            }
+        }
+        pub fn with_trailers(mut self, trailers: Option<Box<dyn RequestTrailers>>) -> Self {
+            self.trailers = trailers;
+            self
+        }
+    }
+    ///
+    ///Trailer section of a request, sent once its body has been written.
+    ///
+    pub struct TrailersRequest {
+        stream_id: u64,
+        headers: Vec<h3::Header>,
+    }
+    impl TrailersRequest {
+        pub fn new(stream_id: u64, trailers: Vec<(String, String)>) -> Self {
+            Self {
+                stream_id,
+                headers: trailers
+                    .iter()
+                    .map(|(name, value)| {
+                        h3::Header::new(name.to_ascii_lowercase().as_bytes(), value.as_bytes())
+                    })
+                    .collect(),
             }
         }
+        pub fn stream_id(&self) -> u64 {
+            self.stream_id
+        }
+        pub fn headers(&self) -> &[h3::Header] {
+            &self.headers
+        }
     }
     pub struct BodyRequest {
         packet_id: usize,
@@ -487,6 +632,44 @@ mod request_builder {
         }
     }
 
+    ///
+    ///`content-length` is only announced for payloads whose size is known up front.
+    ///
+    fn content_length_header(payload: &RequestBody) -> Option<h3::Header> {
+        payload
+            .known_len()
+            .map(|len| h3::Header::new(b"content-length", len.to_string().as_bytes()))
+    }
+
+    ///
+    ///Apply the requested content coding to the payload, returning the `content-encoding` header
+    ///to announce it.
+    ///
+    fn compress_payload(
+        payload: RequestBody,
+        compression: Option<Encoding>,
+    ) -> Result<(RequestBody, Option<h3::Header>), ()> {
+        match compression {
+            Some(encoding) => match payload.compress(encoding) {
+                Ok(compressed) => Ok((
+                    compressed,
+                    Some(h3::Header::new(
+                        b"content-encoding",
+                        encoding.as_str().as_bytes(),
+                    )),
+                )),
+                Err(e) => {
+                    error!(
+                        "Failed to compress request body with [{:?}] [{:?}]",
+                        encoding, e
+                    );
+                    Err(())
+                }
+            },
+            None => Ok((payload, None)),
+        }
+    }
+
     impl Http3Request {
         pub fn new(peer_socket_address: Option<SocketAddr>, uuid: Uuid) -> Http3RequestBuilder {
             Http3RequestBuilder {
@@ -497,12 +680,28 @@ mod request_builder {
                 event_subscriber: vec![],
                 user_agent: None,
                 authority: peer_socket_address,
+                host: None,
                 custom_headers: None,
+                query: vec![],
+                invalid_query: false,
+                compression: None,
+                decompress: true,
+                retry_safe: false,
+                content_digest: false,
+                trailers: None,
+                line_sink: None,
+                stream_framer: None,
+                replay_source: None,
                 uuid,
             }
         }
     }
 
+    ///
+    ///Headers that are not forwarded when a redirection leads to another authority.
+    ///
+    const SENSITIVE_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
     /// Request builder : call set_method, set_path, set_user_agent, and build.
     pub struct Http3RequestBuilder {
         method: Option<H3Method>,
@@ -512,14 +711,183 @@ mod request_builder {
         event_subscriber: Vec<Arc<dyn RequestEventListener + 'static + Send + Sync>>,
         user_agent: Option<String>,
         authority: Option<SocketAddr>,
+        host: Option<String>,
         custom_headers: Option<Vec<(String, String)>>,
+        query: Vec<String>,
+        invalid_query: bool,
+        compression: Option<Encoding>,
+        decompress: bool,
+        retry_safe: bool,
+        content_digest: bool,
+        trailers: Option<Box<dyn RequestTrailers>>,
+        line_sink: Option<LineSink>,
+        stream_framer: Option<Box<dyn StreamFramer>>,
+        replay_source: Option<ReplaySource>,
         uuid: Uuid,
     }
 
+    ///
+    ///Copy of a request to send it again (authentication refresh, retry, redirection). It is
+    ///taken once and shared by the client layers the request goes through : a new request is
+    ///only copied from it when one of them actually sends it again.
+    ///
+    #[derive(Clone)]
+    pub struct ReplaySource(Arc<Mutex<Http3RequestBuilder>>);
+
+    impl ReplaySource {
+        ///
+        ///A new sending of the request, None if its body can't be read again.
+        ///
+        pub fn request(&self) -> Option<Http3RequestBuilder> {
+            let mut request = self.0.lock().unwrap().try_clone()?;
+            request.replay_source = Some(self.clone());
+            Some(request)
+        }
+        ///
+        ///Look at the request without copying it.
+        ///
+        pub fn with_request<R>(&self, f: impl FnOnce(&Http3RequestBuilder) -> R) -> R {
+            f(&self.0.lock().unwrap())
+        }
+        pub fn path_with_query(&self) -> String {
+            self.0.lock().unwrap().path_with_query()
+        }
+    }
+
     impl Http3RequestBuilder {
+        ///
+        ///Source to send this request again, shared with the layers that already asked for
+        ///it. None if the body is a stream or the request has trailers.
+        ///
+        pub fn replay_source(&mut self) -> Option<ReplaySource> {
+            if self.replay_source.is_none() {
+                self.replay_source = Some(ReplaySource(Arc::new(Mutex::new(self.try_clone()?))));
+            }
+            self.replay_source.clone()
+        }
+        ///
+        ///Copy of the builder, before it is built, to send the same request again (retry after
+        ///a refreshed authentication...). None if the body is a stream or the request has
+        ///trailers.
+        ///
+        pub fn try_clone(&self) -> Option<Http3RequestBuilder> {
+            if self.trailers.is_some() {
+                return None;
+            }
+            let method = match &self.method {
+                Some(method) => Some(method.try_clone()?),
+                None => None,
+            };
+            Some(self.clone_with_method(method))
+        }
+        fn clone_with_method(&self, method: Option<H3Method>) -> Http3RequestBuilder {
+            Http3RequestBuilder {
+                method,
+                path: self.path.clone(),
+                scheme: self.scheme.clone(),
+                content_type: self.content_type.clone(),
+                event_subscriber: self.event_subscriber.clone(),
+                user_agent: self.user_agent.clone(),
+                authority: self.authority,
+                host: self.host.clone(),
+                custom_headers: self.custom_headers.clone(),
+                query: self.query.clone(),
+                invalid_query: self.invalid_query,
+                compression: self.compression,
+                decompress: self.decompress,
+                retry_safe: self.retry_safe,
+                content_digest: self.content_digest,
+                trailers: None,
+                line_sink: self.line_sink.clone(),
+                stream_framer: None,
+                replay_source: None,
+                uuid: self.uuid,
+            }
+        }
+        ///
+        ///The request to send to follow a redirection to `authority` / `path` (query string
+        ///included), named `host` if the location gave a host name. With `switch_to_get`, the
+        ///body is dropped and the method becomes GET. Credentials and cookies are not sent to
+        ///another authority.
+        ///
+        pub fn redirected(
+            &self,
+            authority: SocketAddr,
+            host: Option<String>,
+            path: String,
+            switch_to_get: bool,
+        ) -> Option<Http3RequestBuilder> {
+            let mut next = if switch_to_get {
+                let mut as_get = self.clone_with_method(Some(H3Method::GET));
+                as_get.content_type = None;
+                as_get.compression = None;
+                as_get
+            } else {
+                self.try_clone()?
+            };
+            let same_host = host.is_none() || host == self.host;
+            if self.authority != Some(authority) || !same_host {
+                next.host = host;
+                if let Some(headers) = next.custom_headers.as_mut() {
+                    headers.retain(|hdr| {
+                        !SENSITIVE_HEADERS
+                            .iter()
+                            .any(|sensitive| hdr.0.eq_ignore_ascii_case(sensitive))
+                    });
+                }
+            }
+            next.authority = Some(authority);
+            next.path = Some(path);
+            next.query = vec![];
+            Some(next)
+        }
         pub fn post_data(&mut self, path: String, data: Vec<u8>) -> &mut Self {
             self.post(path, RequestBody::new_data(data))
         }
+        pub fn method(&self) -> Option<&H3Method> {
+            self.method.as_ref()
+        }
+        pub fn authority(&self) -> Option<SocketAddr> {
+            self.authority
+        }
+        ///
+        ///Name of the peer the request is addressed to : sent in `:authority` and scope of its
+        ///cookies.
+        ///
+        pub fn set_host(&mut self, host: &str) -> &mut Self {
+            self.host = Some(host.to_ascii_lowercase());
+            self
+        }
+        ///
+        ///Host used if set_host() was not called.
+        ///
+        pub fn default_host(&mut self, host: &str) -> &mut Self {
+            if self.host.is_none() {
+                self.set_host(host);
+            }
+            self
+        }
+        ///
+        ///Value of `:authority` : the host name and the port of the peer, or its address when
+        ///it has no name.
+        ///
+        fn authority_header(&self) -> String {
+            let authority = self.authority.unwrap();
+            match &self.host {
+                Some(host) if host.parse::<std::net::IpAddr>().is_err() => {
+                    format!("{}:{}", host, authority.port())
+                }
+                _ => authority.to_string(),
+            }
+        }
+        ///
+        ///Name of the peer set with set_host(), or the IP of the authority.
+        ///
+        pub fn host(&self) -> Option<String> {
+            self.host
+                .clone()
+                .or_else(|| self.authority.map(|authority| authority.ip().to_string()))
+        }
         pub fn get_path(&self) -> Option<String> {
             if let Some(path) = self.path.as_ref() {
                 Some(path.to_string())
@@ -557,10 +925,17 @@ mod request_builder {
             self.path = Some(path);
             self
         }
-        pub fn delete(&mut self, req_path: String, auth_token: String) -> &mut Self {
+        ///
+        ///Same as GET, but the peer only sends the headers (size, type, validators...).
+        ///
+        pub fn head(&mut self, path: String) -> &mut Self {
+            self.method = Some(H3Method::HEAD);
+            self.path = Some(path);
+            self
+        }
+        pub fn delete(&mut self, req_path: String) -> &mut Self {
             self.method = Some(H3Method::DELETE);
             self.path = Some(req_path);
-            self.set_header("Authorization".to_string(), auth_token);
             self
         }
         pub fn set_user_agent(&mut self, user_agent: String) -> &mut Self {
@@ -575,6 +950,189 @@ mod request_builder {
             }
             self
         }
+        ///
+        ///Value of a header set by the user on this request (name is case insensitive).
+        ///
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.custom_headers.as_ref().and_then(|headers| {
+                headers
+                    .iter()
+                    .find(|hdr| hdr.0.eq_ignore_ascii_case(name))
+                    .map(|hdr| hdr.1.as_str())
+            })
+        }
+        ///
+        ///Replace the value of a user header, or add it.
+        ///
+        pub fn replace_header(&mut self, name: &str, value: String) -> &mut Self {
+            if let Some(headers) = self.custom_headers.as_mut() {
+                headers.retain(|hdr| !hdr.0.eq_ignore_ascii_case(name));
+            }
+            self.set_header(name.to_string(), value)
+        }
+        ///
+        ///Append a key/value pair to the query string. Both are percent-encoded. If they can't
+        ///be, the request fails to build instead of being sent without them.
+        ///
+        pub fn add_query(&mut self, key: &str, value: &str) -> &mut Self {
+            match serde_urlencoded::to_string(&[(key, value)]) {
+                Ok(encoded) => self.query.push(encoded),
+                Err(e) => {
+                    error!("Failed to encode query parameter [{}] [{:?}]", key, e);
+                    self.invalid_query = true;
+                }
+            }
+            self
+        }
+        ///
+        ///Serialize a struct (or any serde type that maps to key/value pairs) into the query
+        ///string. On error the request also fails to build.
+        ///
+        pub fn add_query_serde<T: Serialize + ?Sized>(
+            &mut self,
+            value: &T,
+        ) -> Result<&mut Self, serde_urlencoded::ser::Error> {
+            let encoded = serde_urlencoded::to_string(value).map_err(|e| {
+                self.invalid_query = true;
+                e
+            })?;
+            if !encoded.is_empty() {
+                self.query.push(encoded);
+            }
+            Ok(self)
+        }
+        ///
+        ///The request path with the encoded query string appended, as sent in the `:path` header.
+        ///
+        pub fn path_with_query(&self) -> String {
+            let path = self.path.clone().unwrap_or_default();
+            if self.query.is_empty() {
+                return path;
+            }
+            let separator = if path.contains('?') { '&' } else { '?' };
+            format!("{}{}{}", path, separator, self.query.join("&"))
+        }
+        ///
+        ///Compress the request body with `encoding` and announce it with `content-encoding`.
+        ///
+        pub fn compress(&mut self, encoding: Encoding) -> &mut Self {
+            self.compression = Some(encoding);
+            self
+        }
+        ///
+        ///Compress the body with `encoding` if its known size reaches `min_size` bytes and no
+        ///encoding has been chosen for this request yet.
+        ///
+        pub fn compress_above(&mut self, min_size: usize, encoding: Encoding) -> &mut Self {
+            if self.compression.is_none() {
+                if let Some(H3Method::POST { payload }) = &self.method {
+                    if payload.known_len().map_or(false, |len| len >= min_size) {
+                        self.compression = Some(encoding);
+                    }
+                }
+            }
+            self
+        }
+        ///
+        ///Keep the response body exactly as received : no `accept-encoding` is advertised and a
+        ///`content-encoding` of the response is left undecoded.
+        ///
+        pub fn disable_decompression(&mut self) -> &mut Self {
+            self.decompress = false;
+            self
+        }
+        pub fn decompress(&self) -> bool {
+            self.decompress
+        }
+        ///
+        ///Hand each line of a successful response body to `line_sink` as soon as it is
+        ///received, instead of keeping it in the CompletedResponse.
+        ///
+        pub fn set_line_sink(&mut self, line_sink: LineSink) -> &mut Self {
+            self.line_sink = Some(line_sink);
+            self
+        }
+        pub fn take_line_sink(&mut self) -> Option<LineSink> {
+            self.line_sink.take()
+        }
+        pub fn has_line_sink(&self) -> bool {
+            self.line_sink.is_some()
+        }
+        ///
+        ///Framing of the body when the request is opened as a persistent stream. It is not
+        ///copied by try_clone().
+        ///
+        pub fn set_stream_framer(&mut self, stream_framer: Box<dyn StreamFramer>) -> &mut Self {
+            self.stream_framer = Some(stream_framer);
+            self
+        }
+        pub fn take_stream_framer(&mut self) -> Option<Box<dyn StreamFramer>> {
+            self.stream_framer.take()
+        }
+        ///
+        ///Allow the retry policy to send this request again even if its method is not
+        ///idempotent (a POST the peer handles safely when received twice).
+        ///
+        pub fn mark_retry_safe(&mut self) -> &mut Self {
+            self.retry_safe = true;
+            self
+        }
+        pub fn is_retry_safe(&self) -> bool {
+            self.retry_safe
+        }
+        ///
+        ///Send `trailers` in a trailer section after the body of a POST. Ignored for requests
+        ///without body.
+        ///
+        pub fn set_trailers(&mut self, trailers: impl RequestTrailers) -> &mut Self {
+            self.trailers = Some(Box::new(trailers));
+            self
+        }
+        pub fn has_trailers(&self) -> bool {
+            self.trailers.is_some()
+        }
+        ///
+        ///Send the SHA-256 of the body (as sent, after compression) in `content-digest`. It is a
+        ///header for a body in memory, and a trailer computed while sending for a file or a
+        ///stream.
+        ///
+        pub fn set_content_digest(&mut self) -> &mut Self {
+            self.content_digest = true;
+            self
+        }
+        ///
+        ///The `content-digest` header when the payload is in memory, otherwise the digest is
+        ///added to the trailers and the returned header announces it.
+        ///
+        fn content_digest_header(&mut self, payload: &RequestBody) -> Option<h3::Header> {
+            if !self.content_digest {
+                return None;
+            }
+            if let Some(bytes) = payload.as_bytes() {
+                let digest = ring::digest::digest(&ring::digest::SHA256, bytes);
+                return Some(h3::Header::new(
+                    b"content-digest",
+                    sha256_field(digest.as_ref()).as_bytes(),
+                ));
+            }
+            self.trailers = Some(Box::new(ContentDigest::new(self.trailers.take())));
+            Some(h3::Header::new(b"trailer", b"content-digest"))
+        }
+        ///
+        ///Add the user headers, and `accept-encoding` when the response can be decoded.
+        ///
+        fn add_custom_headers(&self, hdr_req: &mut HeaderRequest) {
+            let mut has_accept_encoding = false;
+            if let Some(headers) = &self.custom_headers {
+                for hdr in headers.iter() {
+                    has_accept_encoding |= hdr.0.eq_ignore_ascii_case("accept-encoding");
+                    hdr_req.add_header_mut(hdr.0.as_str(), hdr.1.as_str());
+                }
+            }
+            if self.decompress && !has_accept_encoding {
+                hdr_req.add_header_mut("accept-encoding", ACCEPT_ENCODING);
+            }
+        }
         pub fn set_content_type(&mut self, content_type: ContentType) -> &mut Self {
             self.content_type = Some(content_type.to_string());
             self
@@ -600,7 +1158,13 @@ mod request_builder {
                 info!("http3 request, nothing to build !");
                 return Err(());
             }
+            if self.invalid_query {
+                error!("http3 request, query parameters failed to encode");
+                return Err(());
+            }
 
+            // the replays of this sending were copied from it, a new sending takes a new one
+            self.replay_source = None;
             let event_subscriber = std::mem::replace(&mut self.event_subscriber, vec![]);
             let (sender, receiver) = crossbeam::channel::bounded::<(u64, String)>(1);
             let confirmation = Some(Http3RequestConfirm { response: receiver });
@@ -612,22 +1176,15 @@ mod request_builder {
                     let mut hdr_req = HeaderRequest::new(true, sender)
                         .add_header(":method", "GET")
                         .add_header(":scheme", "https")
-                        .add_header(":path", self.path.as_ref().unwrap().to_string().as_str())
-                        .add_header(
-                            ":authority",
-                            self.authority.as_ref().unwrap().to_string().as_str(),
-                        )
+                        .add_header(":path", self.path_with_query().as_str())
+                        .add_header(":authority", self.authority_header().as_str())
                         /*
                         .add_header(
                             "user-agent",
                             self.user_agent.as_ref().unwrap().to_string().as_str(),
                         )*/
                         .add_header("accept", "*/*");
-                    if let Some(headers) = &self.custom_headers {
-                        for hdr in headers.iter() {
-                            hdr_req.add_header_mut(hdr.0.as_str(), hdr.1.as_str());
-                        }
-                    }
+                    self.add_custom_headers(&mut hdr_req);
                     let mut res = vec![Http3RequestPrep::Header(hdr_req)];
                     res
                 }
@@ -645,31 +1202,29 @@ mod request_builder {
                         ));
                     }
 
+                    let (payload, content_encoding) =
+                        compress_payload(payload, self.compression.take())?;
                     let mut hdr_req = HeaderRequest::new(false, sender)
                         .add_header(":method", "POST")
                         .add_header(":scheme", "https")
-                        .add_header(":path", self.path.as_ref().unwrap().to_string().as_str())
-                        .add_header("content-length", payload.len().to_string().as_str())
-                        .add_header(
-                            ":authority",
-                            self.authority.as_ref().unwrap().to_string().as_str(),
-                        )
+                        .add_header(":path", self.path_with_query().as_str())
+                        .add_header(":authority", self.authority_header().as_str())
+                        .add_header_option(content_length_header(&payload))
                         .add_header_option(content_type)
+                        .add_header_option(content_encoding)
                         /*
                         .add_header(
                             "user-agent",
                             self.user_agent.as_ref().unwrap().to_string().as_str(),
                         )*/
                         .add_header("accept", "*/*");
-                    if let Some(headers) = &self.custom_headers {
-                        for hdr in headers.iter() {
-                            hdr_req.add_header_mut(hdr.0.as_str(), hdr.1.as_str());
-                        }
-                    }
+                    self.add_custom_headers(&mut hdr_req);
                     let mut res = vec![
                         Http3RequestPrep::Header(hdr_req),
                         //   header request
-                        Http3RequestPrep::Body(Content::new(payload)),
+                        Http3RequestPrep::Body(
+                            Content::new(payload).with_trailers(self.trailers.take()),
+                        ),
                     ];
                     if let Some(ping_frequency) = keep_alive {
                         res.push(Http3RequestPrep::Ping(ping_frequency.duration()))
@@ -680,11 +1235,8 @@ mod request_builder {
                     let mut hrd_req = HeaderRequest::new(true, sender)
                         .add_header(":method", "DELETE")
                         .add_header(":scheme", "https")
-                        .add_header(":path", self.path.as_ref().unwrap().to_string().as_str())
-                        .add_header(
-                            ":authority",
-                            self.authority.as_ref().unwrap().to_string().as_str(),
-                        )
+                        .add_header(":path", self.path_with_query().as_str())
+                        .add_header(":authority", self.authority_header().as_str())
                         /*
                         .add_header(
                             "user-agent",
@@ -692,11 +1244,7 @@ mod request_builder {
                         )*/
                         .add_header("accept", "*/*");
 
-                    if let Some(headers) = &self.custom_headers {
-                        for hdr in headers.iter() {
-                            hrd_req.add_header_mut(hdr.0.as_str(), hdr.1.as_str());
-                        }
-                    }
+                    self.add_custom_headers(&mut hrd_req);
                     vec![Http3RequestPrep::Header(hrd_req)]
                 }
                 _ => vec![],
@@ -722,36 +1270,35 @@ mod request_builder {
                 info!("http3 request, nothing to build !");
                 return Err(());
             }
+            if self.invalid_query {
+                error!("http3 request, query parameters failed to encode");
+                return Err(());
+            }
 
+            // the replays of this sending were copied from it, a new sending takes a new one
+            self.replay_source = None;
             let event_subscriber = std::mem::replace(&mut self.event_subscriber, vec![]);
             let (sender, receiver) = crossbeam::channel::bounded::<(u64, String)>(1);
             let confirmation = Some(Http3RequestConfirm { response: receiver });
 
             let http_request_prep = match self.method.take().unwrap() {
-                H3Method::GET => {
+                method @ (H3Method::GET | H3Method::HEAD) => {
                     let mut hdr_req = HeaderRequest::new(true, sender)
-                        .add_header(":method", "GET")
+                        .add_header(":method", method.as_str())
                         .add_header(":scheme", "https")
-                        .add_header(":path", self.path.as_ref().unwrap().to_string().as_str())
-                        .add_header(
-                            ":authority",
-                            self.authority.as_ref().unwrap().to_string().as_str(),
-                        )
+                        .add_header(":path", self.path_with_query().as_str())
+                        .add_header(":authority", self.authority_header().as_str())
                         /*
                         .add_header(
                             "user-agent",
                             self.user_agent.as_ref().unwrap().to_string().as_str(),
                         )*/
                         .add_header("accept", "*/*");
-                    if let Some(headers) = &self.custom_headers {
-                        for hdr in headers.iter() {
-                            hdr_req.add_header_mut(hdr.0.as_str(), hdr.1.as_str());
-                        }
-                    }
+                    self.add_custom_headers(&mut hdr_req);
                     vec![Http3RequestPrep::Header(hdr_req)]
                 }
                 H3Method::POST { mut payload } => {
-                    if payload.len() == 0 {
+                    if payload.known_len() == Some(0) {
                         error!("Payload must be > to 0 bytes");
                         return Err(());
                     }
@@ -763,42 +1310,39 @@ mod request_builder {
                         ));
                     }
 
+                    let (payload, content_encoding) =
+                        compress_payload(payload, self.compression.take())?;
+                    let content_digest = self.content_digest_header(&payload);
                     let mut hdr_req = HeaderRequest::new(false, sender)
                         .add_header(":method", "POST")
                         .add_header(":scheme", "https")
-                        .add_header(":path", self.path.as_ref().unwrap().to_string().as_str())
-                        .add_header("content-length", payload.len().to_string().as_str())
-                        .add_header(
-                            ":authority",
-                            self.authority.as_ref().unwrap().to_string().as_str(),
-                        )
+                        .add_header(":path", self.path_with_query().as_str())
+                        .add_header(":authority", self.authority_header().as_str())
+                        .add_header_option(content_length_header(&payload))
                         .add_header_option(content_type)
+                        .add_header_option(content_encoding)
+                        .add_header_option(content_digest)
                         /*
                         .add_header(
                             "user-agent",
                             self.user_agent.as_ref().unwrap().to_string().as_str(),
                         )*/
                         .add_header("accept", "*/*");
-                    if let Some(headers) = &self.custom_headers {
-                        for hdr in headers.iter() {
-                            hdr_req.add_header_mut(hdr.0.as_str(), hdr.1.as_str());
-                        }
-                    }
+                    self.add_custom_headers(&mut hdr_req);
                     vec![
                         Http3RequestPrep::Header(hdr_req),
                         //   header request
-                        Http3RequestPrep::Body(Content::new(payload)),
+                        Http3RequestPrep::Body(
+                            Content::new(payload).with_trailers(self.trailers.take()),
+                        ),
                     ]
                 }
                 H3Method::DELETE => {
                     let mut hrd_req = HeaderRequest::new(true, sender)
                         .add_header(":method", "DELETE")
                         .add_header(":scheme", "https")
-                        .add_header(":path", self.path.as_ref().unwrap().to_string().as_str())
-                        .add_header(
-                            ":authority",
-                            self.authority.as_ref().unwrap().to_string().as_str(),
-                        )
+                        .add_header(":path", self.path_with_query().as_str())
+                        .add_header(":authority", self.authority_header().as_str())
                         /*
                         .add_header(
                             "user-agent",
@@ -806,11 +1350,7 @@ mod request_builder {
                         )*/
                         .add_header("accept", "*/*");
 
-                    if let Some(headers) = &self.custom_headers {
-                        for hdr in headers.iter() {
-                            hrd_req.add_header_mut(hdr.0.as_str(), hdr.1.as_str());
-                        }
-                    }
+                    self.add_custom_headers(&mut hrd_req);
                     vec![Http3RequestPrep::Header(hrd_req)]
                 }
                 _ => vec![],
@@ -834,6 +1374,7 @@ mod request_format {
     #[derive(Debug, PartialEq)]
     pub enum H3Method {
         GET,
+        HEAD,
         POST { payload: RequestBody },
         PUT,
         DELETE,
@@ -841,6 +1382,31 @@ mod request_format {
     }
 
     impl H3Method {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Self::GET => "GET",
+                Self::HEAD => "HEAD",
+                Self::POST { .. } => "POST",
+                Self::PUT => "PUT",
+                Self::DELETE => "DELETE",
+                Self::STREAM => "STREAM",
+            }
+        }
+        ///
+        ///Copy of the method to send the request again, None if its payload can't be replayed.
+        ///
+        pub fn try_clone(&self) -> Option<H3Method> {
+            Some(match self {
+                Self::GET => Self::GET,
+                Self::HEAD => Self::HEAD,
+                Self::POST { payload } => Self::POST {
+                    payload: payload.try_clone()?,
+                },
+                Self::PUT => Self::PUT,
+                Self::DELETE => Self::DELETE,
+                Self::STREAM => Self::STREAM,
+            })
+        }
         ///
         ///Parse method name from raw bytes.
         ///
@@ -848,6 +1414,7 @@ mod request_format {
         pub fn parse(input: &[u8]) -> Result<H3Method, ()> {
             match &String::from_utf8_lossy(input)[..] {
                 "GET" => Ok(H3Method::GET),
+                "HEAD" => Ok(H3Method::HEAD),
                 "POST" => Ok(H3Method::POST {
                     payload: RequestBody::Empty,
                 }),
@@ -1012,7 +1579,7 @@ mod request_format {
 }
 
 mod test {
-    use std::{net::SocketAddr, str::FromStr};
+    use std::{net::SocketAddr, str::FromStr, time::Duration};
 
     use quiche::h3::NameValue;
     use test::request_format::BodyType;
@@ -1187,4 +1754,326 @@ mod test {
                 }
         */
     }
+    #[test]
+    fn build_get_request_with_query() {
+        let mut new_request = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        new_request
+            .get("/search".to_string())
+            .add_query("q", "faces & quic")
+            .add_query("page", "2");
+
+        assert_eq!(
+            new_request.path_with_query(),
+            "/search?q=faces+%26+quic&page=2"
+        );
+
+        let request = new_request.build().unwrap();
+        match &request.0[0] {
+            Http3RequestPrep::Header(header) => {
+                assert!(header.headers().iter().any(|hdr| hdr.name() == b":path"
+                    && hdr.value() == b"/search?q=faces+%26+quic&page=2"));
+            }
+            _ => assert!(false),
+        }
+
+        let mut unencodable = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        unencodable.get("/search".to_string());
+        assert!(unencodable.add_query_serde(&"not a map").is_err());
+        assert!(unencodable.build().is_err());
+    }
+
+    #[test]
+    fn redirection_to_another_host_names_it_in_authority() {
+        let mut new_request = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        new_request
+            .get("/old".to_string())
+            .set_header("authorization".to_string(), "Bearer t".to_string());
+
+        let mut redirected = new_request
+            .redirected(
+                SocketAddr::from_str("127.0.0.2:4433").unwrap(),
+                Some("cdn.example.org".to_string()),
+                "/new".to_string(),
+                false,
+            )
+            .unwrap();
+        assert_eq!(redirected.host().as_deref(), Some("cdn.example.org"));
+        assert!(redirected.header("authorization").is_none());
+
+        let request = redirected.build().unwrap();
+        match &request.0[0] {
+            Http3RequestPrep::Header(header) => {
+                assert!(header.headers().iter().any(
+                    |hdr| hdr.name() == b":authority" && hdr.value() == b"cdn.example.org:4433"
+                ));
+            }
+            _ => assert!(false),
+        }
+    }
+
+    struct ByteCount(usize);
+
+    impl RequestTrailers for ByteCount {
+        fn on_body_chunk(&mut self, chunk: &[u8]) {
+            self.0 += chunk.len();
+        }
+        fn trailers(self: Box<Self>) -> Vec<(String, String)> {
+            vec![("X-Byte-Count".to_string(), self.0.to_string())]
+        }
+    }
+
+    #[test]
+    fn post_with_trailers() {
+        let mut new_request = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        new_request
+            .post_data("/upload".to_string(), b"hello".to_vec())
+            .set_trailers(ByteCount(0));
+        assert!(new_request.try_clone().is_none());
+
+        let request = new_request.build().unwrap();
+        let Some(Http3RequestPrep::Body(content)) = request.0.into_iter().nth(1) else {
+            panic!("post without body");
+        };
+        let (_body, trailers) = content.take_with_trailers();
+        let mut trailers = trailers.unwrap();
+        trailers.on_body_chunk(b"hel");
+        trailers.on_body_chunk(b"lo");
+
+        let trailers_request = TrailersRequest::new(0, trailers.trailers());
+        assert_eq!(trailers_request.headers()[0].name(), b"x-byte-count");
+        assert_eq!(trailers_request.headers()[0].value(), b"5");
+    }
+
+    #[test]
+    fn content_digest_as_header_or_trailer() {
+        let mut in_memory = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        in_memory
+            .post_data("/upload".to_string(), b"hello world".to_vec())
+            .set_content_digest();
+        let request = in_memory.build().unwrap();
+        match &request.0[0] {
+            Http3RequestPrep::Header(header) => {
+                assert!(header
+                    .headers()
+                    .iter()
+                    .any(|hdr| hdr.name() == b"content-digest"
+                        && hdr.value()
+                            == b"sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:"));
+            }
+            _ => assert!(false),
+        }
+
+        let mut streamed = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        streamed
+            .post_stream(
+                "/upload".to_string(),
+                Box::new(std::io::Cursor::new(b"hello world".to_vec())),
+            )
+            .set_content_digest();
+        let request = streamed.build().unwrap();
+        let mut request = request.0.into_iter();
+        let Some(Http3RequestPrep::Header(header)) = request.next() else {
+            panic!("post without headers");
+        };
+        assert!(header
+            .headers()
+            .iter()
+            .any(|hdr| hdr.name() == b"trailer" && hdr.value() == b"content-digest"));
+        let Some(Http3RequestPrep::Body(content)) = request.next() else {
+            panic!("post without body");
+        };
+        let mut trailers = content.take_with_trailers().1.unwrap();
+        trailers.on_body_chunk(b"hello world");
+        assert_eq!(
+            trailers.trailers(),
+            vec![(
+                "content-digest".to_string(),
+                "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:".to_string()
+            )]
+        );
+    }
+
+    ///
+    ///The headers of a built POST, and its body.
+    ///
+    fn built_post(request: &mut Http3RequestBuilder) -> (Vec<quiche::h3::Header>, RequestBody) {
+        let mut request = request.build().unwrap().0.into_iter();
+        let Some(Http3RequestPrep::Header(header)) = request.next() else {
+            panic!("post without headers");
+        };
+        let Some(Http3RequestPrep::Body(content)) = request.next() else {
+            panic!("post without body");
+        };
+        (header.headers().to_vec(), content.take())
+    }
+    fn header_value(headers: &[quiche::h3::Header], name: &[u8]) -> Option<Vec<u8>> {
+        headers
+            .iter()
+            .find(|hdr| hdr.name() == name)
+            .map(|hdr| hdr.value().to_vec())
+    }
+
+    #[test]
+    fn compress_above_starts_at_the_threshold() {
+        for (min_size, compressed) in [(101, false), (100, true)] {
+            let mut request = Http3RequestPrep::new(
+                Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+                uuid::Uuid::new_v4(),
+            );
+            request
+                .post_data("/upload".to_string(), vec![b'a'; 100])
+                .compress_above(min_size, crate::Encoding::Gzip);
+            let (headers, _) = built_post(&mut request);
+            assert_eq!(
+                header_value(&headers, b"content-encoding").is_some(),
+                compressed
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_data_announces_its_compressed_length() {
+        let mut request = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        request
+            .post_data("/upload".to_string(), vec![b'a'; 4096])
+            .compress(crate::Encoding::Gzip);
+        let (headers, body) = built_post(&mut request);
+        let body_len = body.known_len().unwrap();
+        assert!(body_len < 4096);
+        assert_eq!(
+            header_value(&headers, b"content-encoding"),
+            Some(b"gzip".to_vec())
+        );
+        assert_eq!(
+            header_value(&headers, b"content-length"),
+            Some(body_len.to_string().into_bytes())
+        );
+    }
+
+    #[test]
+    fn compressed_file_is_streamed_without_length() {
+        let path = std::env::temp_dir().join(format!("upload-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, vec![b'a'; 4096]).unwrap();
+        let mut request = Http3RequestPrep::new(
+            Some(SocketAddr::from_str("127.0.0.1:3000").unwrap()),
+            uuid::Uuid::new_v4(),
+        );
+        request
+            .post_file("/upload".to_string(), &path)
+            .compress(crate::Encoding::Gzip);
+        let (headers, body) = built_post(&mut request);
+        std::fs::remove_file(&path).unwrap();
+        assert!(header_value(&headers, b"content-length").is_none());
+        assert!(body.known_len().is_none());
+    }
+
+    ///
+    ///A pipe-like reader : interrupted once, then its chunks, then EOF or `error`.
+    ///
+    struct ChunkedReader {
+        chunks: Vec<&'static [u8]>,
+        interrupted: bool,
+        error: Option<std::io::ErrorKind>,
+    }
+    impl std::io::Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if !self.interrupted {
+                self.interrupted = true;
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            if self.chunks.is_empty() {
+                return match self.error {
+                    Some(kind) => Err(kind.into()),
+                    None => Ok(0),
+                };
+            }
+            let chunk = self.chunks.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    fn next_request(
+        queue: &RequestQueue,
+    ) -> Option<(Http3Request, crossbeam::channel::Sender<std::time::Instant>)> {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while std::time::Instant::now() < deadline {
+            if let Some(request) = queue.pop_request() {
+                return Some(request);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn streamed_body_without_length_ends_at_eof() {
+        let channel = RequestChannel::new();
+        let queue = channel.get_queue();
+        channel.get_head().send_body(
+            4,
+            1024,
+            RequestBody::new_stream(Box::new(ChunkedReader {
+                chunks: vec![b"hello ", b"world"],
+                interrupted: false,
+                error: None,
+            })),
+            None,
+        );
+        let mut received = vec![];
+        loop {
+            match next_request(&queue) {
+                Some((Http3Request::Body(body), _)) => {
+                    received.extend_from_slice(body.data());
+                    if body.is_end() {
+                        break;
+                    }
+                }
+                other => panic!("unexpected [{:?}]", other),
+            }
+        }
+        assert_eq!(received, b"hello world");
+
+        channel.get_head().send_body(
+            8,
+            1024,
+            RequestBody::new_stream(Box::new(ChunkedReader {
+                chunks: vec![b"partial"],
+                interrupted: false,
+                error: Some(std::io::ErrorKind::BrokenPipe),
+            })),
+            None,
+        );
+        loop {
+            match next_request(&queue) {
+                Some((Http3Request::Body(body), _)) => assert!(!body.is_end()),
+                Some((Http3Request::ResetStream { stream_id }, _)) => {
+                    assert_eq!(stream_id, 8);
+                    break;
+                }
+                other => panic!("unexpected [{:?}]", other),
+            }
+        }
+    }
 }
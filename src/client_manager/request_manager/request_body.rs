@@ -19,6 +19,7 @@ mod content_type {
         AudioOgg,
         AudioFlac,
         Custom(String),
+        FormUrlEncoded,
         Zip,
         Rar,
         OctetStream,
@@ -40,6 +41,7 @@ mod content_type {
                 Self::AudioOgg => String::from("audio/ogg"),
                 Self::AudioFlac => String::from("audio/flac"),
                 Self::Custom(custom_type) => format!("custom/{}", custom_type),
+                Self::FormUrlEncoded => String::from("application/x-www-form-urlencoded"),
                 Self::Zip => String::from("application/zip"),
                 Self::Rar => String::from("application/rar"),
                 Self::OctetStream => String::from("application/octet-stream"),
@@ -59,9 +61,11 @@ mod rqst_body {
 
     use log::{error, warn};
 
+    use crate::client_manager::content_encoding::{compress_bytes, compress_reader, Encoding};
+
     pub enum RequestBody {
         Data(BufReader<Cursor<Vec<u8>>>),
-        File(BufReader<File>),
+        File(PathBuf, BufReader<File>),
         Stream(Box<dyn Read + Send + 'static>),
         Empty,
     }
@@ -91,24 +95,80 @@ mod rqst_body {
                     panic!("pb");
                 }
             };
-            RequestBody::File(BufReader::new(file))
+            RequestBody::File(path, BufReader::new(file))
         }
         pub fn new_stream(stream: Box<dyn Read + Send + 'static>) -> RequestBody {
             RequestBody::Stream(stream)
         }
+        ///
+        ///A fresh copy of the payload, read from its start, to send the request again. A stream
+        ///can only be read once, so it can't be replayed.
+        ///
+        pub fn try_clone(&self) -> Option<RequestBody> {
+            match self {
+                Self::Data(data) => Some(RequestBody::new_data(data.get_ref().get_ref().clone())),
+                Self::File(path, _) => match File::open(path.as_path()) {
+                    Ok(file) => Some(RequestBody::File(path.clone(), BufReader::new(file))),
+                    Err(e) => {
+                        warn!("Failed to reopen [{:?}] for replay [{:?}]", path, e);
+                        None
+                    }
+                },
+                Self::Stream(_) => None,
+                Self::Empty => Some(Self::Empty),
+            }
+        }
+        ///
+        ///The payload when it is held in memory.
+        ///
+        pub fn as_bytes(&self) -> Option<&[u8]> {
+            match self {
+                Self::Data(data) => Some(data.get_ref().get_ref()),
+                _ => None,
+            }
+        }
+        ///
+        ///Size of the payload when it can be known before sending. A `Stream` is read until EOF,
+        ///so its length is unknown and no `content-length` is announced for it.
+        ///
+        pub fn known_len(&self) -> Option<usize> {
+            match self {
+                Self::Stream(_) => None,
+                _ => Some(self.len()),
+            }
+        }
         pub fn len(&self) -> usize {
             match self {
                 Self::Data(data) => data.get_ref().get_ref().len(),
-                Self::File(file) => file.get_ref().metadata().unwrap().len() as usize,
+                Self::File(_, file) => file.get_ref().metadata().unwrap().len() as usize,
                 Self::Stream(stream) => 0,
                 Self::Empty => 0,
             }
         }
+        ///
+        ///Compress the payload with `encoding`. In-memory data is compressed at once and keeps a
+        ///known length; files and streams are wrapped in a streaming encoder and are sent
+        ///without `content-length`.
+        ///
+        pub fn compress(self, encoding: Encoding) -> Result<RequestBody, Error> {
+            match self {
+                Self::Data(data) => Ok(RequestBody::new_data(compress_bytes(
+                    data.into_inner().into_inner(),
+                    encoding,
+                )?)),
+                Self::File(_, file) => Ok(RequestBody::Stream(compress_reader(
+                    Box::new(file),
+                    encoding,
+                )?)),
+                Self::Stream(stream) => Ok(RequestBody::Stream(compress_reader(stream, encoding)?)),
+                Self::Empty => Ok(Self::Empty),
+            }
+        }
         pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
             match self {
                 Self::Empty => Err(Error::other("empty payload")),
                 Self::Data(data) => data.read(buf),
-                Self::File(file) => file.read(buf),
+                Self::File(_, file) => file.read(buf),
                 Self::Stream(stream) => stream.read(buf),
             }
         }
@@ -124,8 +184,8 @@ mod rqst_body {
                         false
                     }
                 }
-                Self::File(_) => {
-                    if let Self::File(_) = other {
+                Self::File(..) => {
+                    if let Self::File(..) = other {
                         true
                     } else {
                         false
@@ -0,0 +1,24 @@
+pub use trailers_trait::RequestTrailers;
+mod trailers_trait {
+    ///
+    ///Header fields sent after the body of a request, in an HTTP/3 trailer section. The
+    ///implementor sees every chunk of the body as it is sent, so it can compute a value over
+    ///the whole payload (a checksum of a streamed file...) without buffering it.
+    ///
+    pub trait RequestTrailers: Send + 'static {
+        fn on_body_chunk(&mut self, _chunk: &[u8]) {}
+        ///
+        ///Called once the last chunk has been handed to the connexion.
+        ///
+        fn trailers(self: Box<Self>) -> Vec<(String, String)>;
+    }
+
+    ///
+    ///Trailers known before the body is sent.
+    ///
+    impl RequestTrailers for Vec<(String, String)> {
+        fn trailers(self: Box<Self>) -> Vec<(String, String)> {
+            *self
+        }
+    }
+}
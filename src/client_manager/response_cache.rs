@@ -0,0 +1,502 @@
+pub use cache_layer::ResponseCache;
+pub use cache_storage::CacheStorage;
+
+mod cache_entry {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use quiche::h3::{self, NameValue};
+    use serde::{Deserialize, Serialize};
+
+    use crate::client_manager::response_manager::CompletedResponse;
+
+    ///
+    ///Directives of a `cache-control` response header that drive the cache.
+    ///
+    #[derive(Debug, Default, PartialEq)]
+    pub struct CacheControl {
+        pub no_store: bool,
+        pub no_cache: bool,
+        pub max_age: Option<u64>,
+    }
+
+    impl CacheControl {
+        pub fn parse(headers: &[h3::Header]) -> Self {
+            let mut cache_control = Self::default();
+            for hdr in headers.iter().filter(|hdr| hdr.name() == b"cache-control") {
+                for directive in String::from_utf8_lossy(hdr.value()).split(',') {
+                    let directive = directive.trim().to_ascii_lowercase();
+                    match directive.split_once('=') {
+                        Some(("max-age", value)) => {
+                            cache_control.max_age = value.trim_matches('"').parse::<u64>().ok()
+                        }
+                        Some(_) => {}
+                        None if directive == "no-store" => cache_control.no_store = true,
+                        None if directive == "no-cache" => cache_control.no_cache = true,
+                        None => {}
+                    }
+                }
+            }
+            cache_control
+        }
+    }
+
+    pub fn header_value(headers: &[h3::Header], name: &[u8]) -> Option<String> {
+        headers
+            .iter()
+            .find(|hdr| hdr.name() == name)
+            .map(|hdr| String::from_utf8_lossy(hdr.value()).to_string())
+    }
+
+    pub fn now_as_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0)
+    }
+
+    ///
+    ///A stored response with what is needed to decide on its freshness and to revalidate it.
+    ///The body is kept apart from the metadata when stored on disk.
+    ///
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct CachedResponse {
+        headers: Vec<(String, String)>,
+        #[serde(skip)]
+        pub data: Vec<u8>,
+        stored_at: u64,
+        max_age: Option<u64>,
+        no_cache: bool,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        #[serde(default)]
+        credentials: Option<String>,
+    }
+
+    impl CachedResponse {
+        ///
+        ///Build an entry from a 200 response, if the server allows it to be stored and gave
+        ///either a lifetime or a validator. `credentials` identifies the `authorization` and
+        ///`cookie` the request was sent with. A response varying on other request headers
+        ///than `accept-encoding` is not stored.
+        ///
+        pub fn from_response(
+            response: &CompletedResponse,
+            credentials: Option<String>,
+        ) -> Option<Self> {
+            let headers = response.headers();
+            let cache_control = CacheControl::parse(&headers);
+            let etag = header_value(&headers, b"etag");
+            let last_modified = header_value(&headers, b"last-modified");
+            let varies = headers
+                .iter()
+                .filter(|hdr| hdr.name() == b"vary")
+                .flat_map(|hdr| {
+                    String::from_utf8_lossy(hdr.value())
+                        .split(',')
+                        .map(|name| name.trim().to_ascii_lowercase())
+                        .collect::<Vec<_>>()
+                })
+                .any(|name| !name.is_empty() && name != "accept-encoding");
+
+            if cache_control.no_store
+                || varies
+                || (cache_control.max_age.is_none() && etag.is_none() && last_modified.is_none())
+            {
+                return None;
+            }
+            Some(Self {
+                // cookies were handed to the jar when the response came in, a cache hit must
+                // not set them again
+                headers: headers
+                    .iter()
+                    .filter(|hdr| hdr.name() != b"set-cookie")
+                    .map(|hdr| {
+                        (
+                            String::from_utf8_lossy(hdr.name()).to_string(),
+                            String::from_utf8_lossy(hdr.value()).to_string(),
+                        )
+                    })
+                    .collect(),
+                data: response.as_data().to_vec(),
+                stored_at: now_as_secs(),
+                max_age: cache_control.max_age,
+                no_cache: cache_control.no_cache,
+                etag,
+                last_modified,
+                credentials,
+            })
+        }
+        ///
+        ///The entry is only handed to requests sent with the credentials it was fetched with.
+        ///
+        pub fn is_for(&self, credentials: &Option<String>) -> bool {
+            &self.credentials == credentials
+        }
+        pub fn is_fresh(&self) -> bool {
+            if self.no_cache {
+                return false;
+            }
+            match self.max_age {
+                Some(max_age) => now_as_secs().saturating_sub(self.stored_at) < max_age,
+                None => false,
+            }
+        }
+        ///
+        ///A 304 confirmed the entry : restart its lifetime with the directives it carries.
+        ///
+        pub fn revalidated(&mut self, not_modified: &CompletedResponse) {
+            let headers = not_modified.headers();
+            let cache_control = CacheControl::parse(&headers);
+            self.stored_at = now_as_secs();
+            if cache_control.max_age.is_some() {
+                self.max_age = cache_control.max_age;
+            }
+            self.no_cache = cache_control.no_cache;
+            if let Some(etag) = header_value(&headers, b"etag") {
+                self.etag = Some(etag);
+            }
+        }
+        ///
+        ///Conditional headers for the revalidation request.
+        ///
+        pub fn validators(&self) -> Vec<(String, String)> {
+            let mut validators = vec![];
+            if let Some(etag) = &self.etag {
+                validators.push(("if-none-match".to_string(), etag.to_string()));
+            }
+            if let Some(last_modified) = &self.last_modified {
+                validators.push(("if-modified-since".to_string(), last_modified.to_string()));
+            }
+            validators
+        }
+        pub fn to_response(&self, stream_id: u64) -> CompletedResponse {
+            CompletedResponse::new(
+                stream_id,
+                self.headers
+                    .iter()
+                    .map(|(name, value)| h3::Header::new(name.as_bytes(), value.as_bytes()))
+                    .collect(),
+                self.data.clone(),
+            )
+        }
+    }
+}
+
+mod cache_storage {
+    use std::{
+        collections::HashMap,
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use log::error;
+
+    use super::cache_entry::CachedResponse;
+
+    ///
+    ///Where the cached responses live : in memory for the client lifetime, or in a directory
+    ///(one metadata file and one body file per entry) to survive restarts.
+    ///
+    #[derive(Clone)]
+    pub enum CacheStorage {
+        Memory(Arc<Mutex<HashMap<String, CachedResponse>>>),
+        Disk(PathBuf),
+    }
+
+    impl CacheStorage {
+        pub fn load(&self, key: &str) -> Option<CachedResponse> {
+            match self {
+                Self::Memory(table) => table.lock().unwrap().get(key).cloned(),
+                Self::Disk(dir) => {
+                    let file_name = disk_file_name(key);
+                    let meta = fs::read(dir.join(format!("{}.json", file_name))).ok()?;
+                    let mut entry = serde_json::from_slice::<CachedResponse>(&meta).ok()?;
+                    entry.data = fs::read(dir.join(format!("{}.body", file_name))).ok()?;
+                    Some(entry)
+                }
+            }
+        }
+        pub fn store(&self, key: &str, entry: CachedResponse) {
+            match self {
+                Self::Memory(table) => {
+                    table.lock().unwrap().insert(key.to_string(), entry);
+                }
+                Self::Disk(dir) => {
+                    let file_name = disk_file_name(key);
+                    let meta = match serde_json::to_vec(&entry) {
+                        Ok(meta) => meta,
+                        Err(e) => {
+                            error!("Failed to serialize cache entry [{}] [{:?}]", key, e);
+                            return;
+                        }
+                    };
+                    if let Err(e) = fs::write(dir.join(format!("{}.body", file_name)), &entry.data)
+                        .and_then(|_| fs::write(dir.join(format!("{}.json", file_name)), meta))
+                    {
+                        error!("Failed to write cache entry [{}] [{:?}]", key, e);
+                    }
+                }
+            }
+        }
+        pub fn remove(&self, key: &str) {
+            match self {
+                Self::Memory(table) => {
+                    table.lock().unwrap().remove(key);
+                }
+                Self::Disk(dir) => {
+                    let file_name = disk_file_name(key);
+                    let _ = fs::remove_file(dir.join(format!("{}.json", file_name)));
+                    let _ = fs::remove_file(dir.join(format!("{}.body", file_name)));
+                }
+            }
+        }
+    }
+
+    fn disk_file_name(key: &str) -> String {
+        ring::digest::digest(&ring::digest::SHA256, key.as_bytes())
+            .as_ref()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+mod cache_layer {
+    use std::{
+        collections::HashMap,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::client_manager::{
+        request_manager::{H3Method, Http3RequestBuilder},
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    use super::{cache_entry::CachedResponse, CacheStorage};
+
+    ///
+    ///Optional HTTP cache in front of the GET requests. Fresh entries are answered without
+    ///reaching the peer, stale ones are revalidated with `if-none-match` / `if-modified-since`
+    ///and a `304` is handed back to the caller as the cached full response.
+    ///
+    #[derive(Clone)]
+    pub struct ResponseCache {
+        storage: CacheStorage,
+    }
+
+    impl ResponseCache {
+        pub fn in_memory() -> Self {
+            Self {
+                storage: CacheStorage::Memory(Arc::new(Mutex::new(HashMap::new()))),
+            }
+        }
+        pub fn on_disk(dir: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+            std::fs::create_dir_all(dir.as_ref())?;
+            Ok(Self {
+                storage: CacheStorage::Disk(dir.as_ref().to_path_buf()),
+            })
+        }
+        pub fn clear(&self, authority_and_path: &str) {
+            self.storage.remove(authority_and_path);
+        }
+
+        ///
+        ///Serve the request from the cache when possible, otherwise send it with `dispatch` and
+        ///keep the response if it can be stored.
+        ///
+        pub fn send_through(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            dispatch: impl FnOnce(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()>,
+        ) -> Result<WaitPeerResponse, ()> {
+            let Some(key) = cache_key(http3_request_builder) else {
+                return dispatch(http3_request_builder);
+            };
+            let credentials = credentials(http3_request_builder);
+            let cached = self
+                .storage
+                .load(&key)
+                .filter(|entry| entry.is_for(&credentials));
+
+            if let Some(entry) = &cached {
+                if entry.is_fresh() {
+                    return Ok(WaitPeerResponse::ready(entry.to_response(0)));
+                }
+                for (name, value) in entry.validators() {
+                    http3_request_builder.set_header(name, value);
+                }
+            }
+
+            let storage = self.storage.clone();
+            Ok(dispatch(http3_request_builder)?.intercept(move |response| {
+                Ok(on_peer_response(
+                    &storage,
+                    &key,
+                    credentials,
+                    cached,
+                    response?,
+                ))
+            }))
+        }
+    }
+
+    fn on_peer_response(
+        storage: &CacheStorage,
+        key: &str,
+        credentials: Option<String>,
+        cached: Option<CachedResponse>,
+        response: CompletedResponse,
+    ) -> CompletedResponse {
+        match response.http_status() {
+            Some(304) => match cached {
+                Some(mut entry) => {
+                    entry.revalidated(&response);
+                    storage.store(key, entry.clone());
+                    entry.to_response(response.stream_id())
+                }
+                None => response,
+            },
+            Some(200) => {
+                match CachedResponse::from_response(&response, credentials) {
+                    Some(entry) => storage.store(key, entry),
+                    None => storage.remove(key),
+                }
+                response
+            }
+            _ => response,
+        }
+    }
+
+    ///
+    ///SHA-256 of the `authorization` and `cookie` headers of the request, None without them.
+    ///
+    fn credentials(http3_request_builder: &Http3RequestBuilder) -> Option<String> {
+        let authorization = http3_request_builder.header("authorization");
+        let cookie = http3_request_builder.header("cookie");
+        if authorization.is_none() && cookie.is_none() {
+            return None;
+        }
+        let credentials = format!(
+            "{}\n{}",
+            authorization.unwrap_or_default(),
+            cookie.unwrap_or_default()
+        );
+        Some(
+            ring::digest::digest(&ring::digest::SHA256, credentials.as_bytes())
+                .as_ref()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+        )
+    }
+
+    ///
+    ///Only GET requests for a whole resource are cached, keyed by authority and path (query
+    ///string included). An entry fetched with other credentials is ignored. A body streamed line by line is not kept, so it is not cached.
+    ///
+    fn cache_key(http3_request_builder: &Http3RequestBuilder) -> Option<String> {
+        match http3_request_builder.method() {
+            Some(H3Method::GET)
+                if http3_request_builder.header("range").is_none()
+                    && !http3_request_builder.has_line_sink() =>
+            {
+                Some(format!(
+                    "{}{}",
+                    http3_request_builder.authority()?,
+                    http3_request_builder.path_with_query()
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use quiche::h3::{self, NameValue};
+
+    use super::cache_entry::{CacheControl, CachedResponse};
+    use crate::client_manager::response_manager::CompletedResponse;
+
+    #[test]
+    fn cache_control_directives() {
+        let headers = vec![
+            h3::Header::new(b":status", b"200"),
+            h3::Header::new(b"cache-control", b"public, max-age=60, no-cache"),
+        ];
+        assert_eq!(
+            CacheControl::parse(&headers),
+            CacheControl {
+                no_store: false,
+                no_cache: true,
+                max_age: Some(60),
+            }
+        );
+    }
+
+    #[test]
+    fn storable_and_fresh_entries() {
+        let fresh = CompletedResponse::new(
+            0,
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"cache-control", b"max-age=60"),
+                h3::Header::new(b"etag", b"\"v1\""),
+            ],
+            b"avatar".to_vec(),
+        );
+        let entry = CachedResponse::from_response(&fresh, None).unwrap();
+        assert!(entry.is_fresh());
+        assert_eq!(
+            entry.validators(),
+            vec![("if-none-match".to_string(), "\"v1\"".to_string())]
+        );
+        assert_eq!(entry.to_response(3).as_data(), b"avatar");
+
+        let with_cookie = CompletedResponse::new(
+            0,
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"cache-control", b"max-age=60"),
+                h3::Header::new(b"set-cookie", b"session=abc"),
+            ],
+            vec![],
+        );
+        let entry = CachedResponse::from_response(&with_cookie, None).unwrap();
+        assert!(!entry
+            .to_response(5)
+            .headers()
+            .iter()
+            .any(|hdr| hdr.name() == b"set-cookie"));
+
+        let no_store = CompletedResponse::new(
+            0,
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"cache-control", b"no-store, max-age=60"),
+            ],
+            vec![],
+        );
+        assert!(CachedResponse::from_response(&no_store, None).is_none());
+
+        let by_language = CompletedResponse::new(
+            0,
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"cache-control", b"max-age=60"),
+                h3::Header::new(b"vary", b"Accept-Encoding, Accept-Language"),
+            ],
+            vec![],
+        );
+        assert!(CachedResponse::from_response(&by_language, None).is_none());
+
+        let private = CachedResponse::from_response(&fresh, Some("alice".to_string())).unwrap();
+        assert!(private.is_for(&Some("alice".to_string())));
+        assert!(!private.is_for(&Some("bob".to_string())));
+        assert!(!private.is_for(&None));
+    }
+}
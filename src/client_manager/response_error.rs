@@ -0,0 +1,56 @@
+pub use error::ResponseError;
+
+mod error {
+    use std::fmt::Display;
+
+    ///
+    ///Why WaitPeerResponse::wait_response() has no response to give.
+    ///
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ResponseError {
+        ///
+        ///The request could not be sent, or its stream or connexion was closed before the
+        ///response was complete.
+        ///
+        Disconnected,
+        ///
+        ///The body received doesn't match the `content-digest` announced by the peer.
+        ///
+        DigestMismatch { expected: String, received: String },
+        ///
+        ///The public key of the peer certificate matches none of the pins of the ClientConfig,
+        ///the connexion was closed.
+        ///
+        PinningFailed,
+        ///
+        ///The body could not be decoded from its `content-encoding` : it is truncated or
+        ///corrupted.
+        ///
+        Decoding { encoding: String, reason: String },
+    }
+
+    impl Display for ResponseError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::Disconnected => write!(f, "response stream closed before completion"),
+                Self::DigestMismatch { expected, received } => write!(
+                    f,
+                    "content-digest mismatch : expected [{}] received [{}]",
+                    expected, received
+                ),
+                Self::PinningFailed => write!(f, "peer public key matches no pinned key"),
+                Self::Decoding { encoding, reason } => {
+                    write!(f, "failed decoding [{}] body : {}", encoding, reason)
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for ResponseError {}
+
+    impl From<crossbeam::channel::RecvError> for ResponseError {
+        fn from(_: crossbeam::channel::RecvError) -> Self {
+            Self::Disconnected
+        }
+    }
+}
@@ -1,7 +1,8 @@
-pub use queue_builder::{ResponseChannel, ResponseHead, ResponseQueue};
+pub use queue_builder::{ResponseChannel, ResponseEvent, ResponseHead, ResponseQueue};
 pub use response_builder::PartialResponse;
 pub use response_builder::{
-    DownloadProgressStatus, Http3Response, ReqStatus, UploadProgressStatus, WaitPeerResponse,
+    CompletedResponse, DownloadProgressStatus, Http3Response, ReqStatus, UploadProgressStatus,
+    WaitPeerResponse,
 };
 pub use response_mngr::ResponseManager;
 
@@ -85,15 +86,30 @@ mod response_mngr {
 mod queue_builder {
     use super::*;
 
+    ///
+    ///What the client sends to the response manager : a packet of one stream, or a change of
+    ///the whole connexion.
+    ///
+    #[derive(Debug)]
+    pub enum ResponseEvent {
+        Stream(Http3Response),
+        ///
+        ///The connexion is closed : none of its pending requests will get a response.
+        ///
+        ConnexionClosed {
+            connexion_id: String,
+        },
+    }
+
     #[derive(Clone)]
     pub struct ResponseChannel {
         channel: (
-            crossbeam::channel::Sender<Http3Response>,
-            crossbeam::channel::Receiver<Http3Response>,
+            crossbeam::channel::Sender<ResponseEvent>,
+            crossbeam::channel::Receiver<ResponseEvent>,
         ),
     }
     pub struct ResponseHead {
-        head: crossbeam::channel::Sender<Http3Response>,
+        head: crossbeam::channel::Sender<ResponseEvent>,
     }
     impl ResponseHead {
         ///
@@ -102,8 +118,16 @@ mod queue_builder {
         pub fn send_response(
             &self,
             response: Http3Response,
-        ) -> Result<(), crossbeam::channel::SendError<Http3Response>> {
-            self.head.send(response)
+        ) -> Result<(), crossbeam::channel::SendError<ResponseEvent>> {
+            self.head.send(ResponseEvent::Stream(response))
+        }
+        pub fn notify_connexion_closed(
+            &self,
+            connexion_id: &str,
+        ) -> Result<(), crossbeam::channel::SendError<ResponseEvent>> {
+            self.head.send(ResponseEvent::ConnexionClosed {
+                connexion_id: connexion_id.to_string(),
+            })
         }
     }
     impl Clone for ResponseHead {
@@ -119,10 +143,10 @@ mod queue_builder {
     ///
     ///
     pub struct ResponseQueue {
-        queue: crossbeam::channel::Receiver<Http3Response>,
+        queue: crossbeam::channel::Receiver<ResponseEvent>,
     }
     impl ResponseQueue {
-        pub fn pop_response(&self) -> Result<Http3Response, crossbeam::channel::RecvError> {
+        pub fn pop_response(&self) -> Result<ResponseEvent, crossbeam::channel::RecvError> {
             self.queue.recv()
         }
     }
@@ -158,7 +182,7 @@ mod response_builder {
     use std::{
         arch::x86_64::_mm_sfence,
         fmt::{Debug, Display},
-        sync::Arc,
+        sync::{Arc, Mutex},
         time::Duration,
         usize,
     };
@@ -168,27 +192,44 @@ mod response_builder {
     use notify_rust::Notification;
     use quiche::h3::{self, Header, NameValue};
     use serde::{Deserialize, Serialize};
-    use stream_framer::{FrameParser, ParsedStreamData};
     use uuid::Uuid;
 
-    use crate::{client_manager::persistant_stream::StreamSub, my_log, RequestEventListener};
+    use crate::{
+        client_manager::{
+            content_digest::DigestCheck,
+            content_encoding::{ContentDecoder, Encoding},
+            ndjson::{split_lines, LineSink},
+            persistant_stream::StreamSub,
+            response_error::ResponseError,
+            status_code::{StatusCode, StatusError},
+            stream_framing::{DefaultFramer, StreamFramer},
+        },
+        my_log, RequestEventListener,
+    };
 
-    use self::partial_response_impl::{handle_down_stream, respond_once};
+    use self::partial_response_impl::{handle_down_stream, handle_event_stream, respond_once};
 
+    ///
+    ///Bytes of the response body received so far. Without `content-length`, the total and the
+    ///percentage are unknown and only the received bytes are reported.
+    ///
     pub struct DownloadProgressStatus {
         req_path: String,
         request_uuid: Uuid,
-        progress: f32,
-        total: usize,
+        progress: Option<f32>,
+        total: Option<usize>,
         received: usize,
     }
     impl Display for DownloadProgressStatus {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(
-                f,
-                "percentage_completed [{}] || written/total [{}/{}]",
-                self.progress, self.received, self.total
-            )
+            match (self.progress, self.total) {
+                (Some(progress), Some(total)) => write!(
+                    f,
+                    "percentage_completed [{}] || written/total [{}/{}]",
+                    progress, self.received, total
+                ),
+                _ => write!(f, "received [{}] bytes", self.received),
+            }
         }
     }
     impl DownloadProgressStatus {
@@ -196,19 +237,12 @@ mod response_builder {
             req_path: &str,
             request_uuid: Uuid,
             received: usize,
-            total: usize,
-            percentage_completed: f32,
+            total: Option<usize>,
         ) -> Self {
-            let value = if percentage_completed > 1.0 {
-                1.0
-            } else {
-                percentage_completed
-            };
-
             Self {
                 req_path: req_path.to_string(),
                 request_uuid,
-                progress: value,
+                progress: total.map(|total| completion_ratio(received, total)),
                 total,
                 received,
             }
@@ -219,11 +253,30 @@ mod response_builder {
         pub fn req_path(&self) -> String {
             self.req_path.to_owned()
         }
-        pub fn progress(&self) -> f32 {
-            self.progress as f32
+        ///
+        ///Completion between 0.0 and 1.0, None if the body length is unknown.
+        ///
+        pub fn progress(&self) -> Option<f32> {
+            self.progress
+        }
+        pub fn received(&self) -> usize {
+            self.received
+        }
+        pub fn total(&self) -> Option<usize> {
+            self.total
         }
     }
 
+    ///
+    ///`received / total`, capped to 1.0. An empty body is complete.
+    ///
+    pub fn completion_ratio(received: usize, total: usize) -> f32 {
+        if total == 0 {
+            return 1.0;
+        }
+        (received as f32 / total as f32).min(1.0)
+    }
+
     #[derive(Clone)]
     pub struct UploadProgressStatus {
         request_uuid: Uuid,
@@ -310,11 +363,14 @@ mod response_builder {
         }
     }
 
+    #[derive(Clone)]
     pub struct CompletedResponse {
         headers: Vec<h3::Header>,
         #[allow(warnings)]
         stream_id: u64,
         data: Vec<u8>,
+        informational: Vec<Vec<h3::Header>>,
+        trailers: Vec<h3::Header>,
     }
     impl Display for CompletedResponse {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -327,12 +383,31 @@ mod response_builder {
         }
     }
 
+    ///
+    ///Response sorted by the class of its status : 2xx Success, 3xx Redirect, 4xx ClientError,
+    ///5xx ServerError. Error for a status that can't be read, None without status.
+    ///
     pub enum ReqStatus {
         Success {
             stream_id: u64,
             headers: Vec<h3::Header>,
             data: Option<Vec<u8>>,
         },
+        Redirect {
+            stream_id: u64,
+            headers: Vec<h3::Header>,
+            data: Option<Vec<u8>>,
+        },
+        ClientError {
+            stream_id: u64,
+            headers: Vec<h3::Header>,
+            data: Option<Vec<u8>>,
+        },
+        ServerError {
+            stream_id: u64,
+            headers: Vec<h3::Header>,
+            data: Option<Vec<u8>>,
+        },
         Error {
             stream_id: u64,
             headers: Vec<h3::Header>,
@@ -341,17 +416,57 @@ mod response_builder {
         None,
     }
 
+    impl ReqStatus {
+        pub fn status_code(&self) -> Option<StatusCode> {
+            match self {
+                Self::Success { headers, .. }
+                | Self::Redirect { headers, .. }
+                | Self::ClientError { headers, .. }
+                | Self::ServerError { headers, .. }
+                | Self::Error { headers, .. } => StatusCode::from_headers(headers),
+                Self::None => None,
+            }
+        }
+    }
+
     impl CompletedResponse {
         pub fn new(stream_id: u64, headers: Vec<h3::Header>, data: Vec<u8>) -> Self {
             Self {
                 headers,
                 stream_id,
                 data,
+                informational: vec![],
+                trailers: vec![],
             }
         }
+        pub fn with_informational(mut self, informational: Vec<Vec<h3::Header>>) -> Self {
+            self.informational = informational;
+            self
+        }
+        pub fn with_trailers(mut self, trailers: Vec<h3::Header>) -> Self {
+            self.trailers = trailers;
+            self
+        }
+        ///
+        ///The header fields sent by the peer after the body (checksums, final status of a
+        ///streamed response...). Empty if the response has no trailer section.
+        ///
+        pub fn trailers(&self) -> &[h3::Header] {
+            &self.trailers
+        }
+        ///
+        ///The 1xx header blocks received before the final response (103 Early Hints...), in
+        ///reception order.
+        ///
+        pub fn informational_headers(&self) -> &[Vec<h3::Header>] {
+            &self.informational
+        }
         pub fn as_data(&self) -> &[u8] {
             &self.data
         }
+        pub fn stream_id(&self) -> u64 {
+            self.stream_id
+        }
         pub fn status_code(&self) -> Option<Vec<u8>> {
             if let Some(status) = self.headers.iter().find(|hdr| hdr.name() == b":status") {
                 Some(status.value().to_vec())
@@ -359,34 +474,74 @@ mod response_builder {
                 None
             }
         }
+        ///
+        ///The `:status` of the response as a number.
+        ///
+        pub fn http_status(&self) -> Option<u16> {
+            self.http_status_code().map(|status| status.as_u16())
+        }
         pub fn raw_data(&mut self) -> Vec<u8> {
             std::mem::replace(&mut self.data, Vec::with_capacity(1))
         }
+        ///
+        ///The `:status` of the response as a StatusCode, None if missing or out of range.
+        ///
+        pub fn http_status_code(&self) -> Option<StatusCode> {
+            StatusCode::from_headers(&self.headers)
+        }
+        ///
+        ///Take the response, sorted by its status class. A status that can't be read, or an
+        ///informational status as final response, is an Error.
+        ///
         pub fn status(&mut self) -> ReqStatus {
-            if let Some(status) = self.headers.iter().find(|hdr| hdr.name() == b":status") {
-                if status.value() == b"200" || status.value() == b"201" {
-                    ReqStatus::Success {
-                        stream_id: self.stream_id,
-                        headers: std::mem::replace(&mut self.headers, vec![]),
-                        data: if self.data.is_empty() {
-                            None
-                        } else {
-                            Some(std::mem::replace(&mut self.data, vec![]))
-                        },
-                    }
-                } else {
-                    ReqStatus::Error {
-                        stream_id: self.stream_id,
-                        headers: std::mem::replace(&mut self.headers, vec![]),
-                        data: if self.data.is_empty() {
-                            None
-                        } else {
-                            Some(std::mem::replace(&mut self.data, vec![]))
-                        },
-                    }
-                }
+            if !self.headers.iter().any(|hdr| hdr.name() == b":status") {
+                return ReqStatus::None;
+            }
+            let status_code = self.http_status_code();
+            let stream_id = self.stream_id;
+            let headers = std::mem::take(&mut self.headers);
+            let data = if self.data.is_empty() {
+                None
             } else {
-                ReqStatus::None
+                Some(std::mem::take(&mut self.data))
+            };
+            match status_code {
+                Some(status) if status.is_success() => ReqStatus::Success {
+                    stream_id,
+                    headers,
+                    data,
+                },
+                Some(status) if status.is_redirect() => ReqStatus::Redirect {
+                    stream_id,
+                    headers,
+                    data,
+                },
+                Some(status) if status.is_client_error() => ReqStatus::ClientError {
+                    stream_id,
+                    headers,
+                    data,
+                },
+                Some(status) if status.is_server_error() => ReqStatus::ServerError {
+                    stream_id,
+                    headers,
+                    data,
+                },
+                _ => ReqStatus::Error {
+                    stream_id,
+                    headers,
+                    data,
+                },
+            }
+        }
+        ///
+        ///Ok with the response, or Err for a 4xx / 5xx status.
+        ///
+        pub fn error_for_status(self) -> Result<CompletedResponse, StatusError> {
+            match self.http_status_code() {
+                Some(status) if status.is_client_error() || status.is_server_error() => {
+                    Err(StatusError::new(status, self))
+                }
+                _ => Ok(self),
             }
         }
         pub fn has_body(&self) -> bool {
@@ -430,6 +585,14 @@ mod response_builder {
     pub enum Http3Response {
         Header(Http3ResponseHeader),
         Body(Http3ResponseBody),
+        ///
+        ///The peer reset the stream : no response will come for this request.
+        ///
+        Reset {
+            stream_id: u64,
+            connexion_id: String,
+            error_code: u64,
+        },
     }
     impl Debug for Http3Response {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -451,6 +614,14 @@ mod response_builder {
                         header.headers()
                     )
                 }
+                Self::Reset {
+                    stream_id,
+                    error_code,
+                    ..
+                } => write!(
+                    f,
+                    "reset stream_id [{stream_id}] : error code [{error_code}]"
+                ),
             }
         }
     }
@@ -485,6 +656,7 @@ mod response_builder {
             match self {
                 Self::Header(header) => header.stream_id % 4 == 3,
                 Self::Body(body) => body.stream_id % 4 == 3,
+                Self::Reset { .. } => false,
             }
         }
         /// Create new header response type.
@@ -508,18 +680,25 @@ mod response_builder {
                     (headers.stream_id, headers.connexion_id.to_owned())
                 }
                 Http3Response::Body(body) => (body.stream_id, body.connexion_id.to_owned()),
+                Http3Response::Reset {
+                    stream_id,
+                    connexion_id,
+                    ..
+                } => (*stream_id, connexion_id.to_owned()),
             }
         }
         pub fn stream_id(&self) -> u64 {
             match self {
                 Http3Response::Header(headers) => headers.stream_id,
                 Http3Response::Body(body) => body.stream_id,
+                Http3Response::Reset { stream_id, .. } => *stream_id,
             }
         }
         pub fn connexion_id(&self) -> &String {
             match self {
                 Http3Response::Header(headers) => &headers.connexion_id,
                 Http3Response::Body(body) => &body.connexion_id,
+                Http3Response::Reset { connexion_id, .. } => connexion_id,
             }
         }
         pub fn headers(&self) -> Option<Vec<h3::Header>> {
@@ -530,6 +709,7 @@ mod response_builder {
                 }
 
                 Http3Response::Body(_) => None,
+                Http3Response::Reset { .. } => None,
             }
         }
         pub fn packet(&self) -> Option<&[u8]> {
@@ -540,18 +720,21 @@ mod response_builder {
                 }
 
                 Http3Response::Body(body) => Some(&body.packet[..]),
+                Http3Response::Reset { .. } => None,
             }
         }
         pub fn len(&self) -> Option<usize> {
             match self {
                 Http3Response::Header(_) => None,
                 Http3Response::Body(body) => Some(body.packet.len()),
+                Http3Response::Reset { .. } => None,
             }
         }
         pub fn is_end(&self) -> bool {
             match self {
                 Http3Response::Header(headers) => headers.end,
                 Http3Response::Body(body) => body.end,
+                Http3Response::Reset { .. } => true,
             }
         }
     }
@@ -659,17 +842,27 @@ mod response_builder {
         }
     }
 
+    type Interceptor = Box<
+        dyn FnOnce(
+                Result<CompletedResponse, ResponseError>,
+            ) -> Result<CompletedResponse, ResponseError>
+            + Send,
+    >;
+
     #[allow(warnings)]
     pub struct WaitPeerResponse {
         stream_id: u64,
         connexion_id: String,
-        response_channel: crossbeam::channel::Receiver<CompletedResponse>,
+        response_channel: crossbeam::channel::Receiver<Result<CompletedResponse, ResponseError>>,
         progress_channel: crossbeam::channel::Receiver<UploadProgressStatus>,
+        interceptor: Mutex<Option<Interceptor>>,
     }
     impl WaitPeerResponse {
         pub fn new(
             stream_ids: &(u64, String),
-            response_channel: crossbeam::channel::Receiver<CompletedResponse>,
+            response_channel: crossbeam::channel::Receiver<
+                Result<CompletedResponse, ResponseError>,
+            >,
             progress_channel: crossbeam::channel::Receiver<UploadProgressStatus>,
         ) -> WaitPeerResponse {
             WaitPeerResponse {
@@ -677,6 +870,7 @@ mod response_builder {
                 connexion_id: stream_ids.1.to_owned(),
                 response_channel,
                 progress_channel,
+                interceptor: Mutex::new(None),
             }
         }
         ///
@@ -703,11 +897,78 @@ mod response_builder {
                 .unwrap();
             self
         }
-        pub fn wait_response(&self) -> Result<CompletedResponse, crossbeam::channel::RecvError> {
-            self.response_channel.recv()
+        pub fn wait_response(&self) -> Result<CompletedResponse, ResponseError> {
+            let response = self
+                .response_channel
+                .recv()
+                .unwrap_or_else(|e| Err(e.into()));
+            match self.interceptor.lock().unwrap().take() {
+                Some(interceptor) => interceptor(response),
+                None => response,
+            }
+        }
+        ///
+        ///A response that is already available, without any request sent to the peer (e.g.
+        ///served from a cache).
+        ///
+        pub fn ready(response: CompletedResponse) -> WaitPeerResponse {
+            let (response_sender, response_channel) = crossbeam::channel::bounded(1);
+            let (_, progress_channel) = crossbeam::channel::bounded(1);
+            let stream_id = response.stream_id;
+            if let Err(e) = response_sender.send(Ok(response)) {
+                debug!("Error: Failed to prepare ready response [{:?}]", e);
+            }
+            WaitPeerResponse {
+                stream_id,
+                connexion_id: String::new(),
+                response_channel,
+                progress_channel,
+                interceptor: Mutex::new(None),
+            }
+        }
+        ///
+        ///A request that failed before reaching the peer : wait_response() returns `error`.
+        ///
+        pub fn failed(error: ResponseError) -> WaitPeerResponse {
+            let (response_sender, response_channel) = crossbeam::channel::bounded(1);
+            let (_, progress_channel) = crossbeam::channel::bounded(1);
+            if let Err(e) = response_sender.send(Err(error)) {
+                debug!("Error: Failed to prepare failed response [{:?}]", e);
+            }
+            WaitPeerResponse {
+                stream_id: 0,
+                connexion_id: String::new(),
+                response_channel,
+                progress_channel,
+                interceptor: Mutex::new(None),
+            }
+        }
+        ///
+        ///Run `interceptor` on the peer response before wait_response() returns it, after the
+        ///interceptors already set. They run on the thread calling wait_response(), so nothing
+        ///is intercepted for a response that is never waited for.
+        ///
+        pub fn intercept(
+            self,
+            interceptor: impl FnOnce(
+                    Result<CompletedResponse, ResponseError>,
+                ) -> Result<CompletedResponse, ResponseError>
+                + Send
+                + 'static,
+        ) -> WaitPeerResponse {
+            let interceptor: Interceptor = match self.interceptor.into_inner().unwrap() {
+                Some(previous) => Box::new(move |response| interceptor(previous(response))),
+                None => Box::new(interceptor),
+            };
+            WaitPeerResponse {
+                stream_id: self.stream_id,
+                connexion_id: self.connexion_id,
+                response_channel: self.response_channel,
+                progress_channel: self.progress_channel,
+                interceptor: Mutex::new(Some(interceptor)),
+            }
         }
     }
-    type MessageLen = usize;
     pub struct PartialResponse {
         stream_id: u64,
         connexion_id: String,
@@ -720,13 +981,21 @@ mod response_builder {
         stream_body_len: Option<Vec<usize>>,
         stream_data: Vec<Vec<u8>>,
         stream_message_count: usize,
-        incompleted_stream_data_buffer: Option<(MessageLen, Vec<u8>)>,
-        truncated_header_buffer: Option<Vec<u8>>,
+        framer: Box<dyn StreamFramer>,
         data: Vec<u8>,
+        received_len: usize,
+        decompress: bool,
+        head_request: bool,
+        decoder: Option<ContentDecoder>,
+        decoding_error: Option<ResponseError>,
+        digest_check: Option<DigestCheck>,
+        line_sink: Option<LineSink>,
+        informational: Vec<Vec<h3::Header>>,
+        trailers: Vec<h3::Header>,
         packet_count: usize,
         response_channel: (
-            crossbeam::channel::Sender<CompletedResponse>,
-            crossbeam::channel::Receiver<CompletedResponse>,
+            crossbeam::channel::Sender<Result<CompletedResponse, ResponseError>>,
+            crossbeam::channel::Receiver<Result<CompletedResponse, ResponseError>>,
         ),
         progress_channel: (
             crossbeam::channel::Sender<UploadProgressStatus>,
@@ -749,7 +1018,7 @@ mod response_builder {
             stream_ids: &(u64, String),
         ) -> (
             Self,
-            crossbeam::channel::Receiver<CompletedResponse>,
+            crossbeam::channel::Receiver<Result<CompletedResponse, ResponseError>>,
             crossbeam::channel::Receiver<UploadProgressStatus>,
         ) {
             let request_uuid = Uuid::new_v4();
@@ -763,12 +1032,20 @@ mod response_builder {
                 headers: None,
                 content_length: None,
                 stream_body_len: None,
-                incompleted_stream_data_buffer: None,
-                truncated_header_buffer: None,
+                framer: Box::new(DefaultFramer::new()),
                 stream_message_count: 0,
                 packet_count: 0,
                 stream_data: vec![],
                 data: vec![],
+                received_len: 0,
+                decompress: true,
+                head_request: false,
+                decoder: None,
+                decoding_error: None,
+                digest_check: None,
+                line_sink: None,
+                informational: vec![],
+                trailers: vec![],
                 response_channel: crossbeam::channel::bounded(1),
                 progress_channel: crossbeam::channel::bounded(1),
             };
@@ -791,7 +1068,7 @@ mod response_builder {
             stream_ids: &(u64, String),
         ) -> (
             Self,
-            crossbeam::channel::Receiver<CompletedResponse>,
+            crossbeam::channel::Receiver<Result<CompletedResponse, ResponseError>>,
             crossbeam::channel::Receiver<UploadProgressStatus>,
         ) {
             let request_uuid = Uuid::new_v4();
@@ -805,12 +1082,20 @@ mod response_builder {
                 headers: None,
                 content_length: None,
                 stream_body_len: None,
-                incompleted_stream_data_buffer: None,
-                truncated_header_buffer: None,
+                framer: Box::new(DefaultFramer::new()),
                 packet_count: 0,
                 stream_message_count: 0,
                 stream_data: vec![],
                 data: vec![],
+                received_len: 0,
+                decompress: true,
+                head_request: false,
+                decoder: None,
+                decoding_error: None,
+                digest_check: None,
+                line_sink: None,
+                informational: vec![],
+                trailers: vec![],
                 response_channel: crossbeam::channel::bounded(1),
                 progress_channel: crossbeam::channel::bounded(1),
             };
@@ -824,50 +1109,185 @@ mod response_builder {
         }
         // return a possible collection of completed messages.
         pub fn start_new_body_reception(&mut self, data: Vec<u8>) -> Option<Vec<Vec<u8>>> {
-            // One new body can contains multiples Header + message
-            //
-
-            let mut output: Vec<Vec<u8>> = vec![];
-
-            match data.parse_frame_header(
-                self.incompleted_stream_data_buffer.take(),
-                self.truncated_header_buffer.take(),
-            ) {
-                Ok(parsing_res) => {
-                    for parsed in parsing_res {
-                        match parsed {
-                            ParsedStreamData::CompletedWithHeader(msg_size, data) => {
-                                output.push(data);
-                            }
-                            ParsedStreamData::IncompleteWithHeader(msg_size, data) => {
-                                // this has to be the last
-                                // reserve for the next packet
-                                self.incompleted_stream_data_buffer = Some((msg_size, data));
-                            }
-                            ParsedStreamData::IncompleteWithoutHeaderUnFinished(data) => {
-                                // case when this packet is smaller than the message size
-                            }
-                            ParsedStreamData::TruncatedHeader(truncated_header) => {
-                                // truncated header
-                                match &self.truncated_header_buffer {
-                                    Some(_partial_hdr) => {}
-                                    None => self.truncated_header_buffer = Some(truncated_header),
-                                }
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("[{:?}]", e);
-                }
-            }
-
+            let output = self.framer.push(&data);
             if output.is_empty() {
                 None
             } else {
                 Some(output)
             }
         }
+        ///
+        ///Frame the body of a persistent stream with `framer` instead of the stream_framer
+        ///format.
+        ///
+        pub fn set_framer(&mut self, framer: Box<dyn StreamFramer>) {
+            self.framer = framer;
+        }
+        ///
+        ///When false, a `content-encoding` response body is handed back as received.
+        ///
+        pub fn set_decompress(&mut self, decompress: bool) {
+            self.decompress = decompress;
+        }
+        ///
+        ///The response of a HEAD has no body : its `content-digest` is not checked.
+        ///
+        pub fn set_head_request(&mut self, head_request: bool) {
+            self.head_request = head_request;
+        }
+        ///
+        ///Lines of a 2xx body go to `line_sink` as they are received, the CompletedResponse
+        ///then only holds what wasn't handed over.
+        ///
+        pub fn set_line_sink(&mut self, line_sink: Option<LineSink>) {
+            self.line_sink = line_sink;
+        }
+        ///
+        ///Hand the complete lines received so far to the line sink, and the last unterminated
+        ///one with `flush`. The body of an error status is kept whole.
+        ///
+        fn hand_over_lines(&mut self, flush: bool) {
+            let Some(line_sink) = self.line_sink.as_ref() else {
+                return;
+            };
+            let success = StatusCode::from_headers(self.headers.as_deref().unwrap_or_default())
+                .map_or(false, |status| status.is_success());
+            if success {
+                split_lines(&mut self.data, line_sink.as_ref(), flush);
+            }
+            if flush {
+                // releases the receiving side of the lines
+                self.line_sink = None;
+            }
+        }
+        ///
+        ///Stack a body packet, decoding it first if the response has a supported
+        ///`content-encoding`.
+        ///
+        fn push_body_data(&mut self, packet: &[u8]) {
+            self.received_len += packet.len();
+            if let Some(digest_check) = self.digest_check.as_mut() {
+                digest_check.update(packet);
+            }
+            if self.decoding_error.is_some() {
+                // the rest of a body that can't be decoded is dropped
+                return;
+            }
+            match self.decoder.as_mut() {
+                Some(decoder) => match decoder.decode(packet) {
+                    Ok(decoded) => self.data.extend(decoded),
+                    Err(e) => {
+                        error!(
+                            "Failed decoding [{}] body on stream_id [{}] -> [{:?}]",
+                            decoder.encoding().as_str(),
+                            self.stream_id,
+                            e
+                        );
+                        self.decoding_error = Some(ResponseError::Decoding {
+                            encoding: decoder.encoding().as_str().to_string(),
+                            reason: e.to_string(),
+                        });
+                    }
+                },
+                None => self.data.extend_from_slice(packet),
+            }
+        }
+        fn finish_body_data(&mut self) {
+            if self.decoding_error.is_some() {
+                return;
+            }
+            if let Some(decoder) = self.decoder.as_mut() {
+                match decoder.finish() {
+                    Ok(decoded) => self.data.extend(decoded),
+                    Err(e) => {
+                        error!(
+                            "Failed decoding [{}] body end on stream_id [{}] -> [{:?}]",
+                            decoder.encoding().as_str(),
+                            self.stream_id,
+                            e
+                        );
+                        self.decoding_error = Some(ResponseError::Decoding {
+                            encoding: decoder.encoding().as_str().to_string(),
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        ///
+        ///A 1xx header block : kept for the CompletedResponse. A `100` carrying `x-progress` is
+        ///the peer reporting how much of the upload it received.
+        ///
+        fn on_informational_headers(&mut self, headers: Http3ResponseHeader) {
+            debug!("Informational headers [{:?}]", headers.headers());
+            let is_continue = StatusCode::from_headers(headers.headers())
+                .map_or(false, |status| status.as_u16() == 100);
+            if let Some(progress) = headers
+                .headers()
+                .iter()
+                .find(|hdr| hdr.name() == b"x-progress")
+                .filter(|_| is_continue)
+            {
+                if let Ok(len) = String::from_utf8_lossy(progress.value()).parse::<usize>() {
+                    if let Err(e) = self.progress_channel.0.send(UploadProgressStatus::new(
+                        self.req_path.as_str(),
+                        self.request_uuid,
+                        len,
+                        0,
+                        0.0,
+                    )) {
+                        debug!(
+                            "Error: Failed sending progress status for stream_id [{}] -> [{:?}]",
+                            headers.stream_id(),
+                            e
+                        );
+                    }
+                } else {
+                    error!("Failed parsing hdr value : not a digit");
+                }
+                return;
+            }
+            self.informational.push(headers.headers().to_vec());
+        }
+        ///
+        ///The response once its body is complete. Fails if the body could not be decoded or
+        ///doesn't match the `content-digest` of the peer.
+        ///
+        fn completed_response(
+            &mut self,
+            data: Vec<u8>,
+        ) -> Result<CompletedResponse, ResponseError> {
+            if let Some(e) = self.decoding_error.take() {
+                error!("Response to [{}] rejected : {}", self.req_path, e);
+                return Err(e);
+            }
+            if let Some(digest_check) = self.digest_check.take() {
+                let headers = self.headers.as_deref().unwrap_or_default();
+                if let Err(e) = digest_check.verify(headers, &self.trailers) {
+                    error!("Response to [{}] rejected : {}", self.req_path, e);
+                    return Err(e);
+                }
+            }
+            Ok(
+                CompletedResponse::new(self.stream_id, self.take_completed_headers(), data)
+                    .with_informational(std::mem::take(&mut self.informational))
+                    .with_trailers(std::mem::take(&mut self.trailers)),
+            )
+        }
+        ///
+        ///Headers handed to the CompletedResponse. Once the body has been decoded,
+        ///`content-encoding` and `content-length` no longer describe it and are removed.
+        ///
+        fn take_completed_headers(&mut self) -> Vec<h3::Header> {
+            let headers = std::mem::replace(self.headers.as_mut().unwrap(), Vec::with_capacity(1));
+            if self.decoder.is_none() {
+                return headers;
+            }
+            headers
+                .into_iter()
+                .filter(|hdr| hdr.name() != b"content-encoding" && hdr.name() != b"content-length")
+                .collect()
+        }
         pub fn _set_content_length(&mut self, content_length: usize) {
             self.content_length = Some(content_length);
         }
@@ -909,43 +1329,31 @@ mod response_builder {
 
             match server_packet {
                 Http3Response::Header(headers) => {
-                    if let Some(_status_100) = headers
-                        .headers()
-                        .iter()
-                        .find(|hdr| hdr.name() == b":status" && hdr.value() == b"100")
-                    {
-                        if let Some(progress) = headers
-                            .headers()
-                            .iter()
-                            .find(|hdr| hdr.name() == b"x-progress")
-                        {
-                            if let Ok(len) =
-                                String::from_utf8_lossy(progress.value()).parse::<usize>()
-                            {
-                                if let Err(e) =
-                                    self.progress_channel.0.send(UploadProgressStatus::new(
-                                        self.req_path.as_str(),
-                                        self.request_uuid,
-                                        len,
-                                        0,
-                                        0.0,
-                                    ))
-                                {
-                                    debug!(
-                        "Error: Failed sending progress status for stream_id [{}] -> [{:?}]",
-                        headers.stream_id(),
-                        e
-                         );
-                                }
-                            } else {
-                                error!("Failed parsing hdr value : not a digit");
-                            }
-                        }
+                    let status = StatusCode::from_headers(headers.headers());
+                    if status.map_or(false, |status| status.is_informational()) {
+                        self.on_informational_headers(headers);
+                        return false;
+                    }
+                    if self.headers.is_some() {
+                        // A header block after the final headers is the trailer section : the
+                        // response is completed by the end of the stream that follows it.
+                        debug!("Trailers [{:?}]", headers.headers());
+                        self.trailers = headers.headers().to_vec();
                         return false;
                     }
 
                     debug!("Headers [{:?}]", headers.headers());
                     self.headers = Some(headers.headers().to_vec());
+                    if self.decompress {
+                        self.decoder = response_decoder(headers.headers());
+                    }
+                    // no body to check : the digest describes the representation that
+                    // wasn't sent
+                    let bodyless = self.head_request
+                        || status.map_or(false, |status| matches!(status.as_u16(), 204 | 304));
+                    if !bodyless {
+                        self.digest_check = DigestCheck::for_headers(headers.headers());
+                    }
 
                     let _content_length = if let Some(content_length) = headers
                         .headers()
@@ -962,14 +1370,8 @@ mod response_builder {
                     };
 
                     if headers.is_end() {
-                        if let Err(e) = self.response_channel.0.send(CompletedResponse::new(
-                            self.stream_id,
-                            std::mem::replace(
-                                self.headers.as_mut().unwrap(),
-                                Vec::with_capacity(1),
-                            ),
-                            vec![],
-                        )) {
+                        let completed_response = self.completed_response(vec![]);
+                        if let Err(e) = self.response_channel.0.send(completed_response) {
                             debug!(
                         "Error: Failed sending complete response for stream_id [{}] -> [{:?}]",
                         headers.stream_id(),
@@ -989,6 +1391,9 @@ mod response_builder {
                         }
                         None => {}
                     }
+                    if let StreamSub::EventStream { parser, callback } = &stream_sub {
+                        return handle_event_stream(self, body, parser, callback.as_ref());
+                    }
                     match &self.streamable {
                         Some(_) => {
                             handle_down_stream(self, body, &stream_sub);
@@ -997,28 +1402,9 @@ mod response_builder {
 
                         None => {}
                     }
-                    if let Some(headers) = &self.headers {
-                        let status = String::from_utf8_lossy(
-                            headers
-                                .iter()
-                                .find(|hdr| hdr.name() == b":status")
-                                .unwrap()
-                                .value(),
-                        )
-                        .parse::<usize>()
-                        .unwrap();
-                        let content_len = String::from_utf8_lossy(
-                            headers
-                                .iter()
-                                .find(|hdr| hdr.name() == b"content-length")
-                                .unwrap()
-                                .value(),
-                        )
-                        .parse::<usize>()
-                        .unwrap_or(0);
-
-                        respond_once(self, body, status, content_len, &mut can_delete_in_table);
-                        return false;
+                    if self.headers.is_some() {
+                        respond_once(self, body, &mut can_delete_in_table);
+                        return can_delete_in_table;
                     }
                     if let BodyType::UploadProgressStatusBody(progress_status) =
                         body.body_type(self.req_path.as_str(), self.request_uuid)
@@ -1033,17 +1419,69 @@ mod response_builder {
 
                     //debug!("Error : No headers found for body [{}]", body.stream_id());
                 }
+                Http3Response::Reset {
+                    stream_id,
+                    error_code,
+                    ..
+                } => {
+                    warn!(
+                        "Stream [{}] of request [{}] reset with [{}]",
+                        stream_id, self.req_path, error_code
+                    );
+                    // the event that was being received is incomplete, it must not be merged
+                    // with the first one of a reconnection
+                    if let Some(StreamSub::EventStream { parser, .. }) = &self.streamable {
+                        parser.lock().unwrap().reset_stream();
+                    }
+                    if let Err(e) = self
+                        .response_channel
+                        .0
+                        .send(Err(ResponseError::Disconnected))
+                    {
+                        debug!(
+                            "Error: Failed sending reset of stream_id [{}] -> [{:?}]",
+                            stream_id, e
+                        );
+                    }
+                    can_delete_in_table = true;
+                }
             }
             can_delete_in_table
         }
     }
 
+    ///
+    ///A decoder for the `content-encoding` of the response, if it is one the client supports.
+    ///
+    fn response_decoder(headers: &[h3::Header]) -> Option<ContentDecoder> {
+        let content_encoding = headers
+            .iter()
+            .find(|hdr| hdr.name() == b"content-encoding")?;
+        let Some(encoding) = Encoding::parse(content_encoding.value()) else {
+            warn!(
+                "Unsupported content-encoding [{}], body is left as received",
+                String::from_utf8_lossy(content_encoding.value())
+            );
+            return None;
+        };
+        match ContentDecoder::new(encoding) {
+            Ok(decoder) => Some(decoder),
+            Err(e) => {
+                error!("Failed to create [{}] decoder [{:?}]", encoding.as_str(), e);
+                None
+            }
+        }
+    }
+
     mod partial_response_impl {
-        use std::sync::Arc;
+        use std::sync::{Arc, Mutex};
 
         use log::{debug, error};
 
-        use crate::client_manager::persistant_stream::{StreamControlFlow, StreamEvent, StreamSub};
+        use crate::client_manager::{
+            event_stream::{SseEvent, SseParser},
+            persistant_stream::{StreamControlFlow, StreamEvent, StreamSub},
+        };
 
         use super::{CompletedResponse, Http3ResponseBody, PartialResponse, UploadProgressStatus};
 
@@ -1056,7 +1494,15 @@ mod response_builder {
             //bytes length has been read.
 
             partial_response.stream_message_count += 1;
-            match partial_response.start_new_body_reception(body.packet) {
+            let is_end = body.is_end();
+            let mut completed_coll = partial_response.start_new_body_reception(body.packet);
+            if is_end {
+                // the framer may hold an unterminated last message
+                if let Some(last) = partial_response.framer.finish() {
+                    completed_coll.get_or_insert_with(Vec::new).push(last);
+                }
+            }
+            match completed_coll {
                 Some(completed_coll) => {
                     for completed in completed_coll {
                         let stream_event = StreamEvent::new(
@@ -1077,69 +1523,89 @@ mod response_builder {
             }
         }
 
+        ///
+        ///Hand the events of a `text/event-stream` body to the callback as they are parsed.
+        ///When the stream ends the response is completed (without body), so the caller can
+        ///reconnect. Returns true once the response can be removed from the table.
+        ///
+        pub fn handle_event_stream(
+            partial_response: &mut PartialResponse,
+            body: Http3ResponseBody,
+            parser: &Mutex<SseParser>,
+            callback: &(dyn Fn(SseEvent, StreamControlFlow) + Send + Sync),
+        ) -> bool {
+            let events = parser.lock().unwrap().push(body.packet());
+            for event in events {
+                callback(event, StreamControlFlow);
+            }
+            if !body.is_end() {
+                return false;
+            }
+            if partial_response.headers.is_none() {
+                return true;
+            }
+            // the events have been delivered, the body is not kept
+            partial_response.digest_check = None;
+            let completed_response = partial_response.completed_response(vec![]);
+            if let Err(e) = partial_response.response_channel.0.send(completed_response) {
+                debug!(
+                    "Error: Failed sending end of event stream for stream_id [{}] -> [{:?}]",
+                    body.stream_id(),
+                    e
+                );
+            }
+            true
+        }
+
         pub fn respond_once(
             partial_response: &mut PartialResponse,
             body: Http3ResponseBody,
-            status: usize,
-            content_len: usize,
             can_delete_in_table: &mut bool,
         ) {
-            let percentage_completed = partial_response.data.len() / content_len;
             if body.packet.len() > 0 {
                 partial_response.packet_count += 1;
-                partial_response.data.extend_from_slice(body.packet());
+                partial_response.push_body_data(body.packet());
+                partial_response.hand_over_lines(false);
             }
+            // Progress is measured on the bytes received, content-length is the encoded size.
+            // Without content-length, the body ends with the stream (FIN).
             for sub in &partial_response.event_subscriber {
                 if let Err(e) = sub.on_download_progress(super::DownloadProgressStatus::new(
                     partial_response.req_path.as_str(),
                     partial_response.request_uuid,
-                    partial_response.data.len(),
-                    content_len,
-                    partial_response.data.len() as f32 / content_len as f32,
+                    partial_response.received_len,
+                    partial_response.content_length,
                 )) {
                     error!("Failed to send Upload progress")
                 }
             }
 
             if body.is_end() {
+                partial_response.finish_body_data();
+                partial_response.hand_over_lines(true);
                 if let Some(total_len) = partial_response.content_length {
-                    let percentage_completed =
-                        partial_response.data.len() as f32 / total_len as f32;
-
                     for sub in &partial_response.event_subscriber {
                         if let Err(e) = sub.on_upload_progress(UploadProgressStatus::new(
                             partial_response.req_path.as_str(),
                             partial_response.request_uuid,
-                            partial_response.data.len(),
+                            partial_response.received_len,
                             total_len,
-                            percentage_completed,
+                            super::completion_ratio(partial_response.received_len, total_len),
                         )) {
                             error!("Failed to send Upload progress")
                         }
                     }
                 }
-                if status != 100 {
-                    if let Err(e) =
-                        partial_response
-                            .response_channel
-                            .0
-                            .send(CompletedResponse::new(
-                                partial_response.stream_id,
-                                std::mem::replace(
-                                    partial_response.headers.as_mut().unwrap(),
-                                    Vec::with_capacity(1),
-                                ),
-                                std::mem::replace(&mut partial_response.data, vec![]),
-                            ))
-                    {
-                        debug!(
-                            "Error: Failed sending complete response for stream_id [{}] -> [{:?}]",
-                            body.stream_id(),
-                            e
-                        );
-                    } else {
-                        *can_delete_in_table = true;
-                    }
+                let data = std::mem::take(&mut partial_response.data);
+                let completed_response = partial_response.completed_response(data);
+                if let Err(e) = partial_response.response_channel.0.send(completed_response) {
+                    debug!(
+                        "Error: Failed sending complete response for stream_id [{}] -> [{:?}]",
+                        body.stream_id(),
+                        e
+                    );
+                } else {
+                    *can_delete_in_table = true;
                 }
             }
         }
@@ -1169,8 +1635,18 @@ mod response_manager_worker {
         let partial_table_clone_0 = partial_response_table.clone();
         let partial_table_clone_1 = partial_response_table.clone();
         std::thread::spawn(move || {
-            while let Ok(server_response) = response_queue.pop_response() {
+            while let Ok(response_event) = response_queue.pop_response() {
                 let table_guard = &mut *partial_table_clone_0.lock().unwrap();
+                let server_response = match response_event {
+                    ResponseEvent::Stream(server_response) => server_response,
+                    ResponseEvent::ConnexionClosed { connexion_id } => {
+                        // dropping the partial responses closes their response channels, so
+                        // the waiting sides get an error instead of hanging
+                        debug!("Connexion [{connexion_id}] closed, dropping its pending responses");
+                        table_guard.retain(|(_, conn_id), _| *conn_id != connexion_id);
+                        continue;
+                    }
+                };
                 let (stream_id, conn_id) = server_response.ids();
                 let mut delete_entry = false;
                 if let Some(entry) = table_guard.get_mut(&(stream_id, conn_id.to_owned())) {
@@ -1195,3 +1671,344 @@ mod response_manager_worker {
         });
     }
 }
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use quiche::h3;
+
+    use std::sync::{Arc, Mutex};
+
+    use super::response_builder::PartialResponse;
+    use crate::client_manager::{
+        event_stream::{SseEvent, SseParser},
+        ndjson::LineSink,
+        persistant_stream::StreamSub,
+        response_error::ResponseError,
+    };
+
+    #[test]
+    fn complete_on_fin_without_content_length_after_early_hints() {
+        let stream_ids = (0, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new("/feed", vec![], &stream_ids);
+
+        let early_hints = vec![
+            h3::Header::new(b":status", b"103"),
+            h3::Header::new(b"link", b"</style.css>; rel=preload"),
+        ];
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            0,
+            "conn".to_string(),
+            early_hints.clone(),
+            false
+        )));
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            0,
+            "conn".to_string(),
+            vec![h3::Header::new(b":status", b"200")],
+            false
+        )));
+        for packet in [&b"first "[..], &b"second"[..]] {
+            assert!(!partial_response.extend_data(Http3Response::new_body_data(
+                0,
+                "conn".to_string(),
+                packet,
+                false
+            )));
+        }
+        assert!(partial_response.extend_data(Http3Response::new_body_data(
+            0,
+            "conn".to_string(),
+            &[],
+            true
+        )));
+
+        let response = completed.recv().unwrap().unwrap();
+        assert_eq!(response.as_data(), b"first second");
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(response.informational_headers(), &[early_hints]);
+    }
+
+    #[test]
+    fn trailers_after_body_are_kept_apart() {
+        let stream_ids = (4, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new("/archive", vec![], &stream_ids);
+
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            4,
+            "conn".to_string(),
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"trailer", b"x-checksum"),
+            ],
+            false
+        )));
+        assert!(!partial_response.extend_data(Http3Response::new_body_data(
+            4,
+            "conn".to_string(),
+            b"payload",
+            false
+        )));
+        let trailers = vec![h3::Header::new(b"x-checksum", b"abc123")];
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            4,
+            "conn".to_string(),
+            trailers.clone(),
+            true
+        )));
+        assert!(partial_response.extend_data(Http3Response::new_body_data(
+            4,
+            "conn".to_string(),
+            &[],
+            true
+        )));
+
+        let response = completed.recv().unwrap().unwrap();
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(response.as_data(), b"payload");
+        assert_eq!(response.trailers(), &trailers[..]);
+    }
+
+    #[test]
+    fn corrupted_body_fails_content_digest() {
+        let stream_ids = (8, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new("/backup.tar", vec![], &stream_ids);
+
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            8,
+            "conn".to_string(),
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(
+                    b"content-digest",
+                    b"sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:"
+                ),
+            ],
+            false
+        )));
+        assert!(partial_response.extend_data(Http3Response::new_body_data(
+            8,
+            "conn".to_string(),
+            b"hello w0rld",
+            true
+        )));
+
+        assert!(matches!(
+            completed.recv().unwrap(),
+            Err(ResponseError::DigestMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn bodyless_responses_skip_content_digest() {
+        let digest = h3::Header::new(
+            b"content-digest",
+            b"sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:",
+        );
+        let stream_ids = (16, "conn".to_string());
+        let (mut head_response, completed, _progress) =
+            PartialResponse::new("/backup.tar", vec![], &stream_ids);
+        head_response.set_head_request(true);
+        assert!(head_response.extend_data(Http3Response::new_header(
+            16,
+            "conn".to_string(),
+            vec![h3::Header::new(b":status", b"200"), digest.clone()],
+            true
+        )));
+        assert!(completed.recv().unwrap().is_ok());
+
+        let stream_ids = (20, "conn".to_string());
+        let (mut not_modified, completed, _progress) =
+            PartialResponse::new("/backup.tar", vec![], &stream_ids);
+        assert!(!not_modified.extend_data(Http3Response::new_header(
+            20,
+            "conn".to_string(),
+            vec![h3::Header::new(b":status", b"304"), digest],
+            false
+        )));
+        assert!(not_modified.extend_data(Http3Response::new_body_data(
+            20,
+            "conn".to_string(),
+            b"",
+            true
+        )));
+        assert!(completed.recv().unwrap().is_ok());
+    }
+
+    #[test]
+    fn undecodable_body_fails_response() {
+        let stream_ids = (12, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new("/report", vec![], &stream_ids);
+
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            12,
+            "conn".to_string(),
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"content-encoding", b"gzip"),
+            ],
+            false
+        )));
+        assert!(partial_response.extend_data(Http3Response::new_body_data(
+            12,
+            "conn".to_string(),
+            b"this is not gzip",
+            true
+        )));
+
+        assert!(matches!(
+            completed.recv().unwrap(),
+            Err(ResponseError::Decoding { encoding, .. }) if encoding == "gzip"
+        ));
+    }
+
+    #[test]
+    fn event_stream_completes_at_end_of_stream() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let received = events.clone();
+        let stream_sub = StreamSub::EventStream {
+            parser: Arc::new(Mutex::new(SseParser::new())),
+            callback: Arc::new(move |event: SseEvent, _| received.lock().unwrap().push(event)),
+        };
+        let stream_ids = (12, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new_streamable("/events", vec![], stream_sub, &stream_ids);
+
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            12,
+            "conn".to_string(),
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"content-type", b"text/event-stream"),
+            ],
+            false
+        )));
+        assert!(!partial_response.extend_data(Http3Response::new_body_data(
+            12,
+            "conn".to_string(),
+            b"id: 1\ndata: up",
+            false
+        )));
+        assert_eq!(events.lock().unwrap().len(), 0);
+        assert!(partial_response.extend_data(Http3Response::new_body_data(
+            12,
+            "conn".to_string(),
+            b"\n\n",
+            true
+        )));
+
+        assert_eq!(events.lock().unwrap()[0].data(), "up");
+        assert_eq!(completed.recv().unwrap().unwrap().http_status(), Some(200));
+    }
+
+    #[test]
+    fn reset_event_stream_drops_the_incomplete_event() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let received = events.clone();
+        let parser = Arc::new(Mutex::new(SseParser::new()));
+        let stream_sub = StreamSub::EventStream {
+            parser: parser.clone(),
+            callback: Arc::new(move |event: SseEvent, _| received.lock().unwrap().push(event)),
+        };
+        let stream_ids = (20, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new_streamable("/events", vec![], stream_sub, &stream_ids);
+
+        assert!(!partial_response.extend_data(Http3Response::new_header(
+            20,
+            "conn".to_string(),
+            vec![
+                h3::Header::new(b":status", b"200"),
+                h3::Header::new(b"content-type", b"text/event-stream"),
+            ],
+            false
+        )));
+        assert!(!partial_response.extend_data(Http3Response::new_body_data(
+            20,
+            "conn".to_string(),
+            b"data: cut",
+            false
+        )));
+        assert!(partial_response.extend_data(Http3Response::Reset {
+            stream_id: 20,
+            connexion_id: "conn".to_string(),
+            error_code: 0x10c,
+        }));
+
+        assert!(matches!(
+            completed.recv().unwrap(),
+            Err(ResponseError::Disconnected)
+        ));
+        assert!(parser.lock().unwrap().push(b"\n\n").is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn lines_are_handed_over_as_received() {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let received = lines.clone();
+        let line_sink: LineSink =
+            Arc::new(move |line: &[u8]| received.lock().unwrap().push(line.to_vec()));
+        let stream_ids = (16, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new("/logs/tail", vec![], &stream_ids);
+        partial_response.set_line_sink(Some(line_sink.clone()));
+
+        partial_response.extend_data(Http3Response::new_header(
+            16,
+            "conn".to_string(),
+            vec![h3::Header::new(b":status", b"200")],
+            false,
+        ));
+        partial_response.extend_data(Http3Response::new_body_data(
+            16,
+            "conn".to_string(),
+            b"{\"n\":1}\n{\"n\"",
+            false,
+        ));
+        assert_eq!(*lines.lock().unwrap(), vec![b"{\"n\":1}".to_vec()]);
+        assert!(partial_response.extend_data(Http3Response::new_body_data(
+            16,
+            "conn".to_string(),
+            b":2}",
+            true
+        )));
+        assert_eq!(lines.lock().unwrap()[1], b"{\"n\":2}".to_vec());
+        assert!(completed.recv().unwrap().unwrap().as_data().is_empty());
+
+        // an error status keeps its body in the response
+        let stream_ids = (20, "conn".to_string());
+        let (mut partial_response, completed, _progress) =
+            PartialResponse::new("/logs/tail", vec![], &stream_ids);
+        partial_response.set_line_sink(Some(line_sink));
+        partial_response.extend_data(Http3Response::new_header(
+            20,
+            "conn".to_string(),
+            vec![h3::Header::new(b":status", b"503")],
+            false,
+        ));
+        partial_response.extend_data(Http3Response::new_body_data(
+            20,
+            "conn".to_string(),
+            b"busy\n",
+            true,
+        ));
+        assert_eq!(lines.lock().unwrap().len(), 2);
+        assert_eq!(completed.recv().unwrap().unwrap().as_data(), b"busy\n");
+    }
+
+    #[test]
+    fn download_progress_without_length() {
+        let unknown = DownloadProgressStatus::new("/feed", uuid::Uuid::new_v4(), 12, None);
+        assert_eq!(unknown.progress(), None);
+        assert_eq!(unknown.to_string(), "received [12] bytes");
+
+        let empty = DownloadProgressStatus::new("/empty", uuid::Uuid::new_v4(), 0, Some(0));
+        assert_eq!(empty.progress(), Some(1.0));
+    }
+}
@@ -0,0 +1,367 @@
+pub use retry::RetryPolicy;
+
+mod retry {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime},
+    };
+
+    use log::warn;
+    use quiche::h3::NameValue;
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    use crate::client_manager::{
+        request_manager::{H3Method, Http3RequestBuilder, ReplaySource},
+        response_error::ResponseError,
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    ///
+    ///When and how often a failed request is sent again. A request is retried when its stream
+    ///is reset, its connexion is lost, its body fails the `content-digest` check, or the peer
+    ///answers one of the retryable status codes.
+    ///
+    ///Only the idempotent methods are retried by default. A POST is retried if the request is
+    ///marked as retry safe and its body can be read again (data or file, not a stream). Once
+    ///a line of the response has been handed to the line sink of the request, it is not sent
+    ///again.
+    ///
+    #[derive(Debug, Clone)]
+    pub struct RetryPolicy {
+        max_attempts: u32,
+        methods: Vec<String>,
+        status_codes: Vec<u16>,
+        base_delay: Duration,
+        max_delay: Duration,
+    }
+
+    impl Default for RetryPolicy {
+        fn default() -> Self {
+            Self {
+                max_attempts: 3,
+                methods: vec![
+                    "GET".to_string(),
+                    "HEAD".to_string(),
+                    "PUT".to_string(),
+                    "DELETE".to_string(),
+                ],
+                status_codes: vec![502, 503, 504],
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(10),
+            }
+        }
+    }
+
+    impl RetryPolicy {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        ///
+        ///Total number of sendings, first one included.
+        ///
+        pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+            self.max_attempts = max_attempts.max(1);
+            self
+        }
+        pub fn methods(mut self, methods: &[&str]) -> Self {
+            self.methods = methods.iter().map(|m| m.to_ascii_uppercase()).collect();
+            self
+        }
+        pub fn status_codes(mut self, status_codes: &[u16]) -> Self {
+            self.status_codes = status_codes.to_vec();
+            self
+        }
+        ///
+        ///The delay doubles at each attempt from `base_delay`, up to `max_delay`. A random part
+        ///of it is removed so the clients don't retry all at once.
+        ///
+        pub fn backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+            self.base_delay = base_delay;
+            self.max_delay = max_delay.max(base_delay);
+            self
+        }
+
+        pub fn applies_to(&self, http3_request_builder: &Http3RequestBuilder) -> bool {
+            match http3_request_builder.method() {
+                Some(H3Method::POST { .. }) if http3_request_builder.is_retry_safe() => true,
+                Some(method) => self.methods.iter().any(|m| m == method.as_str()),
+                None => false,
+            }
+        }
+        fn retryable_status(&self, response: &CompletedResponse) -> bool {
+            response
+                .http_status()
+                .map_or(false, |status| self.status_codes.contains(&status))
+        }
+        ///
+        ///Wait before the attempt number `attempt` (the first retry is 1). A `retry-after`
+        ///from the peer is used instead of the backoff, capped to `max_delay`.
+        ///
+        pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+            if let Some(retry_after) = retry_after {
+                return retry_after.min(self.max_delay);
+            }
+            let exponential = self
+                .base_delay
+                .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+                .min(self.max_delay);
+            let mut random = [0u8; 4];
+            let jitter = match SystemRandom::new().fill(&mut random) {
+                Ok(()) => u32::from_be_bytes(random) as f64 / u32::MAX as f64,
+                Err(_) => 0.0,
+            };
+            // equal jitter : between half and the whole delay
+            exponential.mul_f64(0.5 + jitter / 2.0)
+        }
+
+        ///
+        ///Send the request with `dispatch`, and send it again while the policy allows it. An
+        ///`Err` from `dispatch` means the request could not be built and is never retried; the
+        ///retries run in `wait_response`, not while sending.
+        ///
+        pub fn send_through(
+            &self,
+            http3_request_builder: &mut Http3RequestBuilder,
+            dispatch: impl Fn(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()> + Send + 'static,
+        ) -> Result<WaitPeerResponse, ()> {
+            if self.max_attempts < 2 || !self.applies_to(http3_request_builder) {
+                return dispatch(http3_request_builder);
+            }
+            let Some(replay) = http3_request_builder.replay_source() else {
+                return dispatch(http3_request_builder);
+            };
+
+            let delivered = Arc::new(AtomicBool::new(false));
+            watch_line_sink(http3_request_builder, &delivered);
+
+            let policy = self.clone();
+            let peer_response = dispatch(http3_request_builder)?;
+            Ok(peer_response.intercept(move |response| {
+                policy.retry_loop(response, replay, &delivered, &dispatch)
+            }))
+        }
+
+        fn retry_loop(
+            &self,
+            mut response: Result<CompletedResponse, ResponseError>,
+            replay: ReplaySource,
+            delivered: &Arc<AtomicBool>,
+            dispatch: &impl Fn(&mut Http3RequestBuilder) -> Result<WaitPeerResponse, ()>,
+        ) -> Result<CompletedResponse, ResponseError> {
+            let mut attempt = 1;
+            loop {
+                if delivered.load(Ordering::SeqCst) {
+                    // the lines handed over can't be taken back
+                    break;
+                }
+                let retry_after = match &response {
+                    Ok(completed) if self.retryable_status(completed) => retry_after(completed),
+                    Ok(_) => break,
+                    // the same peer key would be presented again
+                    Err(ResponseError::PinningFailed) => break,
+                    Err(_) => None,
+                };
+                if attempt >= self.max_attempts {
+                    break;
+                }
+                let Some(mut next_request) = replay.request() else {
+                    break;
+                };
+                watch_line_sink(&mut next_request, delivered);
+                std::thread::sleep(self.delay(attempt, retry_after));
+                attempt += 1;
+                warn!(
+                    "Retrying [{}] attempt [{}/{}]",
+                    replay.path_with_query(),
+                    attempt,
+                    self.max_attempts
+                );
+                response = match dispatch(&mut next_request) {
+                    Ok(peer_response) => peer_response.wait_response(),
+                    // the request can't be built, the next attempts would fail the same way
+                    Err(()) => return Err(ResponseError::Disconnected),
+                };
+            }
+            response
+        }
+    }
+
+    ///
+    ///Set `delivered` when the line sink of `request` receives its first line.
+    ///
+    fn watch_line_sink(request: &mut Http3RequestBuilder, delivered: &Arc<AtomicBool>) {
+        if let Some(line_sink) = request.take_line_sink() {
+            let delivered = delivered.clone();
+            request.set_line_sink(Arc::new(move |line: &[u8]| {
+                delivered.store(true, Ordering::SeqCst);
+                line_sink(line);
+            }));
+        }
+    }
+
+    ///
+    ///`retry-after` as a number of seconds or as an HTTP date.
+    ///
+    fn retry_after(response: &CompletedResponse) -> Option<Duration> {
+        let headers = response.headers();
+        let value = headers.iter().find(|hdr| hdr.name() == b"retry-after")?;
+        let value = String::from_utf8_lossy(value.value()).trim().to_string();
+        if let Ok(secs) = value.parse::<u64>() {
+            return Some(Duration::from_secs(secs));
+        }
+        let date = httpdate::parse_http_date(&value).ok()?;
+        Some(
+            date.duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use quiche::h3;
+    use uuid::Uuid;
+
+    use crate::client_manager::{
+        request_manager::Http3RequestPrep,
+        response_error::ResponseError,
+        response_manager::{CompletedResponse, WaitPeerResponse},
+    };
+
+    #[test]
+    fn backoff_is_bounded_and_honors_retry_after() {
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(100), Duration::from_secs(1));
+        for attempt in 1..10 {
+            let delay = policy.delay(attempt, None);
+            let full =
+                Duration::from_millis(100 * 2u64.pow(attempt - 1)).min(Duration::from_secs(1));
+            assert!(delay >= full / 2 && delay <= full);
+        }
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(30))),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn retries_503_then_returns_success() {
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let sent = Arc::new(AtomicUsize::new(0));
+        let sent_clone = sent.clone();
+
+        let mut builder =
+            Http3RequestPrep::new(Some("127.0.0.1:3000".parse().unwrap()), Uuid::new_v4());
+        builder.get("/".to_string());
+        let response = policy
+            .send_through(&mut builder, move |_| {
+                let status: &[u8] = if sent_clone.fetch_add(1, Ordering::SeqCst) < 2 {
+                    b"503"
+                } else {
+                    b"200"
+                };
+                Ok(WaitPeerResponse::ready(CompletedResponse::new(
+                    0,
+                    vec![
+                        h3::Header::new(b":status", status),
+                        h3::Header::new(b"retry-after", b"0"),
+                    ],
+                    vec![],
+                )))
+            })
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+
+        let mut stream_post =
+            Http3RequestPrep::new(Some("127.0.0.1:3000".parse().unwrap()), Uuid::new_v4());
+        stream_post
+            .post_stream("/up".to_string(), Box::new(std::io::empty()))
+            .mark_retry_safe();
+        assert!(policy.applies_to(&stream_post));
+        assert!(stream_post.replay_source().is_none());
+    }
+
+    #[test]
+    fn streamed_lines_are_not_replayed() {
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let sent = Arc::new(AtomicUsize::new(0));
+        let sent_clone = sent.clone();
+
+        let mut builder =
+            Http3RequestPrep::new(Some("127.0.0.1:3000".parse().unwrap()), Uuid::new_v4());
+        builder
+            .get("/logs".to_string())
+            .set_line_sink(Arc::new(|_: &[u8]| {}));
+        let response = policy.send_through(&mut builder, move |request| {
+            sent_clone.fetch_add(1, Ordering::SeqCst);
+            // the connexion is lost after the first line
+            if let Some(line_sink) = request.take_line_sink() {
+                line_sink(b"{\"seq\": 1}");
+            }
+            Ok(WaitPeerResponse::failed(ResponseError::Disconnected))
+        });
+        assert!(response.unwrap().wait_response().is_err());
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn build_errors_are_not_retried() {
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let sent = Arc::new(AtomicUsize::new(0));
+        let sent_clone = sent.clone();
+
+        let mut builder =
+            Http3RequestPrep::new(Some("127.0.0.1:3000".parse().unwrap()), Uuid::new_v4());
+        builder.get("/".to_string());
+        let response = policy.send_through(&mut builder, move |_| {
+            sent_clone.fetch_add(1, Ordering::SeqCst);
+            Err(())
+        });
+        assert!(response.is_err());
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+
+        // a lost connexion is retried once the response is waited for
+        let lost = Arc::new(AtomicUsize::new(0));
+        let lost_clone = lost.clone();
+        let mut builder =
+            Http3RequestPrep::new(Some("127.0.0.1:3000".parse().unwrap()), Uuid::new_v4());
+        builder.get("/".to_string());
+        let peer_response = policy
+            .send_through(&mut builder, move |_| {
+                if lost_clone.fetch_add(1, Ordering::SeqCst) < 2 {
+                    return Ok(WaitPeerResponse::failed(ResponseError::Disconnected));
+                }
+                Ok(WaitPeerResponse::ready(CompletedResponse::new(
+                    0,
+                    vec![h3::Header::new(b":status", b"200")],
+                    vec![],
+                )))
+            })
+            .unwrap();
+        assert_eq!(lost.load(Ordering::SeqCst), 1);
+        let response = peer_response.wait_response().unwrap();
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(lost.load(Ordering::SeqCst), 3);
+    }
+}
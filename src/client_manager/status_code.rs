@@ -0,0 +1,208 @@
+pub use http_status::{StatusCode, StatusError};
+
+mod http_status {
+    use std::fmt::Display;
+
+    use quiche::h3::{self, NameValue};
+
+    use crate::client_manager::response_manager::CompletedResponse;
+
+    ///
+    ///HTTP status of a response, in the 100-599 range.
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub struct StatusCode(u16);
+
+    impl StatusCode {
+        pub fn from_u16(code: u16) -> Option<StatusCode> {
+            if (100..600).contains(&code) {
+                Some(StatusCode(code))
+            } else {
+                None
+            }
+        }
+        ///
+        ///Parse the value of a `:status` header.
+        ///
+        pub fn parse(value: &[u8]) -> Option<StatusCode> {
+            Self::from_u16(String::from_utf8_lossy(value).trim().parse::<u16>().ok()?)
+        }
+        ///
+        ///The `:status` of a header section, None if missing or out of range.
+        ///
+        pub fn from_headers(headers: &[h3::Header]) -> Option<StatusCode> {
+            headers
+                .iter()
+                .find(|hdr| hdr.name() == b":status")
+                .and_then(|hdr| Self::parse(hdr.value()))
+        }
+        pub fn as_u16(&self) -> u16 {
+            self.0
+        }
+        pub fn is_informational(&self) -> bool {
+            (100..200).contains(&self.0)
+        }
+        pub fn is_success(&self) -> bool {
+            (200..300).contains(&self.0)
+        }
+        pub fn is_redirect(&self) -> bool {
+            (300..400).contains(&self.0)
+        }
+        pub fn is_client_error(&self) -> bool {
+            (400..500).contains(&self.0)
+        }
+        pub fn is_server_error(&self) -> bool {
+            (500..600).contains(&self.0)
+        }
+        ///
+        ///Reason phrase registered for the code, None for an unassigned code.
+        ///
+        pub fn canonical_reason(&self) -> Option<&'static str> {
+            Some(match self.0 {
+                100 => "Continue",
+                101 => "Switching Protocols",
+                102 => "Processing",
+                103 => "Early Hints",
+                200 => "OK",
+                201 => "Created",
+                202 => "Accepted",
+                203 => "Non-Authoritative Information",
+                204 => "No Content",
+                205 => "Reset Content",
+                206 => "Partial Content",
+                207 => "Multi-Status",
+                208 => "Already Reported",
+                226 => "IM Used",
+                300 => "Multiple Choices",
+                301 => "Moved Permanently",
+                302 => "Found",
+                303 => "See Other",
+                304 => "Not Modified",
+                305 => "Use Proxy",
+                307 => "Temporary Redirect",
+                308 => "Permanent Redirect",
+                400 => "Bad Request",
+                401 => "Unauthorized",
+                402 => "Payment Required",
+                403 => "Forbidden",
+                404 => "Not Found",
+                405 => "Method Not Allowed",
+                406 => "Not Acceptable",
+                407 => "Proxy Authentication Required",
+                408 => "Request Timeout",
+                409 => "Conflict",
+                410 => "Gone",
+                411 => "Length Required",
+                412 => "Precondition Failed",
+                413 => "Content Too Large",
+                414 => "URI Too Long",
+                415 => "Unsupported Media Type",
+                416 => "Range Not Satisfiable",
+                417 => "Expectation Failed",
+                418 => "I'm a teapot",
+                421 => "Misdirected Request",
+                422 => "Unprocessable Content",
+                423 => "Locked",
+                424 => "Failed Dependency",
+                425 => "Too Early",
+                426 => "Upgrade Required",
+                428 => "Precondition Required",
+                429 => "Too Many Requests",
+                431 => "Request Header Fields Too Large",
+                451 => "Unavailable For Legal Reasons",
+                500 => "Internal Server Error",
+                501 => "Not Implemented",
+                502 => "Bad Gateway",
+                503 => "Service Unavailable",
+                504 => "Gateway Timeout",
+                505 => "HTTP Version Not Supported",
+                506 => "Variant Also Negotiates",
+                507 => "Insufficient Storage",
+                508 => "Loop Detected",
+                510 => "Not Extended",
+                511 => "Network Authentication Required",
+                _ => return None,
+            })
+        }
+    }
+
+    impl Display for StatusCode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self.canonical_reason() {
+                Some(reason) => write!(f, "{} {}", self.0, reason),
+                None => write!(f, "{}", self.0),
+            }
+        }
+    }
+
+    ///
+    ///Returned by CompletedResponse::error_for_status() for a 4xx or 5xx response. The
+    ///response is kept to read the error body.
+    ///
+    #[derive(Clone)]
+    pub struct StatusError {
+        status: StatusCode,
+        response: CompletedResponse,
+    }
+
+    impl StatusError {
+        pub fn new(status: StatusCode, response: CompletedResponse) -> Self {
+            Self { status, response }
+        }
+        pub fn status(&self) -> StatusCode {
+            self.status
+        }
+        pub fn response(&self) -> &CompletedResponse {
+            &self.response
+        }
+        pub fn into_response(self) -> CompletedResponse {
+            self.response
+        }
+    }
+
+    impl std::fmt::Debug for StatusError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "StatusError({})", self.status)
+        }
+    }
+
+    impl Display for StatusError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            if self.status.is_client_error() {
+                write!(f, "client error : {}", self.status)
+            } else {
+                write!(f, "server error : {}", self.status)
+            }
+        }
+    }
+
+    impl std::error::Error for StatusError {}
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    #[test]
+    fn status_classes_and_reasons() {
+        let no_content = StatusCode::parse(b"204").unwrap();
+        assert!(no_content.is_success());
+        assert_eq!(no_content.to_string(), "204 No Content");
+
+        assert!(StatusCode::from_u16(308).unwrap().is_redirect());
+        assert!(StatusCode::from_u16(429).unwrap().is_client_error());
+        assert!(StatusCode::from_u16(503).unwrap().is_server_error());
+        assert!(StatusCode::from_u16(103).unwrap().is_informational());
+        assert_eq!(StatusCode::from_u16(599).unwrap().canonical_reason(), None);
+        assert_eq!(StatusCode::from_u16(600), None);
+        assert_eq!(StatusCode::parse(b"abc"), None);
+        assert_eq!(
+            StatusCode::from_headers(&[
+                h3::Header::new(b"server", b"test"),
+                h3::Header::new(b":status", b"404")
+            ]),
+            StatusCode::from_u16(404)
+        );
+        assert_eq!(StatusCode::from_headers(&[]), None);
+    }
+}
@@ -0,0 +1,293 @@
+pub use framers::{
+    DefaultFramer, FixedSizeFramer, NewlineFramer, RawFramer, StreamFramer, VarintFramer,
+};
+
+mod framers {
+    use log::{debug, error};
+    use stream_framer::{FrameParser, ParsedStreamData};
+
+    use crate::client_manager::ndjson::split_lines;
+
+    ///
+    ///Cuts the body packets of a persistent stream into the messages handed to the stream
+    ///callback. Messages can be split across packets : what is not complete yet is kept for
+    ///the next packet.
+    ///
+    pub trait StreamFramer: Send + 'static {
+        ///
+        ///The messages completed by this packet, in order.
+        ///
+        fn push(&mut self, packet: &[u8]) -> Vec<Vec<u8>>;
+        ///
+        ///Called when the stream ends : the last message if the format allows it to be
+        ///unterminated.
+        ///
+        fn finish(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    ///
+    ///The stream_framer format : each message starts with a stream_framer header giving its
+    ///length. Used when the StreamBuilder doesn't select another framer.
+    ///
+    #[derive(Default)]
+    pub struct DefaultFramer {
+        incompleted_stream_data_buffer: Option<(usize, Vec<u8>)>,
+        truncated_header_buffer: Option<Vec<u8>>,
+    }
+
+    impl DefaultFramer {
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl StreamFramer for DefaultFramer {
+        fn push(&mut self, packet: &[u8]) -> Vec<Vec<u8>> {
+            // One new body can contains multiples Header + message
+            let mut output: Vec<Vec<u8>> = vec![];
+
+            match packet.to_vec().parse_frame_header(
+                self.incompleted_stream_data_buffer.take(),
+                self.truncated_header_buffer.take(),
+            ) {
+                Ok(parsing_res) => {
+                    for parsed in parsing_res {
+                        match parsed {
+                            ParsedStreamData::CompletedWithHeader(_msg_size, data) => {
+                                output.push(data);
+                            }
+                            ParsedStreamData::IncompleteWithHeader(msg_size, data) => {
+                                // this has to be the last
+                                // reserve for the next packet
+                                self.incompleted_stream_data_buffer = Some((msg_size, data));
+                            }
+                            ParsedStreamData::IncompleteWithoutHeaderUnFinished(_data) => {
+                                // case when this packet is smaller than the message size
+                            }
+                            ParsedStreamData::TruncatedHeader(truncated_header) => {
+                                // truncated header
+                                if self.truncated_header_buffer.is_none() {
+                                    self.truncated_header_buffer = Some(truncated_header);
+                                }
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("Failed parsing stream_framer header [{:?}]", e);
+                }
+            }
+            output
+        }
+    }
+
+    ///
+    ///Messages prefixed by their length as an unsigned LEB128 varint, as written by
+    ///protobuf's `writeDelimitedTo`.
+    ///
+    pub struct VarintFramer {
+        buffer: Vec<u8>,
+        max_len: usize,
+    }
+
+    impl VarintFramer {
+        ///
+        ///`max_len` bounds the announced message length, a longer one drops the buffered data
+        ///instead of waiting for it.
+        ///
+        pub fn new(max_len: usize) -> Self {
+            Self {
+                buffer: vec![],
+                max_len,
+            }
+        }
+    }
+
+    impl Default for VarintFramer {
+        fn default() -> Self {
+            Self::new(16 * 1024 * 1024)
+        }
+    }
+
+    ///
+    ///The varint at the start of `buffer` and its size in bytes, None while it is incomplete.
+    ///
+    fn read_varint(buffer: &[u8]) -> Option<Result<(u64, usize), ()>> {
+        let mut value = 0u64;
+        for (i, byte) in buffer.iter().enumerate() {
+            if i == 10 {
+                return Some(Err(()));
+            }
+            value |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some(Ok((value, i + 1)));
+            }
+        }
+        None
+    }
+
+    impl StreamFramer for VarintFramer {
+        fn push(&mut self, packet: &[u8]) -> Vec<Vec<u8>> {
+            self.buffer.extend_from_slice(packet);
+            let mut output = vec![];
+            let mut start = 0;
+            while let Some(prefix) = read_varint(&self.buffer[start..]) {
+                let Ok((len, prefix_len)) = prefix else {
+                    error!("Invalid varint length prefix, dropping the stream buffer");
+                    self.buffer.clear();
+                    return output;
+                };
+                if len > self.max_len as u64 {
+                    error!(
+                        "Message of [{}] bytes over the [{}] limit, dropping the stream buffer",
+                        len, self.max_len
+                    );
+                    self.buffer.clear();
+                    return output;
+                }
+                let end = start + prefix_len + len as usize;
+                if end > self.buffer.len() {
+                    break;
+                }
+                output.push(self.buffer[start + prefix_len..end].to_vec());
+                start = end;
+            }
+            self.buffer.drain(..start);
+            output
+        }
+    }
+
+    ///
+    ///One message per line, without its `\n` or `\r\n`. A last unterminated line is handed
+    ///over when the stream ends.
+    ///
+    #[derive(Default)]
+    pub struct NewlineFramer {
+        buffer: Vec<u8>,
+    }
+
+    impl NewlineFramer {
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl StreamFramer for NewlineFramer {
+        fn push(&mut self, packet: &[u8]) -> Vec<Vec<u8>> {
+            self.buffer.extend_from_slice(packet);
+            let mut output = vec![];
+            split_lines(&mut self.buffer, |line| output.push(line.to_vec()), false);
+            output
+        }
+        fn finish(&mut self) -> Option<Vec<u8>> {
+            let mut last_line = None;
+            split_lines(
+                &mut self.buffer,
+                |line| last_line = Some(line.to_vec()),
+                true,
+            );
+            last_line
+        }
+    }
+
+    ///
+    ///Records of `record_len` bytes each.
+    ///
+    pub struct FixedSizeFramer {
+        buffer: Vec<u8>,
+        record_len: usize,
+    }
+
+    impl FixedSizeFramer {
+        pub fn new(record_len: usize) -> Self {
+            Self {
+                buffer: vec![],
+                record_len: record_len.max(1),
+            }
+        }
+    }
+
+    impl StreamFramer for FixedSizeFramer {
+        fn push(&mut self, packet: &[u8]) -> Vec<Vec<u8>> {
+            self.buffer.extend_from_slice(packet);
+            let complete_len = self.buffer.len() - self.buffer.len() % self.record_len;
+            let output = self.buffer[..complete_len]
+                .chunks(self.record_len)
+                .map(|record| record.to_vec())
+                .collect();
+            self.buffer.drain(..complete_len);
+            output
+        }
+        fn finish(&mut self) -> Option<Vec<u8>> {
+            if !self.buffer.is_empty() {
+                debug!(
+                    "Stream ended on an incomplete record of [{}] bytes",
+                    self.buffer.len()
+                );
+                self.buffer.clear();
+            }
+            None
+        }
+    }
+
+    ///
+    ///No framing : every body packet is handed over as received.
+    ///
+    #[derive(Default)]
+    pub struct RawFramer;
+
+    impl StreamFramer for RawFramer {
+        fn push(&mut self, packet: &[u8]) -> Vec<Vec<u8>> {
+            if packet.is_empty() {
+                vec![]
+            } else {
+                vec![packet.to_vec()]
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    fn push_all(framer: &mut dyn StreamFramer, packets: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut messages = vec![];
+        for packet in packets {
+            messages.extend(framer.push(packet));
+        }
+        messages.extend(framer.finish());
+        messages
+    }
+
+    #[test]
+    fn framers_rebuild_split_messages() {
+        let long = vec![7u8; 300];
+        let mut varint_stream = vec![0x03, b'a', b'b', b'c', 0xac, 0x02];
+        varint_stream.extend_from_slice(&long);
+        varint_stream.push(0x00);
+        let (first, second) = varint_stream.split_at(5);
+        assert_eq!(
+            push_all(&mut VarintFramer::default(), &[first, second]),
+            vec![b"abc".to_vec(), long, vec![]]
+        );
+        assert!(VarintFramer::new(10).push(&[0x20]).is_empty());
+
+        assert_eq!(
+            push_all(&mut NewlineFramer::new(), &[b"one\r", b"\ntw", b"o\nthree"]),
+            vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+        );
+
+        assert_eq!(
+            push_all(&mut FixedSizeFramer::new(4), &[b"abcdef", b"gh", b"ij"]),
+            vec![b"abcd".to_vec(), b"efgh".to_vec()]
+        );
+
+        assert_eq!(
+            push_all(&mut RawFramer, &[b"as", b"", b"received"]),
+            vec![b"as".to_vec(), b"received".to_vec()]
+        );
+    }
+}
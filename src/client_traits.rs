@@ -1,4 +1,4 @@
-pub use body_interfaces::{IntoBodyReq, Json};
+pub use body_interfaces::{Form, IntoBodyReq, Json};
 
 mod body_interfaces {
     use serde::{Deserialize, Serialize};
@@ -23,6 +23,47 @@ mod body_interfaces {
         }
     }
 
+    ///
+    ///An `application/x-www-form-urlencoded` body. Fields are percent-encoded in insertion order.
+    ///
+    #[derive(Default)]
+    pub struct Form {
+        fields: Vec<(String, String)>,
+    }
+
+    impl Form {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        pub fn field(mut self, name: &str, value: &str) -> Self {
+            self.fields.push((name.to_string(), value.to_string()));
+            self
+        }
+        ///
+        ///Build a form from any serde type that maps to key/value pairs (struct, map, slice of
+        ///tuples).
+        ///
+        pub fn from_serde<T: Serialize + ?Sized>(
+            value: &T,
+        ) -> Result<Self, serde_urlencoded::ser::Error> {
+            let encoded = serde_urlencoded::to_string(value)?;
+            let fields = serde_urlencoded::from_str::<Vec<(String, String)>>(&encoded)
+                .map_err(|e| serde_urlencoded::ser::Error::Custom(e.to_string().into()))?;
+            Ok(Self { fields })
+        }
+    }
+
+    impl IntoBodyReq for Form {
+        fn content_type(&self) -> ContentType {
+            ContentType::FormUrlEncoded
+        }
+        fn into_bytes(self) -> Vec<u8> {
+            serde_urlencoded::to_string(&self.fields)
+                .unwrap_or_default()
+                .into_bytes()
+        }
+    }
+
     impl<T> IntoBodyReq for Vec<T>
     where
         T: Serialize + Json,
@@ -51,3 +92,38 @@ mod body_interfaces {
         }
     }
 }
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Login {
+        user: String,
+        remember: bool,
+        otp: Option<u32>,
+        attempt: u8,
+    }
+
+    #[test]
+    fn form_fields_keep_serialization_order() {
+        let form = Form::from_serde(&Login {
+            user: "zoé d".to_string(),
+            remember: true,
+            otp: None,
+            attempt: 2,
+        })
+        .unwrap();
+        assert_eq!(
+            form.into_bytes(),
+            b"user=zo%C3%A9+d&remember=true&attempt=2".to_vec()
+        );
+
+        let pairs = Form::from_serde(&[("b", "2"), ("a", "1")]).unwrap();
+        assert_eq!(pairs.into_bytes(), b"b=2&a=1".to_vec());
+
+        assert!(Form::from_serde(&"not a form").is_err());
+    }
+}
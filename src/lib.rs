@@ -4,11 +4,25 @@ mod client_init;
 mod client_manager;
 mod client_traits;
 mod my_log;
+#[cfg(feature = "test-server")]
+mod test_server;
 
-pub use crate::client_config::{ClientConfig, ConnexionInfos};
-pub use crate::client_manager::Http3ClientManager;
-pub use crate::client_manager::ReqStatus;
+pub use crate::client_config::{ClientConfig, ClientIdentity, ConnexionInfos, KeyLog, PemSource};
+pub use crate::client_init::{LinkImpairment, NetworkImpairment};
+pub use crate::client_manager::RequestTrailers;
+pub use crate::client_manager::{Authenticator, BasicAuth, BearerAuth};
 pub use crate::client_manager::{BodyType, ClientRequestManager, ContentType, H3Method};
+pub use crate::client_manager::{CompletedResponse, CookieJar, ResponseCache, RetryPolicy};
+pub use crate::client_manager::{ConnectionStats, STATS_INTERVAL};
+pub use crate::client_manager::{DefaultFramer, FixedSizeFramer, NewlineFramer};
+pub use crate::client_manager::{Encoding, Http3ClientManager, Http3ClientManagerBuilder};
+pub use crate::client_manager::{Http3RequestBuilder, Middleware, NdjsonItems, WaitPeerResponse};
 pub use crate::client_manager::{ProgressTracker, RequestEvent, RequestEventListener};
-pub use crate::client_traits::IntoBodyReq;
+pub use crate::client_manager::{RangedDownload, RedirectPolicy};
+pub use crate::client_manager::{RawFramer, StreamFramer, VarintFramer};
+pub use crate::client_manager::{ReqStatus, ResponseError, SpkiPins, StatusCode, StatusError};
+pub use crate::client_manager::{SseEvent, SseHandle};
 pub use crate::client_traits::Json;
+pub use crate::client_traits::{Form, IntoBodyReq};
+#[cfg(feature = "test-server")]
+pub use crate::test_server::{Route, TestRequest, TestResponse, TestServer};
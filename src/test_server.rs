@@ -0,0 +1,996 @@
+pub use server::{Route, TestRequest, TestResponse, TestServer};
+
+mod server {
+    use std::{
+        collections::{HashMap, VecDeque},
+        net::{SocketAddr, UdpSocket},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread::JoinHandle,
+        time::{Duration, Instant},
+    };
+
+    use log::{debug, error, warn};
+    use quiche::h3::{self, NameValue};
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    const TEST_CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_certs/");
+    const MAX_DATAGRAM_SIZE: usize = 1350;
+    const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+    ///
+    ///A request received by the TestServer, handed to Route::Handler once its body is complete.
+    ///
+    #[derive(Debug, Clone, Default)]
+    pub struct TestRequest {
+        method: String,
+        path: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl TestRequest {
+        pub fn method(&self) -> &str {
+            self.method.as_str()
+        }
+        ///
+        ///The `:path` of the request, query string included.
+        ///
+        pub fn path(&self) -> &str {
+            self.path.as_str()
+        }
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(hdr_name, _)| hdr_name.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+        pub fn headers(&self) -> &[(String, String)] {
+            &self.headers
+        }
+        pub fn body(&self) -> &[u8] {
+            &self.body
+        }
+    }
+
+    ///
+    ///A complete response of the TestServer.
+    ///
+    #[derive(Debug, Clone)]
+    pub struct TestResponse {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl TestResponse {
+        pub fn new(status: u16) -> Self {
+            Self {
+                status,
+                headers: vec![],
+                body: vec![],
+            }
+        }
+        pub fn header(mut self, name: &str, value: &str) -> Self {
+            self.headers.push((name.to_string(), value.to_string()));
+            self
+        }
+        pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+            self.body = body.into();
+            self
+        }
+    }
+
+    ///
+    ///What the TestServer answers on a path.
+    ///
+    #[derive(Clone)]
+    pub enum Route {
+        ///
+        ///`200` with the request body, its method in `x-echo-method` and its path in
+        ///`x-echo-path`.
+        ///
+        Echo,
+        ///
+        ///Always the same response.
+        ///
+        Status(TestResponse),
+        ///
+        ///`200` then one body chunk every `interval`.
+        ///
+        SlowBody {
+            chunks: Vec<Vec<u8>>,
+            interval: Duration,
+        },
+        ///
+        ///A `100` with `x-progress: <received bytes>` each time `every` more bytes of the
+        ///request body are received, then a `200` with the received length as body.
+        ///
+        UploadProgress {
+            every: usize,
+        },
+        ///
+        ///`200` then one message every `interval` on a stream that stays open until the last
+        ///one. The messages are written as is : encode them in the format of the framer
+        ///selected on the StreamBuilder (stream_framer headers by default, see
+        ///Route::framed_downstream()).
+        ///
+        Downstream {
+            frames: Vec<Vec<u8>>,
+            interval: Duration,
+        },
+        Handler(Arc<dyn Fn(&TestRequest) -> TestResponse + Send + Sync + 'static>),
+    }
+
+    impl Route {
+        pub fn status(status: u16) -> Self {
+            Self::Status(TestResponse::new(status))
+        }
+        pub fn handler(
+            handler: impl Fn(&TestRequest) -> TestResponse + Send + Sync + 'static,
+        ) -> Self {
+            Self::Handler(Arc::new(handler))
+        }
+        ///
+        ///Route::Downstream of `messages` each preceded by its stream_framer header, as read
+        ///by a stream opened without framer (DefaultFramer).
+        ///
+        pub fn framed_downstream(messages: &[&[u8]], interval: Duration) -> Self {
+            Self::Downstream {
+                frames: messages
+                    .iter()
+                    .map(|message| stream_framer_frame(message))
+                    .collect(),
+                interval,
+            }
+        }
+    }
+
+    ///
+    ///`message` preceded by its stream_framer header.
+    ///
+    fn stream_framer_frame(message: &[u8]) -> Vec<u8> {
+        use stream_framer::FrameWriter;
+
+        message
+            .to_vec()
+            .create_frame()
+            .expect("failed writing stream_framer header")
+    }
+
+    ///
+    ///Loopback HTTP/3 server for integration tests. Paths without a route get a `404`. The
+    ///server stops when dropped.
+    ///
+    pub struct TestServer {
+        address: SocketAddr,
+        routes: Arc<Mutex<HashMap<String, Route>>>,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl TestServer {
+        ///
+        ///Start a server on 127.0.0.1 with the certificate of `test_certs/`.
+        ///
+        pub fn start() -> Result<Self, ()> {
+            Self::with_certs(
+                &format!("{TEST_CERTS}server.crt"),
+                &format!("{TEST_CERTS}server.key"),
+            )
+        }
+        pub fn with_certs(cert_chain_file: &str, priv_key_file: &str) -> Result<Self, ()> {
+            let mut config = server_config(cert_chain_file, priv_key_file)?;
+            let socket = UdpSocket::bind("127.0.0.1:0").map_err(|e| {
+                error!("Failed to bind the test server socket [{:?}]", e);
+            })?;
+            let _ = socket.set_read_timeout(Some(POLL_INTERVAL));
+            let address = socket.local_addr().map_err(|_| ())?;
+            let routes: Arc<Mutex<HashMap<String, Route>>> = Arc::new(Mutex::new(HashMap::new()));
+            let stop = Arc::new(AtomicBool::new(false));
+
+            let server_routes = routes.clone();
+            let server_stop = stop.clone();
+            let thread = std::thread::spawn(move || {
+                serve(socket, &mut config, server_routes, server_stop);
+            });
+            Ok(Self {
+                address,
+                routes,
+                stop,
+                thread: Some(thread),
+            })
+        }
+        ///
+        ///Answer `path`, without its query string, with `route`. Replaces the previous route of
+        ///`path`.
+        ///
+        pub fn route(&self, path: &str, route: Route) -> &Self {
+            self.routes.lock().unwrap().insert(path.to_string(), route);
+            self
+        }
+        pub fn address(&self) -> SocketAddr {
+            self.address
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    fn server_config(cert_chain_file: &str, priv_key_file: &str) -> Result<quiche::Config, ()> {
+        let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION).map_err(|_| ())?;
+        config
+            .load_cert_chain_from_pem_file(cert_chain_file)
+            .map_err(|e| error!("Failed to load [{}] [{:?}]", cert_chain_file, e))?;
+        config
+            .load_priv_key_from_pem_file(priv_key_file)
+            .map_err(|e| error!("Failed to load [{}] [{:?}]", priv_key_file, e))?;
+        config
+            .set_application_protos(quiche::h3::APPLICATION_PROTOCOL)
+            .map_err(|_| ())?;
+        config.set_max_idle_timeout(20000);
+        config.set_max_recv_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_max_send_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_initial_max_data(100_000_000);
+        config.set_initial_max_stream_data_bidi_local(100_000_000);
+        config.set_initial_max_stream_data_bidi_remote(100_000_000);
+        config.set_initial_max_stream_data_uni(100_000_000);
+        config.set_initial_max_streams_bidi(100);
+        config.set_initial_max_streams_uni(100);
+        config.set_disable_active_migration(true);
+        Ok(config)
+    }
+
+    enum Action {
+        Headers(Vec<h3::Header>, bool),
+        Body(Vec<u8>, bool),
+    }
+
+    ///
+    ///An Action sent on its stream once `at` is reached and the previous ones are sent.
+    ///
+    struct Scheduled {
+        at: Instant,
+        action: Action,
+    }
+
+    #[derive(Default)]
+    struct ServerStream {
+        request: TestRequest,
+        route: Option<Route>,
+        reported: usize,
+        headers_sent: bool,
+        actions: VecDeque<Scheduled>,
+    }
+
+    struct ServerConnexion {
+        conn: quiche::Connection,
+        h3_conn: Option<h3::Connection>,
+        streams: HashMap<u64, ServerStream>,
+    }
+
+    fn serve(
+        socket: UdpSocket,
+        config: &mut quiche::Config,
+        routes: Arc<Mutex<HashMap<String, Route>>>,
+        stop: Arc<AtomicBool>,
+    ) {
+        let local_addr = socket.local_addr().unwrap();
+        let h3_config = h3::Config::new().unwrap();
+        let mut buf = [0; 65535];
+        let mut out = [0; MAX_DATAGRAM_SIZE];
+        // one connexion per client socket : the clients don't migrate
+        let mut connexions: HashMap<SocketAddr, ServerConnexion> = HashMap::new();
+
+        while !stop.load(Ordering::SeqCst) {
+            if let Ok((len, from)) = socket.recv_from(&mut buf) {
+                receive(&mut connexions, config, local_addr, from, &mut buf[..len]);
+            }
+
+            for connexion in connexions.values_mut() {
+                connexion.conn.on_timeout();
+                if connexion.h3_conn.is_none()
+                    && (connexion.conn.is_established() || connexion.conn.is_in_early_data())
+                {
+                    match h3::Connection::with_transport(&mut connexion.conn, &h3_config) {
+                        Ok(h3_conn) => connexion.h3_conn = Some(h3_conn),
+                        Err(e) => error!("Test server failed to start HTTP/3 [{:?}]", e),
+                    }
+                }
+                handle_requests(connexion, &routes, &mut buf);
+                send_actions(connexion);
+                loop {
+                    match connexion.conn.send(&mut out) {
+                        Ok((write, send_info)) => {
+                            if let Err(e) = socket.send_to(&out[..write], send_info.to) {
+                                warn!("Test server send failed [{:?}]", e);
+                                break;
+                            }
+                        }
+                        Err(quiche::Error::Done) => break,
+                        Err(e) => {
+                            error!("Test server send failed [{:?}]", e);
+                            connexion.conn.close(false, 0x1, b"fail").ok();
+                            break;
+                        }
+                    }
+                }
+            }
+            connexions.retain(|_, connexion| !connexion.conn.is_closed());
+        }
+    }
+
+    ///
+    ///Hand a datagram to the connexion of its sender, accepting the connexion on an Initial
+    ///packet.
+    ///
+    fn receive(
+        connexions: &mut HashMap<SocketAddr, ServerConnexion>,
+        config: &mut quiche::Config,
+        local_addr: SocketAddr,
+        from: SocketAddr,
+        packet: &mut [u8],
+    ) {
+        if !connexions.contains_key(&from) {
+            let Ok(hdr) = quiche::Header::from_slice(packet, quiche::MAX_CONN_ID_LEN) else {
+                return;
+            };
+            if hdr.ty != quiche::Type::Initial {
+                return;
+            }
+            let mut scid = [0; quiche::MAX_CONN_ID_LEN];
+            SystemRandom::new().fill(&mut scid).unwrap();
+            let scid = quiche::ConnectionId::from_ref(&scid);
+            match quiche::accept(&scid, None, local_addr, from, config) {
+                Ok(conn) => {
+                    debug!("Test server accepted [{}]", from);
+                    connexions.insert(
+                        from,
+                        ServerConnexion {
+                            conn,
+                            h3_conn: None,
+                            streams: HashMap::new(),
+                        },
+                    );
+                }
+                Err(e) => {
+                    error!("Test server failed to accept [{}] [{:?}]", from, e);
+                    return;
+                }
+            }
+        }
+        let connexion = connexions.get_mut(&from).unwrap();
+        let recv_info = quiche::RecvInfo {
+            from,
+            to: local_addr,
+        };
+        if let Err(e) = connexion.conn.recv(packet, recv_info) {
+            debug!("Test server recv failed [{:?}]", e);
+        }
+    }
+
+    fn handle_requests(
+        connexion: &mut ServerConnexion,
+        routes: &Arc<Mutex<HashMap<String, Route>>>,
+        buf: &mut [u8],
+    ) {
+        let Some(h3_conn) = connexion.h3_conn.as_mut() else {
+            return;
+        };
+        loop {
+            match h3_conn.poll(&mut connexion.conn) {
+                Ok((stream_id, h3::Event::Headers { list, .. })) => {
+                    let stream = connexion.streams.entry(stream_id).or_default();
+                    for hdr in list {
+                        let name = String::from_utf8_lossy(hdr.name()).to_string();
+                        let value = String::from_utf8_lossy(hdr.value()).to_string();
+                        match name.as_str() {
+                            ":method" => stream.request.method = value,
+                            ":path" => stream.request.path = value,
+                            _ => stream.request.headers.push((name, value)),
+                        }
+                    }
+                    let path = stream.request.path.split('?').next().unwrap_or("");
+                    stream.route = routes.lock().unwrap().get(path).cloned();
+                }
+                Ok((stream_id, h3::Event::Data)) => {
+                    let stream = connexion.streams.entry(stream_id).or_default();
+                    while let Ok(read) = h3_conn.recv_body(&mut connexion.conn, stream_id, buf) {
+                        stream.request.body.extend_from_slice(&buf[..read]);
+                    }
+                    if let Some(Route::UploadProgress { every }) = stream.route {
+                        let received = stream.request.body.len();
+                        if received >= stream.reported + every.max(1) {
+                            stream.reported = received;
+                            stream.actions.push_back(Scheduled {
+                                at: Instant::now(),
+                                action: Action::Headers(
+                                    vec![
+                                        h3::Header::new(b":status", b"100"),
+                                        h3::Header::new(
+                                            b"x-progress",
+                                            received.to_string().as_bytes(),
+                                        ),
+                                    ],
+                                    false,
+                                ),
+                            });
+                        }
+                    }
+                }
+                Ok((stream_id, h3::Event::Finished)) => {
+                    let stream = connexion.streams.entry(stream_id).or_default();
+                    respond(stream);
+                }
+                Ok((stream_id, h3::Event::Reset(e))) => {
+                    debug!("Test server stream [{}] reset [{}]", stream_id, e);
+                    connexion.streams.remove(&stream_id);
+                }
+                Ok((_, h3::Event::PriorityUpdate)) => {}
+                Ok((_, h3::Event::GoAway)) => {}
+                Err(h3::Error::Done) => break,
+                Err(e) => {
+                    error!("Test server HTTP/3 error [{:?}]", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn response_headers(status: u16, headers: &[(String, String)]) -> Vec<h3::Header> {
+        let mut response_headers = vec![h3::Header::new(b":status", status.to_string().as_bytes())];
+        response_headers.extend(
+            headers
+                .iter()
+                .map(|(name, value)| h3::Header::new(name.as_bytes(), value.as_bytes())),
+        );
+        response_headers
+    }
+
+    ///
+    ///Schedule the response of a request whose body is complete.
+    ///
+    fn respond(stream: &mut ServerStream) {
+        let now = Instant::now();
+        let (status, headers, chunks, interval) = match stream.route.clone() {
+            Some(Route::Echo) => (
+                200,
+                vec![
+                    ("x-echo-method".to_string(), stream.request.method.clone()),
+                    ("x-echo-path".to_string(), stream.request.path.clone()),
+                    (
+                        "content-length".to_string(),
+                        stream.request.body.len().to_string(),
+                    ),
+                ],
+                vec![stream.request.body.clone()],
+                Duration::ZERO,
+            ),
+            Some(Route::Status(response)) => (
+                response.status,
+                response.headers,
+                vec![response.body],
+                Duration::ZERO,
+            ),
+            Some(Route::Handler(handler)) => {
+                let response = handler(&stream.request);
+                (
+                    response.status,
+                    response.headers,
+                    vec![response.body],
+                    Duration::ZERO,
+                )
+            }
+            Some(Route::SlowBody { chunks, interval }) => (200, vec![], chunks, interval),
+            Some(Route::UploadProgress { .. }) => (
+                200,
+                vec![],
+                vec![stream.request.body.len().to_string().into_bytes()],
+                Duration::ZERO,
+            ),
+            Some(Route::Downstream { frames, interval }) => (200, vec![], frames, interval),
+            None => (404, vec![], vec![], Duration::ZERO),
+        };
+        let chunks: Vec<Vec<u8>> = chunks
+            .into_iter()
+            .filter(|chunk| !chunk.is_empty())
+            .collect();
+
+        stream.actions.push_back(Scheduled {
+            at: now,
+            action: Action::Headers(response_headers(status, &headers), chunks.is_empty()),
+        });
+        let last = chunks.len();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            stream.actions.push_back(Scheduled {
+                at: now + interval * (i as u32 + 1),
+                action: Action::Body(chunk, i + 1 == last),
+            });
+        }
+    }
+
+    ///
+    ///Send the due actions of every stream, in order. A body partially written keeps its
+    ///remaining bytes for the next call.
+    ///
+    fn send_actions(connexion: &mut ServerConnexion) {
+        let Some(h3_conn) = connexion.h3_conn.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        let mut finished = vec![];
+        for (stream_id, stream) in connexion.streams.iter_mut() {
+            while let Some(scheduled) = stream.actions.front_mut() {
+                if scheduled.at > now {
+                    break;
+                }
+                let sent = match &mut scheduled.action {
+                    Action::Headers(headers, fin) => {
+                        let res = if stream.headers_sent {
+                            h3_conn.send_additional_headers(
+                                &mut connexion.conn,
+                                *stream_id,
+                                &headers[..],
+                                false,
+                                *fin,
+                            )
+                        } else {
+                            h3_conn.send_response(
+                                &mut connexion.conn,
+                                *stream_id,
+                                &headers[..],
+                                *fin,
+                            )
+                        };
+                        match res {
+                            Ok(()) => {
+                                stream.headers_sent = true;
+                                if *fin {
+                                    finished.push(*stream_id);
+                                }
+                                true
+                            }
+                            Err(h3::Error::StreamBlocked) | Err(h3::Error::Done) => false,
+                            Err(e) => {
+                                error!("Test server failed sending headers [{:?}]", e);
+                                finished.push(*stream_id);
+                                true
+                            }
+                        }
+                    }
+                    Action::Body(body, fin) => {
+                        match h3_conn.send_body(&mut connexion.conn, *stream_id, &body[..], *fin) {
+                            Ok(written) if written == body.len() => {
+                                if *fin {
+                                    finished.push(*stream_id);
+                                }
+                                true
+                            }
+                            Ok(written) => {
+                                body.drain(..written);
+                                false
+                            }
+                            Err(h3::Error::Done) | Err(h3::Error::StreamBlocked) => false,
+                            Err(e) => {
+                                error!("Test server failed sending body [{:?}]", e);
+                                finished.push(*stream_id);
+                                true
+                            }
+                        }
+                    }
+                };
+                if !sent {
+                    break;
+                }
+                stream.actions.pop_front();
+            }
+        }
+        for stream_id in finished {
+            connexion.streams.remove(&stream_id);
+        }
+    }
+}
+
+mod test {
+    #[allow(warnings)]
+    use super::*;
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        CompletedResponse, Http3ClientManager, Http3RequestBuilder, LinkImpairment, Middleware,
+        NetworkImpairment, NewlineFramer, ProgressTracker, RedirectPolicy, RequestEvent,
+    };
+
+    #[test]
+    fn routes_are_served_on_loopback() {
+        let server = TestServer::start().unwrap();
+        server
+            .route("/echo", Route::Echo)
+            .route(
+                "/teapot",
+                Route::Status(
+                    TestResponse::new(418)
+                        .header("content-type", "text/plain")
+                        .body("short and stout"),
+                ),
+            )
+            .route(
+                "/greet",
+                Route::handler(|req| {
+                    TestResponse::new(200).body(format!(
+                        "hello {}",
+                        req.header("x-name").unwrap_or("nobody")
+                    ))
+                }),
+            );
+        let client = Http3ClientManager::new(&server.address().to_string());
+
+        let response = client
+            .post_data("/echo?id=3", b"ping".to_vec())
+            .send()
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(response.as_data(), b"ping");
+
+        let response = client
+            .get("/teapot")
+            .send()
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(418));
+        assert_eq!(response.as_data(), b"short and stout");
+
+        let response = client
+            .get("/greet")
+            .header("x-name", "tests")
+            .send()
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.as_data(), b"hello tests");
+
+        let response = client
+            .get("/nowhere")
+            .send()
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(404));
+    }
+
+    struct SeenStatuses(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl Middleware for SeenStatuses {
+        fn on_request(&self, builder: &mut Http3RequestBuilder) -> Option<CompletedResponse> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("request {}", builder.get_path().unwrap()));
+            None
+        }
+        fn on_response(&self, response: CompletedResponse) -> CompletedResponse {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("response {}", response.http_status().unwrap()));
+            response
+        }
+    }
+
+    #[test]
+    fn middlewares_wrap_redirections() {
+        let server = TestServer::start().unwrap();
+        server
+            .route(
+                "/old",
+                Route::Status(TestResponse::new(302).header("location", "/new")),
+            )
+            .route("/new", Route::Status(TestResponse::new(200).body("moved")));
+        let client = Http3ClientManager::new(&server.address().to_string());
+        let seen = Arc::new(std::sync::Mutex::new(vec![]));
+        client
+            .set_redirect_policy(RedirectPolicy::default())
+            .add_middleware(SeenStatuses(seen.clone()));
+
+        let response = client.get("/old").send().unwrap().wait_response().unwrap();
+        assert_eq!(response.as_data(), b"moved");
+        assert_eq!(*seen.lock().unwrap(), vec!["request /old", "response 200"]);
+    }
+
+    #[test]
+    fn slow_body_and_upload_progress() {
+        let server = TestServer::start().unwrap();
+        server
+            .route(
+                "/slow",
+                Route::SlowBody {
+                    chunks: vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()],
+                    interval: Duration::from_millis(50),
+                },
+            )
+            .route("/upload", Route::UploadProgress { every: 16 * 1024 });
+        let client = Http3ClientManager::new(&server.address().to_string());
+
+        let start = Instant::now();
+        let response = client.get("/slow").send().unwrap().wait_response().unwrap();
+        assert_eq!(response.as_data(), b"abc");
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        let reports = Arc::new(AtomicUsize::new(0));
+        let counter = reports.clone();
+        let response = client
+            .post_data("/upload", vec![1u8; 256 * 1024])
+            .send()
+            .unwrap()
+            .with_progress_callback(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(response.as_data(), b"262144");
+        assert!(reports.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn downstream_messages_reach_the_stream_callback() {
+        let server = TestServer::start().unwrap();
+        server.route(
+            "/feed",
+            Route::Downstream {
+                frames: vec![b"one\n".to_vec(), b"tw".to_vec(), b"o\nthree\n".to_vec()],
+                interval: Duration::from_millis(20),
+            },
+        );
+        let client = Http3ClientManager::new(&server.address().to_string());
+
+        let (messages, receiver) = crossbeam::channel::unbounded();
+        let mut stream = client.get("/feed").stream();
+        stream.framer(NewlineFramer::new()).open(move |event, _| {
+            let _ = messages.send(event.body_as_slice().to_vec());
+        });
+        let received: Vec<Vec<u8>> = (0..3)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        assert_eq!(
+            received,
+            vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+        );
+    }
+
+    #[test]
+    fn downstream_messages_use_the_default_framer() {
+        let server = TestServer::start().unwrap();
+        server.route(
+            "/feed",
+            Route::framed_downstream(&[b"one", b"two", b"three"], Duration::from_millis(20)),
+        );
+        let client = Http3ClientManager::new(&server.address().to_string());
+
+        let (messages, receiver) = crossbeam::channel::unbounded();
+        let stream = client.get("/feed").stream();
+        stream.open(move |event, _| {
+            let _ = messages.send(event.body_as_slice().to_vec());
+        });
+        let received: Vec<Vec<u8>> = (0..3)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        assert_eq!(
+            received,
+            vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+        );
+    }
+
+    ///
+    ///HEAD and GET of `content`, a `range: bytes=<start>-<end>` answered with a 206 if
+    ///`accept_ranges`.
+    ///
+    fn serve_file(request: &TestRequest, content: &[u8], accept_ranges: bool) -> TestResponse {
+        let mut response =
+            TestResponse::new(200).header("content-length", content.len().to_string().as_str());
+        if accept_ranges {
+            response = response.header("accept-ranges", "bytes");
+        }
+        if request.method() == "HEAD" {
+            return response;
+        }
+        let range = request
+            .header("range")
+            .filter(|_| accept_ranges)
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(start, end)| {
+                Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+            });
+        match range {
+            Some((start, end)) => TestResponse::new(206)
+                .header(
+                    "content-range",
+                    format!("bytes {start}-{end}/{}", content.len()).as_str(),
+                )
+                .body(&content[start..=end]),
+            None => response.body(content),
+        }
+    }
+
+    #[test]
+    fn ranged_downloads_are_reassembled() {
+        let content: Arc<Vec<u8>> = Arc::new((0..3000).map(|i| (i % 251) as u8).collect());
+        let range_requests = Arc::new(AtomicUsize::new(0));
+        let server = TestServer::start().unwrap();
+        let (file, counter) = (content.clone(), range_requests.clone());
+        server.route(
+            "/ranges",
+            Route::handler(move |req| {
+                if req.header("range").is_some() {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+                serve_file(req, &file, true)
+            }),
+        );
+        let file = content.clone();
+        server.route(
+            "/whole",
+            Route::handler(move |req| serve_file(req, &file, false)),
+        );
+        // announces ranges but answers every GET with the whole file
+        let file = content.clone();
+        server.route(
+            "/ignored",
+            Route::handler(move |req| {
+                let mut response = serve_file(req, &file, true);
+                if req.method() != "HEAD" {
+                    response = TestResponse::new(200).body(file.as_slice());
+                }
+                response
+            }),
+        );
+        let file = content.clone();
+        server.route(
+            "/shifted",
+            Route::handler(move |req| {
+                if req.method() == "HEAD" {
+                    return serve_file(req, &file, true);
+                }
+                TestResponse::new(206)
+                    .header("content-range", "bytes 1-1000/3000")
+                    .body(&file[1..=1000])
+            }),
+        );
+        let client = Http3ClientManager::new(&server.address().to_string());
+        let dest = std::env::temp_dir().join(format!("ranged-{}.bin", uuid::Uuid::new_v4()));
+
+        let progress = Arc::new(std::sync::Mutex::new(vec![]));
+        let tracker = ProgressTracker::new();
+        let statuses = progress.clone();
+        tracker.run(move |event| {
+            if let RequestEvent::DownloadProgress(status) = event {
+                statuses
+                    .lock()
+                    .unwrap()
+                    .push((status.received(), status.total()));
+            }
+        });
+        let written = client
+            .download_ranged("/ranges", &dest, 3)
+            .subscribe_event(tracker)
+            .send()
+            .unwrap();
+        assert_eq!(written, 3000);
+        assert_eq!(std::fs::read(&dest).unwrap(), *content);
+        assert_eq!(range_requests.load(Ordering::SeqCst), 3);
+        // the segments are summed : the last status covers the whole file
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let last = loop {
+            let last = progress.lock().unwrap().iter().max().cloned();
+            if last.map_or(false, |(received, _)| received == 3000) || Instant::now() > deadline {
+                break last;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        assert_eq!(last, Some((3000, Some(3000))));
+
+        for path in ["/whole", "/ignored"] {
+            std::fs::remove_file(&dest).ok();
+            let written = client.download_ranged(path, &dest, 3).send().unwrap();
+            assert_eq!(written, 3000);
+            assert_eq!(std::fs::read(&dest).unwrap(), *content);
+        }
+
+        assert!(client.download_ranged("/shifted", &dest, 3).send().is_err());
+        std::fs::remove_file(&dest).ok();
+    }
+
+    #[test]
+    fn qlog_is_written_per_connexion() {
+        let server = TestServer::start().unwrap();
+        server.route("/echo", Route::Echo);
+        let qlog_dir = std::env::temp_dir().join(format!("qlog-{}", uuid::Uuid::new_v4()));
+        let client = Http3ClientManager::builder(&server.address().to_string())
+            .qlog_dir(&qlog_dir)
+            .build();
+
+        // enough packets for the trace to go past the write buffer
+        let response = client
+            .post_data("/echo", vec![7u8; 128 * 1024])
+            .send()
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(200));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (name, len) = loop {
+            let written: Vec<(String, u64)> = std::fs::read_dir(&qlog_dir)
+                .unwrap()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| {
+                    let len = entry.metadata().map_or(0, |metadata| metadata.len());
+                    (entry.file_name().to_string_lossy().to_string(), len)
+                })
+                .collect();
+            assert_eq!(written.len(), 1);
+            if written[0].1 > 0 || Instant::now() > deadline {
+                break written[0].clone();
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        std::fs::remove_dir_all(&qlog_dir).ok();
+
+        let scid = name
+            .strip_prefix("client-")
+            .and_then(|name| name.strip_suffix(".sqlog"))
+            .unwrap();
+        assert_eq!(scid.len(), quiche::MAX_CONN_ID_LEN * 2);
+        assert!(scid.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(len > 0);
+    }
+
+    #[test]
+    fn uploads_survive_an_impaired_link() {
+        let server = TestServer::start().unwrap();
+        server.route("/echo", Route::Echo);
+        let client = Http3ClientManager::builder(&server.address().to_string())
+            .network_impairment(
+                NetworkImpairment::symmetric(
+                    LinkImpairment::new()
+                        .loss(0.05)
+                        .duplication(0.02)
+                        .reordering(0.05, Duration::from_millis(5))
+                        .delay(Duration::from_millis(5))
+                        .bandwidth(2_000_000)
+                        .queue_limit(64 * 1024),
+                )
+                .seed(11),
+            )
+            .build();
+
+        let payload: Vec<u8> = (0..512 * 1024).map(|i| (i % 251) as u8).collect();
+        let response = client
+            .post_data("/echo", payload.clone())
+            .send()
+            .unwrap()
+            .wait_response()
+            .unwrap();
+        assert_eq!(response.http_status(), Some(200));
+        assert_eq!(response.as_data(), payload.as_slice());
+    }
+}